@@ -70,6 +70,25 @@ pub(crate) fn get_vertex_replica() -> &'static u16 {
     })
 }
 
+/// VertexContext is the identity (name and replica) of the vertex, it can be passed explicitly
+/// instead of relying on [get_vertex_name] and [get_vertex_replica], e.g., to test the behavior of
+/// the other replicas.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct VertexContext {
+    pub(crate) name: String,
+    pub(crate) replica: u16,
+}
+
+impl VertexContext {
+    /// returns the context of the current vertex, read from the environment.
+    pub(crate) fn current() -> Self {
+        Self {
+            name: get_vertex_name().to_string(),
+            replica: *get_vertex_replica(),
+        }
+    }
+}
+
 /// Exposes the [Settings] via lazy loading.
 pub fn config() -> &'static Settings {
     static CONF: OnceLock<Settings> = OnceLock::new();
@@ -3,13 +3,18 @@ pub(crate) mod source {
     const DEFAULT_SOURCE_SOCKET: &str = "/var/run/numaflow/source.sock";
     const DEFAULT_SOURCE_SERVER_INFO_FILE: &str = "/var/run/numaflow/sourcer-server-info";
 
+    use std::collections::HashMap;
+    use std::io::Write;
     use std::{fmt::Debug, time::Duration};
 
     use bytes::Bytes;
+    use chrono::{DateTime, Utc};
     use numaflow_models::models::{GeneratorSource, PulsarSource, Source};
     use numaflow_pulsar::source::{PulsarAuth, PulsarSourceConfig};
+    use tokio::time::MissedTickBehavior;
     use tracing::warn;
 
+    use crate::config::VertexContext;
     use crate::error::Error;
     use crate::Result;
 
@@ -116,6 +121,9 @@ pub(crate) mod source {
         }
     }
 
+    /// GeneratorConfig is the config of the generator source. Only the fields up to `jitter` are
+    /// part of the pipeline spec ([GeneratorSource]), the rest are not exposed in the spec and can
+    /// only be set programmatically (e.g., in the tests), they keep their defaults otherwise.
     #[derive(Debug, Clone, PartialEq)]
     pub(crate) struct GeneratorConfig {
         pub rpu: usize,
@@ -125,6 +133,265 @@ pub(crate) mod source {
         pub key_count: u8,
         pub msg_size_bytes: u32,
         pub jitter: Duration,
+        /// explicit set of keys to be used instead of the `key-{n}` keys derived from `key_count`.
+        pub key_spec: Option<KeySpec>,
+        /// how the event-time of the generated messages is assigned.
+        pub event_time: EventTimeMode,
+        /// stop after generating these many messages, making the generator a bounded source.
+        pub max_messages: Option<usize>,
+        /// payloads to cycle through (round-robin) for the generated messages. If set, it takes
+        /// precedence over `content`.
+        pub payloads: Vec<Bytes>,
+        /// type of the offsets assigned to the generated messages.
+        pub offset_type: OffsetType,
+        /// fixed backlog to be reported as pending, so that the generator can be used to exercise
+        /// the autoscaler. `None` means pending is not reported.
+        pub pending: Option<usize>,
+        /// randomize each tick of the generator within `[duration - tick_jitter, duration + tick_jitter]`
+        /// to simulate irregular arrivals. Zero means ticks are regular.
+        pub tick_jitter: Duration,
+        /// static headers to be set on every generated message.
+        pub headers: HashMap<String, String>,
+        /// name of the header carrying the sequence number of the message, if set.
+        pub sequence_header: Option<String>,
+        /// name of the header carrying the replica (of the vertex) which generated the message, so
+        /// that the messages can be attributed to their replica downstream. Not set by default.
+        pub replica_header: Option<String>,
+        /// behavior of the generator when ticks are missed. We skip by default because a burst
+        /// most likely cannot be absorbed, `Burst` is useful for catch-up load tests.
+        pub missed_tick_behavior: MissedTickBehavior,
+        /// vary the size of the generated payloads instead of using the size of the content as is.
+        pub size_distribution: Option<SizeDistribution>,
+        /// pattern repeated to pad the payloads up to the size drawn from `size_distribution`, so
+        /// that binary (e.g., zero bytes) and text paddings can be tested. The payload itself is
+        /// repeated if empty.
+        pub fill_pattern: Vec<u8>,
+        /// partitions reported by the generator, defaults to the vertex replica if not set.
+        pub partitions: Option<Vec<u16>>,
+        /// ramp the rpu from a start to a target rpu, `rpu` is ignored when set.
+        pub ramp: Option<RampConfig>,
+        /// format of the generated payloads, takes precedence over `content` and `payloads`.
+        pub payload_format: PayloadFormat,
+        /// if set, `rpu` is the total rpu of the vertex which is split across these many replicas
+        /// instead of being the rpu of each replica.
+        pub total_replicas: Option<u16>,
+        /// prefix every payload with the sequence number of the message (big-endian u64), so that
+        /// gaps and reordering can be detected downstream.
+        pub sequence_prefix: bool,
+        /// compress each payload, payloads are not compressed by default.
+        pub compression: Option<Compression>,
+        /// emit a fraction of the messages with an event-time in the past to exercise the late
+        /// data handling. No skew by default.
+        pub event_time_skew: Option<EventTimeSkew>,
+        /// number of messages returned by the very first reads (in batches) regardless of the
+        /// rpu, to prime the caches before the steady-state rate kicks in.
+        pub warmup_burst: usize,
+        /// seed for all the randomized aspects of the generator (keys, payloads, jitter, etc.) to
+        /// make the load reproducible, the seeds of the individual options take precedence.
+        /// Random if not set.
+        pub seed: Option<u64>,
+        /// time-to-live of the generated messages, each message carries its expiry in the
+        /// `expires_at` header. Messages do not expire by default.
+        pub ttl: Option<Duration>,
+        /// fraction (0.0 to 1.0) of the messages to be emitted as tombstones, i.e. with an empty
+        /// value, to exercise the compaction and null handling. No tombstones by default.
+        pub tombstone_fraction: f64,
+        /// cap on the total size of the payloads returned by a read, a read returns fewer messages
+        /// than the batch size if another message would exceed it. A single message larger than
+        /// the cap is still returned on its own. No cap by default.
+        pub max_batch_bytes: Option<usize>,
+        /// candidate keys along with the maximum number of messages of each key per time-period,
+        /// to simulate hot keys. A key which has used up its rpu is skipped in favor of the next
+        /// one, and nothing is emitted once all of them are used up. Takes precedence over
+        /// `key_spec` and `key_count`, no per key limits if empty.
+        pub key_rpu: Vec<(String, usize)>,
+        /// emit a heartbeat message (empty value, `heartbeat` header) if no data was generated for
+        /// this long, so that the watermark can progress through the idle periods. No heartbeats
+        /// by default.
+        pub idle_heartbeat: Option<Duration>,
+        /// delay before the first message is generated, measured from the creation of the
+        /// generator, e.g., to let the downstream get ready. No delay by default.
+        pub start_delay: Duration,
+        /// bytes prepended to every payload, e.g., for framing tests. Empty by default.
+        pub payload_prefix: Bytes,
+        /// bytes appended to every payload, e.g., a delimiter. Empty by default.
+        pub payload_suffix: Bytes,
+        /// name and replica of the vertex used for the messages (IDs, offsets and headers) and the
+        /// share of the rpu, the ones of the current vertex are used if not set.
+        pub vertex: Option<VertexContext>,
+        /// track the distinct keys emitted (up to these many), e.g., to verify the coverage of the
+        /// key space. The keys are not tracked by default.
+        pub distinct_keys_cap: Option<usize>,
+    }
+
+    /// EventTimeSkew makes `late_fraction` of the messages late, by moving their event-time back
+    /// by up to `max_lateness`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub(crate) struct EventTimeSkew {
+        /// fraction (0.0 to 1.0) of the messages to be emitted late.
+        pub late_fraction: f64,
+        pub max_lateness: Duration,
+        /// seed for picking the late messages and their lateness, random if not set.
+        pub seed: Option<u64>,
+    }
+
+    /// Compression is the compression applied to the generated payloads.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub(crate) enum Compression {
+        Gzip,
+    }
+
+    impl Compression {
+        /// compresses the data, it is used by both the generator and the ISB writer.
+        pub(crate) fn compress(self, data: &[u8]) -> Vec<u8> {
+            match self {
+                Compression::Gzip => {
+                    let mut encoder =
+                        flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                    // writing to a Vec cannot fail
+                    encoder.write_all(data).expect("failed to gzip the payload");
+                    encoder.finish().expect("failed to gzip the payload")
+                }
+            }
+        }
+    }
+
+    impl GeneratorConfig {
+        /// returns the rpu of the given replica. If `total_replicas` is set, `rpu` is split across
+        /// the replicas and the remainder is spread over the lower replicas, so that the sum of
+        /// the rpu of all the replicas is `rpu`.
+        pub(crate) fn replica_rpu(&self, replica: u16) -> usize {
+            match self.total_replicas {
+                Some(total) if total > 0 => {
+                    let (total, replica) = (total as usize, replica as usize);
+                    self.rpu / total + usize::from(replica < self.rpu % total)
+                }
+                _ => self.rpu,
+            }
+        }
+    }
+
+    /// PayloadFormat is the format of the payloads generated by the generator.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub(crate) enum PayloadFormat {
+        /// the configured content (or payloads) as is.
+        #[default]
+        Raw,
+        /// CSV document with a header row followed by `rows_per_message` rows of random values.
+        Csv {
+            columns: usize,
+            rows_per_message: usize,
+            /// seed for the values, random if not set.
+            seed: Option<u64>,
+        },
+        /// JSON object with the given fields (name and type) populated with random values.
+        Json {
+            fields: Vec<(String, JsonFieldType)>,
+            /// seed for the values, random if not set.
+            seed: Option<u64>,
+        },
+        /// protobuf encoded `GeneratedRecord` with an entry for each of the keys, the values of
+        /// the entries increment across the entries and the messages.
+        Protobuf { keys: Vec<String> },
+    }
+
+    impl PayloadFormat {
+        /// returns the media type of the payloads, the raw payloads have none since the content
+        /// is user provided.
+        pub(crate) fn content_type(&self) -> Option<&'static str> {
+            match self {
+                PayloadFormat::Raw => None,
+                PayloadFormat::Csv { .. } => Some("text/csv"),
+                PayloadFormat::Json { .. } => Some("application/json"),
+                PayloadFormat::Protobuf { .. } => Some("application/x-protobuf"),
+            }
+        }
+    }
+
+    /// JsonFieldType is the type of the value of a field in [PayloadFormat::Json].
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub(crate) enum JsonFieldType {
+        Int,
+        String,
+        Bool,
+        /// RFC3339 timestamp of the generation.
+        Timestamp,
+    }
+
+    /// RampConfig linearly ramps the RPU of the generator from `start_rpu` to `target_rpu` over
+    /// `ramp_duration`, so that load tests can avoid cold-start spikes.
+    #[derive(Debug, Clone, PartialEq)]
+    pub(crate) struct RampConfig {
+        pub start_rpu: usize,
+        pub target_rpu: usize,
+        pub ramp_duration: Duration,
+    }
+
+    impl RampConfig {
+        /// returns the interpolated rpu after `elapsed` time since the start of the ramp.
+        pub(crate) fn rpu_at(&self, elapsed: Duration) -> usize {
+            if elapsed >= self.ramp_duration {
+                return self.target_rpu;
+            }
+            let progress = elapsed.as_secs_f64() / self.ramp_duration.as_secs_f64();
+            let (start, target) = (self.start_rpu as f64, self.target_rpu as f64);
+            (start + (target - start) * progress).round() as usize
+        }
+    }
+
+    /// SizeDistribution is the distribution of the payload sizes of the generated messages. The
+    /// size of each payload is drawn uniformly from `[min_bytes, max_bytes]` and the base content
+    /// is truncated or padded (by repeating it) to that size.
+    #[derive(Debug, Clone, PartialEq)]
+    pub(crate) struct SizeDistribution {
+        pub min_bytes: usize,
+        pub max_bytes: usize,
+        /// seed for the sizes, so that a run can be reproduced. Random if not set.
+        pub seed: Option<u64>,
+    }
+
+    /// OffsetType is the type of the offsets assigned by the generator.
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub(crate) enum OffsetType {
+        /// nanosecond timestamp of the generation as a string.
+        #[default]
+        String,
+        /// monotonically increasing sequence per replica.
+        Int,
+        /// sequence derived from the seed and the partition instead of the timestamp, so that the
+        /// same offsets are generated again on a restart, e.g., to test dedup at the sink.
+        Deterministic { seed: u64 },
+    }
+
+    /// KeySpec is the set of candidate keys for the generated messages along with the strategy
+    /// used to pick a key for each message.
+    #[derive(Debug, Clone, PartialEq)]
+    pub(crate) struct KeySpec {
+        pub keys: Vec<String>,
+        pub selection: KeySelection,
+    }
+
+    /// KeySelection is how the generator picks the key for the next message from [KeySpec].
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub(crate) enum KeySelection {
+        /// cycle through the keys in the order they are specified.
+        RoundRobin,
+        /// pick a key uniformly at random.
+        Random,
+    }
+
+    /// EventTimeMode decides the event-time assigned to each generated message.
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub(crate) enum EventTimeMode {
+        /// wall-clock time of generation, varied by `jitter`.
+        #[default]
+        Now,
+        /// starts at `start` and advances by `step` for every generated message, so that we can
+        /// deterministically control which window a message lands in.
+        Synthetic {
+            start: DateTime<Utc>,
+            step: Duration,
+        },
     }
 
     impl Default for GeneratorConfig {
@@ -137,6 +404,38 @@ pub(crate) mod source {
                 key_count: 0,
                 msg_size_bytes: 8,
                 jitter: Duration::from_secs(0),
+                key_spec: None,
+                event_time: EventTimeMode::Now,
+                max_messages: None,
+                payloads: vec![],
+                offset_type: OffsetType::String,
+                pending: None,
+                tick_jitter: Duration::from_secs(0),
+                headers: HashMap::new(),
+                sequence_header: None,
+                replica_header: None,
+                missed_tick_behavior: MissedTickBehavior::Skip,
+                size_distribution: None,
+                fill_pattern: vec![],
+                partitions: None,
+                ramp: None,
+                payload_format: PayloadFormat::Raw,
+                total_replicas: None,
+                sequence_prefix: false,
+                compression: None,
+                event_time_skew: None,
+                warmup_burst: 0,
+                seed: None,
+                ttl: None,
+                tombstone_fraction: 0.0,
+                max_batch_bytes: None,
+                key_rpu: vec![],
+                idle_heartbeat: None,
+                start_delay: Duration::ZERO,
+                payload_prefix: Bytes::new(),
+                payload_suffix: Bytes::new(),
+                vertex: None,
+                distinct_keys_cap: None,
             }
         }
     }
@@ -422,8 +721,12 @@ mod source_tests {
     use std::time::Duration;
 
     use bytes::Bytes;
+    use numaflow_models::models::GeneratorSource;
 
-    use super::source::{GeneratorConfig, SourceConfig, SourceType, UserDefinedConfig};
+    use super::source::{
+        EventTimeMode, GeneratorConfig, OffsetType, PayloadFormat, RampConfig, SourceConfig,
+        SourceType, UserDefinedConfig,
+    };
 
     #[test]
     fn test_default_generator_config() {
@@ -435,6 +738,82 @@ mod source_tests {
         assert_eq!(default_config.key_count, 0);
         assert_eq!(default_config.msg_size_bytes, 8);
         assert_eq!(default_config.jitter, Duration::from_secs(0));
+        assert_eq!(default_config.key_spec, None);
+        assert_eq!(default_config.event_time, EventTimeMode::Now);
+        assert_eq!(default_config.max_messages, None);
+        assert!(default_config.payloads.is_empty());
+        assert_eq!(default_config.offset_type, OffsetType::String);
+        assert_eq!(default_config.pending, None);
+        assert_eq!(default_config.tick_jitter, Duration::from_secs(0));
+        assert!(default_config.headers.is_empty());
+        assert_eq!(default_config.sequence_header, None);
+        assert_eq!(default_config.replica_header, None);
+        assert_eq!(
+            default_config.missed_tick_behavior,
+            tokio::time::MissedTickBehavior::Skip
+        );
+        assert_eq!(default_config.size_distribution, None);
+        assert!(default_config.fill_pattern.is_empty());
+        assert_eq!(default_config.partitions, None);
+        assert_eq!(default_config.ramp, None);
+        assert_eq!(default_config.payload_format, PayloadFormat::Raw);
+        assert_eq!(default_config.total_replicas, None);
+        assert!(!default_config.sequence_prefix);
+        assert_eq!(default_config.compression, None);
+        assert_eq!(default_config.event_time_skew, None);
+        assert_eq!(default_config.warmup_burst, 0);
+        assert_eq!(default_config.seed, None);
+        assert_eq!(default_config.ttl, None);
+        assert_eq!(default_config.tombstone_fraction, 0.0);
+        assert_eq!(default_config.max_batch_bytes, None);
+        assert!(default_config.key_rpu.is_empty());
+        assert_eq!(default_config.idle_heartbeat, None);
+        assert_eq!(default_config.start_delay, Duration::ZERO);
+        assert!(default_config.payload_prefix.is_empty());
+        assert!(default_config.payload_suffix.is_empty());
+        assert_eq!(default_config.vertex, None);
+        assert_eq!(default_config.distinct_keys_cap, None);
+    }
+
+    #[test]
+    fn test_generator_config_replica_rpu() {
+        let config = GeneratorConfig {
+            rpu: 10,
+            total_replicas: Some(3),
+            ..Default::default()
+        };
+        let split: Vec<usize> = (0..3).map(|replica| config.replica_rpu(replica)).collect();
+        assert_eq!(split, vec![4, 3, 3]);
+        assert_eq!(split.iter().sum::<usize>(), 10);
+
+        // by default the rpu is per replica
+        let config = GeneratorConfig {
+            rpu: 10,
+            ..Default::default()
+        };
+        assert_eq!(config.replica_rpu(0), 10);
+        assert_eq!(config.replica_rpu(2), 10);
+    }
+
+    #[test]
+    fn test_ramp_config_rpu_at() {
+        let ramp = RampConfig {
+            start_rpu: 10,
+            target_rpu: 110,
+            ramp_duration: Duration::from_secs(10),
+        };
+        assert_eq!(ramp.rpu_at(Duration::ZERO), 10);
+        assert_eq!(ramp.rpu_at(Duration::from_secs(5)), 60);
+        assert_eq!(ramp.rpu_at(Duration::from_secs(10)), 110);
+        assert_eq!(ramp.rpu_at(Duration::from_secs(60)), 110);
+
+        // ramping down works the same way
+        let ramp = RampConfig {
+            start_rpu: 100,
+            target_rpu: 0,
+            ramp_duration: Duration::from_secs(4),
+        };
+        assert_eq!(ramp.rpu_at(Duration::from_secs(1)), 75);
     }
 
     #[test]
@@ -461,6 +840,36 @@ mod source_tests {
         }
     }
 
+    #[test]
+    fn test_source_config_from_generator_source() {
+        let generator = GeneratorSource {
+            duration: Some(kube::core::Duration::from(Duration::from_millis(5))),
+            jitter: Some(kube::core::Duration::from(Duration::from_secs(1))),
+            key_count: Some(300),
+            msg_size: Some(16),
+            rpu: Some(100),
+            value: Some(42),
+            value_blob: Some("blob".to_string()),
+        };
+        let SourceType::Generator(config) = SourceType::from(Box::new(generator)) else {
+            panic!("Expected SourceType::Generator");
+        };
+        assert_eq!(
+            config,
+            GeneratorConfig {
+                rpu: 100,
+                content: Bytes::from("blob"),
+                // a duration below the minimum granularity is kept as is
+                duration: Duration::from_millis(5),
+                value: Some(42),
+                key_count: u8::MAX,
+                msg_size_bytes: 16,
+                jitter: Duration::from_secs(1),
+                ..Default::default()
+            }
+        );
+    }
+
     #[test]
     fn test_source_config_user_defined() {
         let user_defined_config = UserDefinedConfig::default();
@@ -12,7 +12,7 @@ use crate::config::components::sink::SinkConfig;
 use crate::config::components::source::SourceConfig;
 use crate::config::components::transformer::{TransformerConfig, TransformerType};
 use crate::config::get_vertex_replica;
-use crate::config::pipeline::isb::{BufferReaderConfig, BufferWriterConfig};
+use crate::config::pipeline::isb::{BufferFullStrategy, BufferReaderConfig, BufferWriterConfig};
 use crate::error::Error;
 use crate::Result;
 
@@ -202,9 +202,12 @@ impl PipelineConfig {
         };
 
         let js_client_config = isb::jetstream::ClientConfig {
-            url: get_var(ENV_NUMAFLOW_SERVING_JETSTREAM_URL)?,
+            urls: isb::jetstream::parse_urls(&get_var(ENV_NUMAFLOW_SERVING_JETSTREAM_URL)?),
             user: get_var(ENV_NUMAFLOW_SERVING_JETSTREAM_USER).ok(),
-            password: get_var(ENV_NUMAFLOW_SERVING_JETSTREAM_PASSWORD).ok(),
+            password: get_var(ENV_NUMAFLOW_SERVING_JETSTREAM_PASSWORD)
+                .ok()
+                .map(isb::jetstream::PasswordSource::Inline),
+            ..Default::default()
         };
 
         let mut from_vertex_config = vec![];
@@ -241,28 +244,34 @@ impl PipelineConfig {
                 .collect();
 
             let default_writer_config = BufferWriterConfig::default();
+            let buffer_full_strategy = match edge.on_full.as_deref() {
+                Some("discardLatest") => BufferFullStrategy::DiscardLatest,
+                _ => default_writer_config.buffer_full_strategy.clone(),
+            };
+            let max_length = vertex_obj
+                .spec
+                .limits
+                .as_ref()
+                .and_then(|l| l.buffer_max_length)
+                .unwrap_or(default_writer_config.max_length as i64)
+                as usize;
+            let usage_limit = vertex_obj
+                .spec
+                .limits
+                .as_ref()
+                .and_then(|l| l.buffer_usage_limit)
+                .unwrap_or(default_writer_config.usage_limit as i64)
+                as f64
+                / 100.0;
             to_vertex_config.push(ToVertexConfig {
                 name: edge.to,
-                writer_config: BufferWriterConfig {
-                    streams,
-                    partitions: partition_count,
-                    max_length: vertex_obj
-                        .spec
-                        .limits
-                        .as_ref()
-                        .and_then(|l| l.buffer_max_length)
-                        .unwrap_or(default_writer_config.max_length as i64)
-                        as usize,
-                    usage_limit: vertex_obj
-                        .spec
-                        .limits
-                        .as_ref()
-                        .and_then(|l| l.buffer_usage_limit)
-                        .unwrap_or(default_writer_config.usage_limit as i64)
-                        as f64
-                        / 100.0,
-                    ..default_writer_config
-                },
+                writer_config: BufferWriterConfig::builder()
+                    .streams(streams)
+                    .partitions(partition_count)
+                    .max_length(max_length)
+                    .usage_limit(usage_limit)
+                    .buffer_full_strategy(buffer_full_strategy)
+                    .build(),
                 partitions: edge.to_vertex_partition_count.unwrap_or_default() as u16,
                 conditions: None,
             });
@@ -350,17 +359,14 @@ mod tests {
             batch_size: 500,
             paf_concurrency: 30000,
             read_timeout: Duration::from_secs(1),
-            js_client_config: isb::jetstream::ClientConfig {
-                url: "localhost:4222".to_string(),
-                user: None,
-                password: None,
-            },
+            js_client_config: isb::jetstream::ClientConfig::new("localhost:4222"),
             from_vertex_config: vec![FromVertexConfig {
                 name: "in".to_string(),
                 reader_config: BufferReaderConfig {
                     partitions: 1,
                     streams: vec![("default-simple-pipeline-out-0".into(), 0)],
                     wip_ack_interval: Duration::from_secs(1),
+                    ..Default::default()
                 },
                 partitions: 0,
             }],
@@ -396,11 +402,7 @@ mod tests {
             batch_size: 1000,
             paf_concurrency: 30000,
             read_timeout: Duration::from_secs(1),
-            js_client_config: isb::jetstream::ClientConfig {
-                url: "localhost:4222".to_string(),
-                user: None,
-                password: None,
-            },
+            js_client_config: isb::jetstream::ClientConfig::new("localhost:4222"),
             from_vertex_config: vec![],
             to_vertex_config: vec![ToVertexConfig {
                 name: "out".to_string(),
@@ -424,6 +426,7 @@ mod tests {
                         key_count: 0,
                         msg_size_bytes: 8,
                         jitter: Duration::from_secs(0),
+                        ..Default::default()
                     }),
                 },
                 transformer_config: None,
@@ -449,11 +452,7 @@ mod tests {
             batch_size: 50,
             paf_concurrency: 30000,
             read_timeout: Duration::from_secs(1),
-            js_client_config: isb::jetstream::ClientConfig {
-                url: "localhost:4222".to_string(),
-                user: None,
-                password: None,
-            },
+            js_client_config: isb::jetstream::ClientConfig::new("localhost:4222"),
             from_vertex_config: vec![],
             to_vertex_config: vec![ToVertexConfig {
                 name: "out".to_string(),
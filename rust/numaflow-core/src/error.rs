@@ -22,6 +22,10 @@ pub enum Error {
     #[error("Connection Error - {0}")]
     Connection(String),
 
+    /// the server rejected the credentials, retrying with the same credentials will not help.
+    #[error("Authentication Failed - {0}")]
+    AuthFailed(String),
+
     #[error("gRPC Error - {0}")]
     Grpc(String),
 
@@ -1,6 +1,7 @@
 use std::cmp::PartialEq;
 use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 
 use async_nats::HeaderValue;
 use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
@@ -23,6 +24,14 @@ use crate::{config, Error};
 
 const DROP: &str = "U+005C__DROP__";
 
+/// Header carrying the expiry (epoch milliseconds) of the message, downstream can skip the message
+/// once it has expired. It is propagated as a NATS header by the ISB writer.
+pub(crate) const EXPIRES_AT_HEADER: &str = "expires_at";
+
+/// Header carrying the media type of the value (e.g., `application/json`), so that downstream can
+/// decode the value without guessing its format.
+pub(crate) const CONTENT_TYPE_HEADER: &str = "content-type";
+
 /// A message that is sent from the source to the sink.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct Message {
@@ -42,13 +51,21 @@ pub(crate) struct Message {
     pub(crate) headers: HashMap<String, String>,
 }
 
-/// Offset of the message which will be used to acknowledge the message.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Offset of the message which will be used to acknowledge the message. Offsets of the same kind
+/// are ordered by their sequence, while across kinds an [Offset::Int] is always lower than an
+/// [Offset::String].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum Offset {
     Int(IntOffset),
     String(StringOffset),
 }
 
+/// Returns the higher of the two offsets, e.g., to track the highest acked offset.
+#[cfg(test)]
+pub(crate) fn max_offset(a: Offset, b: Offset) -> Offset {
+    std::cmp::max(a, b)
+}
+
 impl fmt::Display for Offset {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -58,6 +75,20 @@ impl fmt::Display for Offset {
     }
 }
 
+/// Parses the [fmt::Display] representation of the [Offset]. Since both the kinds are displayed
+/// the same way, an offset whose sequence is an integer is parsed as an [Offset::Int] and as an
+/// [Offset::String] otherwise.
+impl FromStr for Offset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.parse::<IntOffset>() {
+            Ok(offset) => Ok(Offset::Int(offset)),
+            Err(_) => s.parse::<StringOffset>().map(Offset::String),
+        }
+    }
+}
+
 impl TryFrom<async_nats::Message> for Message {
     type Error = Error;
 
@@ -96,14 +127,42 @@ impl TryFrom<async_nats::Message> for Message {
 }
 
 impl Message {
+    /// Creates a message with the given value at the given offset, e.g., to build synthetic
+    /// messages. The ID is derived from the offset, the keys and the headers are left empty.
+    #[cfg(test)]
+    pub(crate) fn from_bytes(value: impl Into<Vec<u8>>, offset: Offset) -> Message {
+        Message {
+            keys: vec![],
+            value: Bytes::from(value.into()),
+            offset: Some(offset.clone()),
+            event_time: Utc::now(),
+            id: MessageID {
+                vertex_name: config::get_vertex_name().to_string(),
+                offset: offset.to_string(),
+                index: 0,
+            },
+            headers: HashMap::new(),
+        }
+    }
+
+    /// Returns true if the message has expired by `now` as per its [EXPIRES_AT_HEADER]. Messages
+    /// without the header (or with a malformed one) never expire.
+    pub(crate) fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.headers
+            .get(EXPIRES_AT_HEADER)
+            .and_then(|expires_at| expires_at.parse::<i64>().ok())
+            .is_some_and(|expires_at| expires_at <= now.timestamp_millis())
+    }
+
     // Check if the message should be dropped.
     pub(crate) fn dropped(&self) -> bool {
         self.keys.len() == 1 && self.keys[0] == DROP
     }
 }
 
-/// IntOffset is integer based offset enum type.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// IntOffset is integer based offset enum type. Offsets are ordered by the sequence first and then
+/// by the partition.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct IntOffset {
     pub(crate) offset: u64,
     pub(crate) partition_idx: u16,
@@ -124,8 +183,38 @@ impl fmt::Display for IntOffset {
     }
 }
 
-/// StringOffset is string based offset enum type.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Parses the [fmt::Display] representation of the [IntOffset].
+impl FromStr for IntOffset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (offset, partition_idx) = split_offset(s)?;
+        let offset = offset
+            .parse()
+            .map_err(|e| Error::Source(format!("Invalid sequence in offset {s}: {e}")))?;
+        Ok(Self::new(offset, partition_idx))
+    }
+}
+
+/// splits the displayed offset `{sequence}-{partition}` into the sequence and the partition.
+fn split_offset(s: &str) -> Result<(&str, u16)> {
+    let (offset, partition_idx) = s
+        .rsplit_once('-')
+        .ok_or_else(|| Error::Source(format!("Invalid offset {s}, partition is missing")))?;
+    if offset.is_empty() {
+        return Err(Error::Source(format!(
+            "Invalid offset {s}, sequence is missing"
+        )));
+    }
+    let partition_idx = partition_idx
+        .parse()
+        .map_err(|e| Error::Source(format!("Invalid partition in offset {s}: {e}")))?;
+    Ok((offset, partition_idx))
+}
+
+/// StringOffset is string based offset enum type. Offsets are ordered lexicographically by the
+/// sequence first and then by the partition.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct StringOffset {
     offset: String,
     partition_idx: u16,
@@ -138,14 +227,35 @@ impl StringOffset {
             partition_idx,
         }
     }
+
+    /// Creates an offset which is unique and sortable per (partition, replica) even if multiple
+    /// offsets are created at the same instant, the sequence is `{timestamp}.{replica}.{counter}`
+    /// where the counter is a monotonic counter of the partition.
+    pub fn sequenced(timestamp_nanos: i64, replica: u16, counter: u64, partition_idx: u16) -> Self {
+        Self {
+            offset: format!("{}.{}.{:020}", timestamp_nanos, replica, counter),
+            partition_idx,
+        }
+    }
 }
 
+/// StringOffset is displayed as `{sequence}-{partition}`.
 impl fmt::Display for StringOffset {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}-{}", self.offset, self.partition_idx)
     }
 }
 
+/// Parses the [fmt::Display] representation of the [StringOffset].
+impl FromStr for StringOffset {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (offset, partition_idx) = split_offset(s)?;
+        Ok(Self::new(offset.to_string(), partition_idx))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub(crate) enum ReadAck {
     /// Message was successfully processed.
@@ -655,4 +765,105 @@ mod tests {
         let result: Result<numaflow_pb::clients::source::Offset> = offset.try_into();
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_int_offset_ordering() {
+        assert!(IntOffset::new(41, 1) < IntOffset::new(42, 1));
+        assert!(IntOffset::new(42, 0) < IntOffset::new(42, 1));
+        assert_eq!(IntOffset::new(42, 1), IntOffset::new(42, 1));
+        assert_eq!(
+            IntOffset::new(100, 0).max(IntOffset::new(9, 0)),
+            IntOffset::new(100, 0)
+        );
+    }
+
+    #[test]
+    fn test_offset_ordering() {
+        let offset = |seq: &str| Offset::String(StringOffset::new(seq.to_string(), 0));
+        assert!(offset("1700000000001") < offset("1700000000002"));
+        assert_eq!(offset("1700000000001"), offset("1700000000001"));
+        assert_eq!(
+            max_offset(offset("1700000000002"), offset("1700000000001")),
+            offset("1700000000002")
+        );
+
+        // across kinds, int offsets are always lower than string offsets
+        let int_offset = Offset::Int(IntOffset::new(u64::MAX, 0));
+        assert!(int_offset < offset("0"));
+        assert_eq!(max_offset(int_offset, offset("0")), offset("0"));
+    }
+
+    #[test]
+    fn test_string_offset_sequenced() {
+        let offset = StringOffset::sequenced(1_700_000_000_000_000_000, 2, 42, 1);
+        assert_eq!(
+            offset.to_string(),
+            "1700000000000000000.2.00000000000000000042-1"
+        );
+        assert_eq!(offset.to_string().parse::<StringOffset>().unwrap(), offset);
+
+        // sortable by the counter within the same instant
+        assert!(offset < StringOffset::sequenced(1_700_000_000_000_000_000, 2, 43, 1));
+
+        assert!("no-partition-x".parse::<StringOffset>().is_err());
+        assert!("1700000000".parse::<StringOffset>().is_err());
+    }
+
+    #[test]
+    fn test_offset_from_str() {
+        let offsets = [
+            Offset::Int(IntOffset::new(42, 1)),
+            Offset::Int(IntOffset::new(0, 0)),
+            Offset::Int(IntOffset::new(u64::MAX, u16::MAX)),
+            Offset::String(StringOffset::new("abc".to_string(), 0)),
+            Offset::String(StringOffset::new(BASE64_STANDARD.encode("42"), 3)),
+            Offset::String(StringOffset::sequenced(1_700_000_000_000_000_000, 2, 42, 1)),
+            // the sequence can have the separator
+            Offset::String(StringOffset::new("a-b-c".to_string(), 2)),
+        ];
+        for offset in offsets {
+            assert_eq!(Offset::from_str(&offset.to_string()).unwrap(), offset);
+        }
+        assert_eq!("42-1".parse::<IntOffset>().unwrap(), IntOffset::new(42, 1));
+
+        for malformed in ["", "42", "-1", "42-", "42-x", "42-70000", "abc-1-"] {
+            assert!(
+                Offset::from_str(malformed).is_err(),
+                "{malformed:?} should not be parsed"
+            );
+        }
+        assert!("abc-1".parse::<IntOffset>().is_err());
+    }
+
+    #[test]
+    fn test_message_from_bytes() {
+        let offset = Offset::Int(IntOffset::new(1, 0));
+        let message = Message::from_bytes("value", offset.clone());
+        assert_eq!(message.value, Bytes::from("value"));
+        assert!(message.keys.is_empty());
+        assert!(message.headers.is_empty());
+        assert_eq!(message.offset, Some(offset.clone()));
+        assert_eq!(message.id.offset, offset.to_string());
+        assert_eq!(message.id.vertex_name, config::get_vertex_name());
+
+        // the ID follows the offset
+        let other = Message::from_bytes(vec![1, 2, 3], Offset::Int(IntOffset::new(2, 0)));
+        assert_eq!(other.value, Bytes::from(vec![1, 2, 3]));
+        assert_ne!(other.id.to_string(), message.id.to_string());
+    }
+
+    #[test]
+    fn test_message_is_expired() {
+        let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let with_expiry = |expires_at: &str| Message {
+            headers: HashMap::from([(EXPIRES_AT_HEADER.to_string(), expires_at.to_string())]),
+            ..Message::from_bytes("value", Offset::Int(IntOffset::new(0, 0)))
+        };
+
+        assert!(with_expiry("1699999999999").is_expired(now));
+        assert!(with_expiry("1700000000000").is_expired(now));
+        assert!(!with_expiry("1700000000001").is_expired(now));
+        assert!(!with_expiry("tomorrow").is_expired(now));
+        assert!(!Message::from_bytes("value", Offset::Int(IntOffset::new(0, 0))).is_expired(now));
+    }
 }
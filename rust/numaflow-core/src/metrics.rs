@@ -28,6 +28,7 @@ use tonic::Request;
 use tracing::{debug, error, info};
 
 use crate::config::{get_pipeline_name, get_vertex_name, get_vertex_replica};
+use crate::reader::LagReader;
 use crate::source::Source;
 use crate::Error;
 
@@ -226,6 +227,7 @@ pub(crate) struct PipelineForwarderMetrics {
     pub(crate) ack_total: Family<Vec<(String, String)>, Counter>,
     pub(crate) ack_time: Family<Vec<(String, String)>, Histogram>,
     pub(crate) write_total: Family<Vec<(String, String)>, Counter>,
+    pub(crate) dropped_total: Family<Vec<(String, String)>, Counter>,
     pub(crate) read_bytes_total: Family<Vec<(String, String)>, Counter>,
     pub(crate) processed_time: Family<Vec<(String, String)>, Histogram>,
     pub(crate) pending: Family<Vec<(String, String)>, Gauge>,
@@ -395,6 +397,7 @@ impl PipelineMetrics {
                 }),
                 pending: Family::<Vec<(String, String)>, Gauge>::default(),
                 write_total: Family::<Vec<(String, String)>, Counter>::default(),
+                dropped_total: Family::<Vec<(String, String)>, Counter>::default(),
             },
             isb: PipelineISBMetrics {
                 paf_resolution_time:
@@ -442,6 +445,11 @@ impl PipelineMetrics {
             "Number of pending messages",
             metrics.forwarder.pending.clone(),
         );
+        forwarder_registry.register(
+            DROPPED_TOTAL,
+            "Total number of messages dropped",
+            metrics.forwarder.dropped_total.clone(),
+        );
         metrics
     }
 }
@@ -682,9 +690,10 @@ struct TimestampedPending {
 
 /// PendingReader is responsible for periodically checking the lag of the reader
 /// and exposing the metrics. It maintains a list of pending stats and ensures that
-/// only the most recent entries are kept.
-pub(crate) struct PendingReader {
-    lag_reader: Source,
+/// only the most recent entries are kept. The lag reader is the [Source] of a monovertex, or the
+/// ISB buffer being read by a pipeline vertex.
+pub(crate) struct PendingReader<L = Source> {
+    lag_reader: L,
     lag_checking_interval: Duration,
     refresh_interval: Duration,
     pending_stats: Arc<Mutex<Vec<TimestampedPending>>>,
@@ -696,14 +705,14 @@ pub(crate) struct PendingReaderTasks {
 }
 
 /// PendingReaderBuilder is used to build a [LagReader] instance.
-pub(crate) struct PendingReaderBuilder {
-    lag_reader: Source,
+pub(crate) struct PendingReaderBuilder<L = Source> {
+    lag_reader: L,
     lag_checking_interval: Option<Duration>,
     refresh_interval: Option<Duration>,
 }
 
-impl PendingReaderBuilder {
-    pub(crate) fn new(lag_reader: Source) -> Self {
+impl<L> PendingReaderBuilder<L> {
+    pub(crate) fn new(lag_reader: L) -> Self {
         Self {
             lag_reader,
             lag_checking_interval: None,
@@ -721,7 +730,7 @@ impl PendingReaderBuilder {
         self
     }
 
-    pub(crate) fn build(self) -> PendingReader {
+    pub(crate) fn build(self) -> PendingReader<L> {
         PendingReader {
             lag_reader: self.lag_reader,
             lag_checking_interval: self
@@ -735,7 +744,7 @@ impl PendingReaderBuilder {
     }
 }
 
-impl PendingReader {
+impl<L: LagReader + Clone + Send + 'static> PendingReader<L> {
     /// Starts the lag reader by spawning tasks to build up pending info and expose pending metrics.
     ///
     /// This method spawns two asynchronous tasks:
@@ -773,16 +782,16 @@ impl Drop for PendingReaderTasks {
     }
 }
 
-/// Periodically checks the pending messages from the source client and build the pending stats.
-async fn build_pending_info(
-    source: Source,
+/// Periodically checks the pending messages from the lag reader and build the pending stats.
+async fn build_pending_info<L: LagReader>(
+    mut lag_reader: L,
     lag_checking_interval: Duration,
     pending_stats: Arc<Mutex<Vec<TimestampedPending>>>,
 ) {
     let mut ticker = time::interval(lag_checking_interval);
     loop {
         ticker.tick().await;
-        match fetch_pending(&source).await {
+        match fetch_pending(&mut lag_reader).await {
             Ok(pending) => {
                 if pending != -1 {
                     let mut stats = pending_stats.lock().await;
@@ -804,7 +813,7 @@ async fn build_pending_info(
     }
 }
 
-async fn fetch_pending(lag_reader: &Source) -> crate::error::Result<i64> {
+async fn fetch_pending<L: LagReader>(lag_reader: &mut L) -> crate::error::Result<i64> {
     let response: i64 = lag_reader.pending().await?.map_or(-1, |p| p as i64); // default to -1(unavailable)
     Ok(response)
 }
@@ -27,6 +27,7 @@ pub(crate) async fn start_forwarder(
         config.batch_size,
         config.read_timeout,
         &config.source_config,
+        None,
         cln_token.clone(),
     )
     .await?;
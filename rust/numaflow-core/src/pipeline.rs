@@ -1,20 +1,29 @@
+use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 
 use async_nats::jetstream::Context;
-use async_nats::{jetstream, ConnectOptions};
+use async_nats::{jetstream, Client, ConnectError, ConnectErrorKind, ConnectOptions};
 use futures::future::try_join_all;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
 use tokio_util::sync::CancellationToken;
-use tracing::info;
+use tracing::{info, warn};
 
+use crate::config::is_mono_vertex;
 use crate::config::pipeline;
+use crate::config::pipeline::isb::jetstream::ClientAuth;
+use crate::config::pipeline::isb::BufferReaderConfig;
 use crate::config::pipeline::{PipelineConfig, SinkVtxConfig, SourceVtxConfig};
 use crate::metrics::{PipelineContainerState, UserDefinedContainerState};
 use crate::pipeline::forwarder::source_forwarder;
-use crate::pipeline::isb::jetstream::reader::JetstreamReader;
-use crate::pipeline::isb::jetstream::ISBWriter;
+use crate::pipeline::isb::jetstream::reader::{JetstreamLagReader, JetstreamReader};
+use crate::pipeline::isb::jetstream::{create_streams, health_check, ISBHealth, ISBWriter};
 use crate::shared::create_components;
 use crate::shared::create_components::create_sink_writer;
-use crate::shared::metrics::start_metrics_server;
+use crate::shared::metrics::{create_pending_reader, start_metrics_server};
 use crate::{error, Result};
 
 mod forwarder;
@@ -43,14 +52,17 @@ async fn start_source_forwarder(
     config: PipelineConfig,
     source_config: SourceVtxConfig,
 ) -> Result<()> {
-    let js_context = create_js_context(config.js_client_config.clone()).await?;
+    let js_client = create_js_client(config.js_client_config.clone()).await?;
+    let js_context = jetstream::new(js_client.clone());
 
-    let buffer_writer = create_buffer_writer(&config, js_context.clone(), cln_token.clone()).await;
+    let buffer_writer =
+        create_buffer_writer(&config, js_context.clone(), cln_token.clone()).await?;
 
     let (source, source_grpc_client) = create_components::create_source(
         config.batch_size,
         config.read_timeout,
         &source_config.source_config,
+        Some(buffer_writer.pressure()),
         cln_token.clone(),
     )
     .await?;
@@ -80,6 +92,7 @@ async fn start_source_forwarder(
     };
 
     forwarder.start().await?;
+    close_js_client(js_client, config.js_client_config.drain_timeout).await;
     Ok(())
 }
 
@@ -88,11 +101,20 @@ async fn start_sink_forwarder(
     config: PipelineConfig,
     sink: SinkVtxConfig,
 ) -> Result<()> {
-    let js_context = create_js_context(config.js_client_config.clone()).await?;
+    let js_client = create_js_client(config.js_client_config.clone()).await?;
+    let js_context = jetstream::new(js_client.clone());
 
     // Create buffer readers for each partition
     let buffer_readers = create_buffer_readers(&config, js_context.clone()).await?;
 
+    // the pending messages of the buffer are summed across all of its partitions
+    let pending_reader = create_pending_reader(
+        &config.metrics_config,
+        JetstreamLagReader::new(js_context.clone(), reader_config(&config)?),
+    )
+    .await;
+    let _pending_reader_handle = pending_reader.start(is_mono_vertex()).await;
+
     // Create sink writers and clients
     let mut sink_writers = Vec::new();
     for _ in &buffer_readers {
@@ -142,6 +164,7 @@ async fn start_sink_forwarder(
         .await
         .map_err(|e| error::Error::Forwarder(e.to_string()))?;
     info!("All forwarders have stopped successfully");
+    close_js_client(js_client, config.js_client_config.drain_timeout).await;
     Ok(())
 }
 
@@ -149,31 +172,43 @@ async fn create_buffer_writer(
     config: &PipelineConfig,
     js_context: Context,
     cln_token: CancellationToken,
-) -> ISBWriter {
-    ISBWriter::new(
-        config.paf_concurrency,
-        config
-            .to_vertex_config
-            .iter()
-            .map(|tv| tv.writer_config.clone())
-            .collect(),
-        js_context,
-        cln_token,
+) -> Result<ISBWriter> {
+    let writer_config: Vec<_> = config
+        .to_vertex_config
+        .iter()
+        .map(|tv| tv.writer_config.clone())
+        .collect();
+    create_streams(&js_context, &writer_config).await?;
+
+    // the vertex should not start while its buffers are missing, being disconnected is not fatal
+    // since the client keeps reconnecting in the background.
+    let health = health_check(
+        &js_context.client(),
+        &writer_config,
+        config.js_client_config.connect_timeout,
     )
-    .await
+    .await;
+    if let ISBHealth::MissingStreams(streams) = &health {
+        return Err(error::Error::ISB(format!(
+            "Streams of the buffers are missing: {streams:?}"
+        )));
+    }
+    if !health.is_healthy() {
+        warn!(
+            ?health,
+            "ISB is not healthy, the writes are retried till it is"
+        );
+    }
+
+    Ok(ISBWriter::new(config.paf_concurrency, writer_config, js_context, cln_token).await)
 }
 
 async fn create_buffer_readers(
     config: &PipelineConfig,
     js_context: Context,
 ) -> Result<Vec<JetstreamReader>> {
-    // Only the reader config of the first "from" vertex is needed, as all "from" vertices currently write
-    // to a common buffer, in the case of a join.
-    let reader_config = &config
-        .from_vertex_config
-        .first()
-        .ok_or_else(|| error::Error::Config("No from vertex config found".to_string()))?
-        .reader_config;
+    let reader_config = reader_config(config)?;
+    reader_config.validate()?;
 
     let mut readers = Vec::new();
     for stream in &reader_config.streams {
@@ -190,25 +225,212 @@ async fn create_buffer_readers(
     Ok(readers)
 }
 
-/// Creates a jetstream context based on the provided configuration
-async fn create_js_context(config: pipeline::isb::jetstream::ClientConfig) -> Result<Context> {
-    // TODO: make these configurable. today this is hardcoded on Golang code too.
+/// returns the config of the buffer being read. Only the reader config of the first "from" vertex
+/// is needed, as all "from" vertices currently write to a common buffer, in the case of a join.
+fn reader_config(config: &PipelineConfig) -> Result<&BufferReaderConfig> {
+    config
+        .from_vertex_config
+        .first()
+        .map(|from_vertex| &from_vertex.reader_config)
+        .ok_or_else(|| error::Error::Config("No from vertex config found".to_string()))
+}
+
+/// Creates a jetstream client based on the provided configuration. The first attempt to connect is
+/// made right away (bounded by the connect timeout), so that rejected credentials fail fast with
+/// [error::Error::AuthFailed] instead of being retried forever. If the server is not reachable, the
+/// client keeps connecting in the background.
+async fn create_js_client(config: pipeline::isb::jetstream::ClientConfig) -> Result<Client> {
+    // the options are consumed by each attempt to connect, the auth is loaded only once for both
+    let auth = load_auth(&config).await?;
+    let opts = connect_options(&config, auth.clone())?;
+    match tokio::time::timeout(
+        config.connect_timeout,
+        async_nats::connect_with_options(config.urls.as_slice(), opts),
+    )
+    .await
+    {
+        Ok(Ok(client)) => return Ok(client),
+        Ok(Err(e)) if is_auth_error(&e) => return Err(error::Error::AuthFailed(e.to_string())),
+        Ok(Err(e)) => warn!(
+            ?e,
+            "Failed to connect to jetstream, connecting in the background"
+        ),
+        Err(_) => warn!(
+            timeout = ?config.connect_timeout,
+            "Timed out connecting to jetstream, connecting in the background"
+        ),
+    }
+
+    let opts = connect_options(&config, auth)?.retry_on_initial_connect();
+    // all the urls are passed so that the client can fail over to any of the servers
+    async_nats::connect_with_options(config.urls.as_slice(), opts)
+        .await
+        .map_err(|e| error::Error::Connection(e.to_string()))
+}
+
+/// Auth is the authentication of the client as loaded from its [ClientAuth], i.e., with the
+/// contents of the credentials file instead of its path.
+#[derive(Clone)]
+enum Auth {
+    Credentials(String),
+    UserPassword { user: String, password: String },
+}
+
+/// Loads the authentication of the client, if any. The credentials file is read here, so that it
+/// is read only once however many times the client tries to connect.
+async fn load_auth(config: &pipeline::isb::jetstream::ClientConfig) -> Result<Option<Auth>> {
+    match config.auth()? {
+        Some(ClientAuth::CredentialsFile(path)) => {
+            let credentials = tokio::fs::read_to_string(&path).await.map_err(|e| {
+                error::Error::Connection(format!("Failed to load credentials file {path}: {e}"))
+            })?;
+            Ok(Some(Auth::Credentials(credentials)))
+        }
+        Some(ClientAuth::UserPassword { user, password }) => {
+            Ok(Some(Auth::UserPassword { user, password }))
+        }
+        None => Ok(None),
+    }
+}
+
+/// returns true if the server rejected the credentials of the client.
+fn is_auth_error(e: &ConnectError) -> bool {
+    matches!(
+        e.kind(),
+        ConnectErrorKind::Authentication | ConnectErrorKind::AuthorizationViolation
+    )
+}
+
+/// Returns the options to connect to the jetstream server with.
+fn connect_options(
+    config: &pipeline::isb::jetstream::ClientConfig,
+    auth: Option<Auth>,
+) -> Result<ConnectOptions> {
+    // TODO: make ping interval configurable. today this is hardcoded on Golang code too.
     let mut opts = ConnectOptions::new()
-        .max_reconnects(None) // -1 for unlimited reconnects
-        .ping_interval(Duration::from_secs(3))
-        .max_reconnects(None)
-        .ping_interval(Duration::from_secs(3))
-        .retry_on_initial_connect();
-
-    if let (Some(user), Some(password)) = (config.user, config.password) {
-        opts = opts.user_and_password(user, password);
+        .max_reconnects(config.max_reconnects) // None for unlimited reconnects
+        .connection_timeout(config.connect_timeout)
+        .ping_interval(Duration::from_secs(3));
+
+    match auth {
+        Some(Auth::Credentials(credentials)) => {
+            opts = opts
+                .credentials(&credentials)
+                .map_err(|e| error::Error::Connection(format!("Invalid credentials file: {e}")))?;
+        }
+        Some(Auth::UserPassword { user, password }) => {
+            opts = opts.user_and_password(user, password);
+        }
+        None => {}
+    }
+
+    if config.tls_insecure_skip_verify {
+        if config.tls_ca_cert_path.is_some() {
+            warn!("Both TLS CA certificate and insecure skip verify are set, skipping server certificate verification");
+        }
+        opts = opts
+            .require_tls(true)
+            .tls_client_config(insecure_tls_client_config()?);
+    } else {
+        if let Some(ca_cert_path) = &config.tls_ca_cert_path {
+            opts = opts
+                .require_tls(true)
+                .add_root_certificates(PathBuf::from(ca_cert_path));
+        }
+        if let (Some(cert_path), Some(key_path)) =
+            (&config.tls_client_cert_path, &config.tls_client_key_path)
+        {
+            opts = opts
+                .require_tls(true)
+                .add_client_certificate(PathBuf::from(cert_path), PathBuf::from(key_path));
+        }
     }
+    Ok(opts)
+}
 
-    let js_client = async_nats::connect_with_options(&config.url, opts)
+/// Closes the jetstream client gracefully, the pending publishes are flushed before the connection
+/// is closed. We wait for at most the drain timeout for the drain to complete, the connection is
+/// left to be closed when the process exits if it does not.
+async fn close_js_client(js_client: Client, drain_timeout: Duration) {
+    match tokio::time::timeout(drain_timeout, drain_js_client(&js_client)).await {
+        Ok(Ok(())) => info!("Closed the jetstream connection"),
+        Ok(Err(e)) => warn!(?e, "Failed to close the jetstream connection gracefully"),
+        Err(_) => warn!(
+            ?drain_timeout,
+            "Timed out draining the jetstream connection"
+        ),
+    }
+}
+
+/// Drains the connection of the client, the pending publishes are flushed and the connection is
+/// closed. The connection is shared by all the clones of the client (the jetstream context, the
+/// writers and the readers), so they are all closed too.
+async fn drain_js_client(js_client: &Client) -> Result<()> {
+    js_client
+        .drain()
         .await
-        .map_err(|e| error::Error::Connection(e.to_string()))?;
+        .map_err(|e| error::Error::Connection(format!("Failed to drain: {e}")))
+}
 
-    Ok(jetstream::new(js_client))
+/// Creates a TLS client config which does not verify the server certificate.
+fn insecure_tls_client_config() -> Result<rustls::ClientConfig> {
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let tls_config = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| error::Error::Config(format!("Invalid TLS config: {e:?}")))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(SkipServerVerification(provider)))
+        .with_no_client_auth();
+    Ok(tls_config)
+}
+
+/// Accepts any server certificate, the signatures are still verified so that the handshake is sane.
+#[derive(Debug)]
+struct SkipServerVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> std::result::Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
 }
 
 #[cfg(test)]
@@ -288,11 +510,7 @@ mod tests {
             batch_size: 1000,
             paf_concurrency: 30000,
             read_timeout: Duration::from_secs(1),
-            js_client_config: isb::jetstream::ClientConfig {
-                url: "localhost:4222".to_string(),
-                user: None,
-                password: None,
-            },
+            js_client_config: isb::jetstream::ClientConfig::new("localhost:4222"),
             from_vertex_config: vec![],
             to_vertex_config: vec![ToVertexConfig {
                 name: "out".to_string(),
@@ -308,6 +526,7 @@ mod tests {
                     usage_limit: 0.8,
                     buffer_full_strategy: RetryUntilSuccess,
                     retry_interval: Duration::from_millis(10),
+                    ..Default::default()
                 },
                 partitions: 5,
                 conditions: None,
@@ -322,6 +541,7 @@ mod tests {
                         key_count: 0,
                         msg_size_bytes: 300,
                         jitter: Duration::from_millis(0),
+                        ..Default::default()
                     }),
                 },
                 transformer_config: None,
@@ -456,11 +676,7 @@ mod tests {
             batch_size: 1000,
             paf_concurrency: 30000,
             read_timeout: Duration::from_secs(1),
-            js_client_config: isb::jetstream::ClientConfig {
-                url: "localhost:4222".to_string(),
-                user: None,
-                password: None,
-            },
+            js_client_config: isb::jetstream::ClientConfig::new("localhost:4222"),
             to_vertex_config: vec![],
             from_vertex_config: vec![FromVertexConfig {
                 name: "in".to_string(),
@@ -472,6 +688,7 @@ mod tests {
                         .map(|(i, key)| (*key, i as u16))
                         .collect(),
                     wip_ack_interval: Duration::from_secs(1),
+                    ..Default::default()
                 },
                 partitions: 0,
             }],
@@ -527,4 +744,108 @@ mod tests {
             context.delete_stream(stream_name).await.unwrap();
         }
     }
+
+    #[test]
+    fn test_insecure_tls_client_config() {
+        let tls_config = insecure_tls_client_config();
+        assert!(tls_config.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_close_js_client_timeout() {
+        // nothing is listening, so the drain never completes
+        let config = isb::jetstream::ClientConfig::new("localhost:1");
+        let js_client = create_js_client(config).await.unwrap();
+
+        let drain_timeout = Duration::from_millis(100);
+        let start = tokio::time::Instant::now();
+        close_js_client(js_client, drain_timeout).await;
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_create_js_client_unresponsive_server() {
+        // a server which accepts the connections but never sends the INFO
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut sockets = vec![];
+            while let Ok((socket, _)) = listener.accept().await {
+                sockets.push(socket);
+            }
+        });
+
+        let config = isb::jetstream::ClientConfig {
+            connect_timeout: Duration::from_millis(200),
+            ..isb::jetstream::ClientConfig::new(format!("127.0.0.1:{port}"))
+        };
+        // the first attempt gives up after the connect timeout, the client keeps connecting in
+        // the background
+        let result = tokio::time::timeout(Duration::from_secs(5), create_js_client(config))
+            .await
+            .expect("the first attempt to connect should be bounded");
+        assert!(result.is_ok(), "expected a client, got {result:?}");
+    }
+
+    #[tokio::test]
+    async fn test_create_js_client_auth_failed() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // a NATS server which rejects every client
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else {
+                    return;
+                };
+                let info = format!(
+                    "INFO {{\"server_id\":\"mock\",\"server_name\":\"mock\",\"version\":\"2.10.0\",\
+                     \"go\":\"go1.21\",\"host\":\"127.0.0.1\",\"port\":{port},\"headers\":true,\
+                     \"max_payload\":1048576,\"proto\":1,\"auth_required\":true}}\r\n"
+                );
+                let _ = socket.write_all(info.as_bytes()).await;
+                // wait for the CONNECT before rejecting it
+                let mut buf = [0; 1024];
+                let _ = socket.read(&mut buf).await;
+                let _ = socket
+                    .write_all(b"-ERR 'Authorization Violation'\r\n")
+                    .await;
+            }
+        });
+
+        let config = isb::jetstream::ClientConfig {
+            user: Some("user".to_string()),
+            password: Some(isb::jetstream::PasswordSource::Inline(
+                "wrong-password".to_string(),
+            )),
+            ..isb::jetstream::ClientConfig::new(format!("127.0.0.1:{port}"))
+        };
+        let result = tokio::time::timeout(Duration::from_secs(5), create_js_client(config))
+            .await
+            .expect("rejected credentials should not be retried");
+        assert!(
+            matches!(result, Err(error::Error::AuthFailed(_))),
+            "expected AuthFailed, got {result:?}"
+        );
+    }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_close_js_client() {
+        let config = isb::jetstream::ClientConfig::new("localhost:4222");
+        let js_client = create_js_client(config).await.unwrap();
+        // the clones share the connection which is closed by the drain
+        let js_context = jetstream::new(js_client.clone());
+        js_client
+            .publish("test_close_js_client", "hello".into())
+            .await
+            .unwrap();
+
+        let drain_timeout = Duration::from_secs(5);
+        let start = tokio::time::Instant::now();
+        drain_js_client(&js_client).await.unwrap();
+        assert!(start.elapsed() < drain_timeout);
+        assert!(js_context.client().flush().await.is_err());
+    }
 }
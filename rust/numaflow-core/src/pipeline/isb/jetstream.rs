@@ -1,17 +1,27 @@
-use async_nats::jetstream::Context;
-use bytes::BytesMut;
+use std::future::Future;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_nats::connection::State;
+use async_nats::jetstream::context::PublishAckFuture;
+use async_nats::jetstream::{stream, Context};
+use async_nats::{jetstream, Client, HeaderMap};
+use bytes::{Bytes, BytesMut};
+use flate2::read::GzDecoder;
 use tokio::task::JoinHandle;
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
-use tracing::info;
+use tracing::{error, info, warn};
 
-use crate::config::pipeline::isb::BufferWriterConfig;
+use crate::config::components::source::Compression;
+use crate::config::pipeline::isb::{BufferWriterConfig, RetentionPolicy};
 use crate::error::Error;
-use crate::message::{ReadAck, ReadMessage};
+use crate::message::{Message, ReadAck, ReadMessage};
 use crate::metrics::{pipeline_isb_metric_labels, pipeline_metrics};
 use crate::pipeline::isb::jetstream::writer::{
-    JetstreamWriter, PafResolver, ResolveAndPublishResult,
+    IsbWriteError, JetstreamWriter, PafResolver, ResolveAndPublishResult, WriteResult,
 };
 use crate::Result;
 
@@ -27,6 +37,108 @@ pub(crate) mod reader;
 /// Stream is a combination of stream name and partition id.
 type Stream = (String, u16);
 
+/// NATS header carrying the compression of the payload written to the ISB, the reader decompresses
+/// the payload accordingly. The payload is not compressed if the header is missing.
+pub(crate) const CONTENT_ENCODING_HEADER: &str = "content-encoding";
+
+/// returns the [CONTENT_ENCODING_HEADER] value of the compression.
+fn content_encoding(compression: Compression) -> &'static str {
+    match compression {
+        Compression::Gzip => "gzip",
+    }
+}
+
+/// decompresses the payload as per its [CONTENT_ENCODING_HEADER], the payload is returned as is if
+/// the header is not set.
+fn decompress_payload(headers: Option<&HeaderMap>, payload: Bytes) -> Result<Bytes> {
+    let Some(encoding) = headers.and_then(|headers| headers.get(CONTENT_ENCODING_HEADER)) else {
+        return Ok(payload);
+    };
+    match encoding.as_str() {
+        "gzip" => {
+            let mut decompressed = vec![];
+            GzDecoder::new(payload.as_ref())
+                .read_to_end(&mut decompressed)
+                .map_err(|e| Error::ISB(format!("Failed to gunzip the payload: {e}")))?;
+            Ok(decompressed.into())
+        }
+        encoding => Err(Error::ISB(format!(
+            "Unsupported content encoding {encoding} of the payload"
+        ))),
+    }
+}
+
+/// StreamSelector picks the stream for the next message of a buffer. The keyed messages are routed
+/// by the hash of their keys, so that all the messages of a key land on the same partition (needed
+/// for keyed reduce), each stream gets a share of the keys proportional to its weight. The rest are
+/// distributed across the streams proportionally to the
+/// `stream_weights` using smooth weighted round-robin, so that the streams are interleaved instead
+/// of being written in bursts. Without weights, it is a plain round-robin.
+struct StreamSelector {
+    weights: Vec<i64>,
+    current: Vec<i64>,
+    total: i64,
+    /// number of the messages distributed by the round-robin so far.
+    counter: u64,
+}
+
+impl StreamSelector {
+    fn new(config: &BufferWriterConfig) -> Self {
+        let stream_count = config.streams.len();
+        let weights: Vec<i64> = if config.stream_weights.is_empty() {
+            vec![1; stream_count]
+        } else if config.stream_weights.len() != stream_count
+            || config.stream_weights.iter().all(|w| *w == 0)
+        {
+            warn!(
+                weights = ?config.stream_weights,
+                stream_count, "Invalid stream weights, distributing the messages uniformly"
+            );
+            vec![1; stream_count]
+        } else {
+            config.stream_weights.iter().map(|w| *w as i64).collect()
+        };
+
+        Self {
+            total: weights.iter().sum(),
+            current: vec![0; stream_count],
+            weights,
+            counter: 0,
+        }
+    }
+
+    /// returns the index of the stream to which the message with the given keys should be written.
+    /// The keys are hashed onto the slots of the streams, each stream has as many slots as its
+    /// weight.
+    fn select(&mut self, keys: &[String]) -> usize {
+        if keys.is_empty() {
+            return self.next();
+        }
+        let mut slot = (key_hash(keys) % self.total as u64) as i64;
+        self.weights
+            .iter()
+            .position(|weight| {
+                slot -= weight;
+                slot < 0
+            })
+            .expect("slot should be within the total weight")
+    }
+
+    /// returns the index of the stream to which the next message should be written.
+    fn next(&mut self) -> usize {
+        let mut selected = 0;
+        for (i, weight) in self.weights.iter().enumerate() {
+            self.current[i] += weight;
+            if self.current[i] > self.current[selected] {
+                selected = i;
+            }
+        }
+        self.current[selected] -= self.total;
+        self.counter += 1;
+        selected
+    }
+}
+
 /// StreamingJetstreamWriter is a streaming version of JetstreamWriter. It accepts a stream of messages
 /// and writes them to Jetstream ISB. It also has a PAF resolver actor to resolve the PAFs.
 #[derive(Clone)]
@@ -34,6 +146,10 @@ pub(crate) struct ISBWriter {
     paf_concurrency: usize,
     config: Vec<BufferWriterConfig>,
     writer: JetstreamWriter,
+    /// stream selector of each buffer, shared across the streaming writes so that the round-robin
+    /// carries on where the previous write left off instead of restarting from the first stream.
+    selectors: Arc<Mutex<Vec<StreamSelector>>>,
+    cancel_token: CancellationToken,
 }
 
 impl ISBWriter {
@@ -54,12 +170,31 @@ impl ISBWriter {
         );
 
         Self {
+            selectors: Arc::new(Mutex::new(config.iter().map(StreamSelector::new).collect())),
             config,
             writer: js_writer,
             paf_concurrency,
+            cancel_token,
         }
     }
 
+    /// Returns the number of the messages distributed by the round-robin so far for each of the
+    /// buffers, the keyed messages are not counted since they are routed by their keys.
+    #[cfg(test)]
+    pub(crate) fn round_robin_counters(&self) -> Vec<u64> {
+        self.selectors
+            .lock()
+            .expect("stream selectors lock poisoned")
+            .iter()
+            .map(|selector| selector.counter)
+            .collect()
+    }
+
+    /// Returns the backpressure signal of the buffers, see [JetstreamWriter::pressure].
+    pub(crate) fn pressure(&self) -> tokio::sync::watch::Receiver<f64> {
+        self.writer.pressure()
+    }
+
     /// Starts reading messages from the stream and writes them to Jetstream ISB.
     pub(crate) async fn streaming_write(
         &self,
@@ -69,8 +204,10 @@ impl ISBWriter {
             let writer = self.writer.clone();
             let paf_concurrency = self.paf_concurrency;
             let config = self.config.clone();
+            let write_concurrency = write_concurrency(&config);
             let mut messages_stream = messages_stream;
-            let mut index = 0;
+            let selectors = Arc::clone(&self.selectors);
+            let cancel_token = self.cancel_token.clone();
 
             async move {
                 let paf_resolver = PafResolver::new(paf_concurrency, writer.clone());
@@ -85,19 +222,75 @@ impl ISBWriter {
                         continue;
                     }
                     let mut pafs = vec![];
+                    let headers = nats_headers(&read_message.message);
+                    let payload: BytesMut = read_message
+                        .message
+                        .clone()
+                        .try_into()
+                        .expect("message serialization should not fail");
+                    let payload: Vec<u8> = payload.into();
+
+                    // the message is written to one of the streams of each buffer
+                    let streams = {
+                        let mut selectors =
+                            selectors.lock().expect("stream selectors lock poisoned");
+                        config
+                            .iter()
+                            .zip(selectors.iter_mut())
+                            .map(|(buffer, selector)| {
+                                buffer.streams[selector.select(&read_message.message.keys)].clone()
+                            })
+                            .collect()
+                    };
+                    let results = write_concurrently(streams, write_concurrency, |stream| {
+                        writer.write(stream, payload.clone(), headers.clone())
+                    })
+                    .await;
 
-                    // FIXME(CF): This is a temporary solution to round-robin the streams
-                    for buffer in &config {
-                        let payload: BytesMut = read_message
-                            .message
-                            .clone()
-                            .try_into()
-                            .expect("message serialization should not fail");
-                        let stream = buffer.streams.get(index).unwrap();
-                        index = (index + 1) % buffer.streams.len();
-
-                        let paf = writer.write(stream.clone(), payload.into()).await;
-                        pafs.push((stream.clone(), paf));
+                    let mut written = false;
+                    let mut failed = vec![];
+                    for (stream, result) in results {
+                        match result {
+                            Ok(result) => {
+                                written |= !matches!(result, WriteResult::Discarded);
+                                collect_write_result(stream, result, &mut pafs);
+                            }
+                            Err(e) => failed.push((stream, e)),
+                        }
+                    }
+
+                    if !failed.is_empty() {
+                        // the write gave up (e.g., the retry deadline was exceeded) before the
+                        // message was written anywhere, the message is NAK'd so that it is
+                        // redelivered and we move on to the next message.
+                        if !written {
+                            error!(errors = ?failed, "Failed to write the message, NAK'ing it");
+                            if read_message.ack.send(ReadAck::Nak).is_err() {
+                                error!(
+                                    "Failed to send NAK for the message which failed to be written"
+                                );
+                            }
+                            continue;
+                        }
+                        // the message has been written to some of the streams, NAK'ing it would
+                        // write it again to those streams on redelivery. Hence, only the failed
+                        // streams are retried (without a deadline).
+                        for (stream, e) in failed {
+                            warn!(
+                                ?e,
+                                ?stream,
+                                "Retrying the write of the partly written message"
+                            );
+                            let result = write_until_success(
+                                &writer,
+                                stream.clone(),
+                                &payload,
+                                &headers,
+                                &cancel_token,
+                            )
+                            .await?;
+                            collect_write_result(stream, result, &mut pafs);
+                        }
                     }
 
                     pipeline_metrics()
@@ -110,10 +303,16 @@ impl ISBWriter {
                         .resolve_pafs(ResolveAndPublishResult {
                             pafs,
                             payload: read_message.message.value.clone().into(),
+                            headers,
                             ack_tx: read_message.ack,
                         })
                         .await?;
                 }
+                // the pending publish acks are resolved before we return, so that the messages
+                // written so far are acked (e.g., while shutting down).
+                if let Err(e) = paf_resolver.flush().await {
+                    warn!(?e, "Failed to flush the writes");
+                }
                 Ok(())
             }
         });
@@ -121,17 +320,812 @@ impl ISBWriter {
     }
 }
 
+/// collects the PAF of a successful write to be resolved, the discarded messages are counted as
+/// dropped.
+fn collect_write_result(
+    stream: Stream,
+    result: WriteResult,
+    pafs: &mut Vec<(Stream, PublishAckFuture)>,
+) {
+    match result.into_paf(&stream) {
+        Ok(paf) => pafs.push((stream, paf)),
+        // nothing to resolve, the message is acked once the others are resolved
+        Err(IsbWriteError::DryRun(_)) => {}
+        // buffer is full and the strategy is to discard the latest message
+        Err(_) => {
+            pipeline_metrics()
+                .forwarder
+                .dropped_total
+                .get_or_create(pipeline_isb_metric_labels())
+                .inc();
+        }
+    }
+}
+
+/// writes the payload to the stream till it succeeds, regardless of the retry deadline. An error
+/// is returned only if the stream is unknown or the shutdown has been initiated.
+async fn write_until_success(
+    writer: &JetstreamWriter,
+    stream: Stream,
+    payload: &[u8],
+    headers: &HeaderMap,
+    cancel_token: &CancellationToken,
+) -> Result<WriteResult> {
+    loop {
+        match writer
+            .write(stream.clone(), payload.to_vec(), headers.clone())
+            .await
+        {
+            Ok(result) => return Ok(result),
+            Err(e @ IsbWriteError::StreamNotFound(_)) => return Err(e.into()),
+            Err(e) if cancel_token.is_cancelled() => {
+                return Err(Error::ISB(format!(
+                    "Shutdown signal received while writing to stream {}: {e}",
+                    stream.0
+                )));
+            }
+            Err(e) => warn!(?e, ?stream, "Write gave up, retrying"),
+        }
+    }
+}
+
+/// returns the FNV-1a hash of the keys. Unlike the std hasher, it is stable across processes and
+/// releases, so that all the replicas route a key to the same partition.
+fn key_hash(keys: &[String]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    // keys are separated by a zero byte, so that ["ab", "c"] and ["a", "bc"] hash differently
+    let bytes = keys
+        .iter()
+        .enumerate()
+        .flat_map(|(i, key)| (i > 0).then_some(0u8).into_iter().chain(key.bytes()));
+    bytes.fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// returns the maximum number of concurrent writes of a message. A message is written to one stream
+/// of each buffer at once, hence the highest `write_concurrency` of the buffers applies.
+fn write_concurrency(config: &[BufferWriterConfig]) -> usize {
+    config
+        .iter()
+        .map(|buffer| buffer.write_concurrency)
+        .max()
+        .unwrap_or(1)
+}
+
+/// Writes to each of the streams concurrently with at most `concurrency` writes in flight, the
+/// results are returned in the order of the streams. Since a message is written at most once to
+/// a stream, the ordering within a stream is preserved as long as the messages are written one
+/// after the other.
+async fn write_concurrently<F, Fut, T>(
+    streams: Vec<Stream>,
+    concurrency: usize,
+    write: F,
+) -> Vec<(Stream, T)>
+where
+    F: Fn(Stream) -> Fut,
+    Fut: Future<Output = T>,
+{
+    let writes = streams.into_iter().map(|stream| {
+        let write = write(stream.clone());
+        async move { (stream, write.await) }
+    });
+    let results = futures::StreamExt::buffered(futures::stream::iter(writes), concurrency.max(1));
+    futures::StreamExt::collect(results).await
+}
+
+/// returns the NATS headers of the message, the headers of the message are propagated so that
+/// downstream can act on them (e.g., skip the expired messages or pick a decoder by the content
+/// type) without decoding the message. The [CONTENT_ENCODING_HEADER] is owned by the writer and is
+/// not propagated, and the headers which cannot be encoded as NATS headers are skipped.
+fn nats_headers(message: &Message) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (key, value) in &message.headers {
+        if key.eq_ignore_ascii_case(CONTENT_ENCODING_HEADER) {
+            continue;
+        }
+        if !is_valid_header_name(key) || !is_valid_header_value(value) {
+            warn!(
+                ?key,
+                ?value,
+                "Skipping the header which is not a valid NATS header"
+            );
+            continue;
+        }
+        headers.insert(key.as_str(), value.as_str());
+    }
+    headers
+}
+
+/// a NATS header name is a non-empty token of visible ASCII characters without a `:`.
+fn is_valid_header_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_graphic() && b != b':')
+}
+
+/// a NATS header value must not break the header line, hence no CR or LF.
+fn is_valid_header_value(value: &str) -> bool {
+    !value.bytes().any(|b| b == b'\r' || b == b'\n')
+}
+
+/// ISBHealth is the readiness status of the Jetstream ISB.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ISBHealth {
+    /// connected to the server and all the streams exist.
+    Healthy,
+    /// not connected to the server or the server did not respond in time.
+    Disconnected,
+    /// connected to the server, but the listed streams do not exist.
+    MissingStreams(Vec<Stream>),
+}
+
+impl ISBHealth {
+    pub(crate) fn is_healthy(&self) -> bool {
+        matches!(self, ISBHealth::Healthy)
+    }
+}
+
+/// Checks whether the Jetstream ISB is ready to be written to, the server is pinged and each of the
+/// streams of the buffers is looked up. A vertex should not report ready while its buffers are
+/// missing.
+pub(crate) async fn health_check(
+    js_client: &Client,
+    buffers: &[BufferWriterConfig],
+    timeout: Duration,
+) -> ISBHealth {
+    if js_client.connection_state() != State::Connected {
+        return ISBHealth::Disconnected;
+    }
+    // flush waits for the server to respond to a PING
+    if !matches!(
+        tokio::time::timeout(timeout, js_client.flush()).await,
+        Ok(Ok(()))
+    ) {
+        return ISBHealth::Disconnected;
+    }
+
+    let js_context = jetstream::new(js_client.clone());
+    let mut missing_streams = vec![];
+    for stream in buffers.iter().flat_map(|buffer| buffer.streams.iter()) {
+        match tokio::time::timeout(timeout, js_context.get_stream(&stream.0)).await {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => {
+                warn!(?e, ?stream, "Stream is not reachable");
+                missing_streams.push(stream.clone());
+            }
+            Err(_) => return ISBHealth::Disconnected,
+        }
+    }
+
+    if missing_streams.is_empty() {
+        ISBHealth::Healthy
+    } else {
+        ISBHealth::MissingStreams(missing_streams)
+    }
+}
+
+impl From<RetentionPolicy> for stream::RetentionPolicy {
+    fn from(retention: RetentionPolicy) -> Self {
+        match retention {
+            RetentionPolicy::Limits => stream::RetentionPolicy::Limits,
+            RetentionPolicy::Interest => stream::RetentionPolicy::Interest,
+            RetentionPolicy::WorkQueue => stream::RetentionPolicy::WorkQueue,
+        }
+    }
+}
+
+/// Creates the streams of the buffers which have `auto_create_streams` set, the existing streams
+/// are left as is. A stream is created with its (optionally prefixed) name as the subject, its max
+/// length as the max messages and the retention policy of the buffer.
+pub(crate) async fn create_streams(js_ctx: &Context, buffers: &[BufferWriterConfig]) -> Result<()> {
+    for buffer in buffers.iter().filter(|buffer| buffer.auto_create_streams) {
+        for (stream_name, _) in &buffer.streams {
+            js_ctx
+                .get_or_create_stream(stream::Config {
+                    name: stream_name.clone(),
+                    subjects: vec![buffer.subject(stream_name)],
+                    max_messages: buffer.stream_max_length(stream_name) as i64,
+                    retention: buffer.retention.into(),
+                    ..Default::default()
+                })
+                .await
+                .map_err(|e| Error::ISB(format!("Failed to create stream {stream_name}: {e}")))?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
 
-    use async_nats::jetstream;
-    use async_nats::jetstream::{consumer, stream};
+    use async_nats::jetstream::consumer;
     use chrono::Utc;
     use tokio::sync::oneshot;
 
     use super::*;
-    use crate::message::{Message, MessageID, ReadAck};
+    use crate::config::pipeline::isb::FaultConfig;
+    use crate::message::{
+        IntOffset, MessageID, Offset, ReadAck, CONTENT_TYPE_HEADER, EXPIRES_AT_HEADER,
+    };
+
+    /// returns a client which is never connected since nothing is listening, it keeps connecting in
+    /// the background and the publishes are buffered by the client till then.
+    async fn disconnected_client() -> async_nats::Client {
+        async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .connect("localhost:1")
+            .await
+            .unwrap()
+    }
+
+    fn stream_counts(config: &BufferWriterConfig, messages: usize) -> Vec<usize> {
+        let mut selector = StreamSelector::new(config);
+        let mut counts = vec![0; config.streams.len()];
+        for _ in 0..messages {
+            counts[selector.next()] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn test_stream_selector_weighted() {
+        let config = BufferWriterConfig {
+            streams: vec![("stream-0".to_string(), 0), ("stream-1".to_string(), 1)],
+            stream_weights: vec![3, 1],
+            ..Default::default()
+        };
+        assert_eq!(stream_counts(&config, 1000), vec![750, 250]);
+
+        // the streams are interleaved rather than written in bursts
+        let mut selector = StreamSelector::new(&config);
+        let picks: Vec<usize> = (0..4).map(|_| selector.next()).collect();
+        assert_eq!(picks, vec![0, 0, 1, 0]);
+    }
+
+    #[test]
+    fn test_stream_selector_uniform() {
+        let streams = (0..3).map(|i| (format!("stream-{}", i), i)).collect();
+        let config = BufferWriterConfig {
+            streams,
+            ..Default::default()
+        };
+        assert_eq!(stream_counts(&config, 999), vec![333, 333, 333]);
+
+        // round-robin without weights
+        let mut selector = StreamSelector::new(&config);
+        let picks: Vec<usize> = (0..6).map(|_| selector.next()).collect();
+        assert_eq!(picks, vec![0, 1, 2, 0, 1, 2]);
+
+        // weights not matching the streams are ignored
+        let config = BufferWriterConfig {
+            stream_weights: vec![5],
+            ..config
+        };
+        assert_eq!(stream_counts(&config, 999), vec![333, 333, 333]);
+    }
+
+    #[test]
+    fn test_stream_selector_keyed() {
+        let streams = (0..4).map(|i| (format!("stream-{}", i), i)).collect();
+        let config = BufferWriterConfig {
+            streams,
+            partitions: 4,
+            ..Default::default()
+        };
+        let mut selector = StreamSelector::new(&config);
+
+        // identical keys always map to the same partition
+        for i in 0..100 {
+            let keys = vec![format!("key-{}", i % 10), "suffix".to_string()];
+            let partition = selector.select(&keys);
+            assert!(partition < 4);
+            for _ in 0..5 {
+                assert_eq!(selector.select(&keys), partition);
+            }
+            // irrespective of the selector
+            assert_eq!(StreamSelector::new(&config).select(&keys), partition);
+        }
+
+        // the keys are spread across the partitions
+        let partitions: HashSet<usize> = (0..100)
+            .map(|i| selector.select(&[format!("key-{}", i)]))
+            .collect();
+        assert_eq!(partitions.len(), 4);
+
+        // empty keys are round-robin
+        let mut selector = StreamSelector::new(&config);
+        let picks: Vec<usize> = (0..8).map(|_| selector.select(&[])).collect();
+        assert_eq!(picks, vec![0, 1, 2, 3, 0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_stream_selector_keyed_weighted() {
+        let streams = (0..3).map(|i| (format!("stream-{}", i), i)).collect();
+        let config = BufferWriterConfig {
+            streams,
+            partitions: 3,
+            stream_weights: vec![3, 1, 0],
+            ..Default::default()
+        };
+        let mut selector = StreamSelector::new(&config);
+
+        // the keys are spread proportionally to the weights
+        let mut counts = vec![0; 3];
+        for i in 0..4000 {
+            let keys = vec![format!("key-{}", i)];
+            let partition = selector.select(&keys);
+            counts[partition] += 1;
+            // identical keys still map to the same partition
+            assert_eq!(selector.select(&keys), partition);
+        }
+        assert_eq!(counts[2], 0);
+        assert!((2700..3300).contains(&counts[0]), "counts {counts:?}");
+        assert!((700..1300).contains(&counts[1]), "counts {counts:?}");
+    }
+
+    #[test]
+    fn test_key_hash() {
+        let keys = |keys: &[&str]| keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        // FNV-1a of "a"
+        assert_eq!(key_hash(&keys(&["a"])), 0xaf63dc4c8601ec8c);
+        assert_ne!(key_hash(&keys(&["ab", "c"])), key_hash(&keys(&["a", "bc"])));
+    }
+
+    #[test]
+    fn test_payload_compression() {
+        let payload = "payload ".repeat(100).into_bytes();
+        let compressed = Compression::Gzip.compress(&payload);
+        assert!(compressed.len() < payload.len());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING_HEADER, content_encoding(Compression::Gzip));
+        let decompressed = decompress_payload(Some(&headers), compressed.into()).unwrap();
+        assert_eq!(decompressed, Bytes::from(payload.clone()));
+
+        // payloads without the header are not compressed
+        let payload = Bytes::from(payload);
+        assert_eq!(decompress_payload(None, payload.clone()).unwrap(), payload);
+        assert_eq!(
+            decompress_payload(Some(&HeaderMap::new()), payload.clone()).unwrap(),
+            payload
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_ENCODING_HEADER, "zstd");
+        assert!(decompress_payload(Some(&headers), payload.clone()).is_err());
+        headers.insert(CONTENT_ENCODING_HEADER, "gzip");
+        assert!(decompress_payload(Some(&headers), payload).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_concurrently() {
+        let streams: Vec<Stream> = (0..3).map(|i| (format!("stream-{}", i), i)).collect();
+        let delay = Duration::from_millis(50);
+
+        // writes the messages one after the other, returns the time taken and the order in which
+        // the messages were written to each stream.
+        async fn write_messages(
+            streams: &[Stream],
+            concurrency: usize,
+            delay: Duration,
+        ) -> (Duration, HashMap<String, Vec<usize>>) {
+            let written = std::sync::Mutex::new(HashMap::<String, Vec<usize>>::new());
+            let start = tokio::time::Instant::now();
+            for message in 0..2 {
+                let results = write_concurrently(streams.to_vec(), concurrency, |stream| {
+                    let written = &written;
+                    async move {
+                        tokio::time::sleep(delay).await;
+                        let mut written = written.lock().unwrap();
+                        written.entry(stream.0).or_default().push(message);
+                    }
+                })
+                .await;
+                // results are in the order of the streams
+                let result_streams: Vec<Stream> = results.into_iter().map(|r| r.0).collect();
+                assert_eq!(result_streams, streams);
+            }
+            (start.elapsed(), written.into_inner().unwrap())
+        }
+
+        // sequential writes take the sum of the delays
+        let (elapsed, written) = write_messages(&streams, 1, delay).await;
+        assert!(elapsed >= delay * 6);
+        assert!(written.values().all(|messages| messages == &vec![0, 1]));
+
+        // concurrent writes overlap, but the ordering within a stream holds
+        let (elapsed, written) = write_messages(&streams, 3, delay).await;
+        assert!(elapsed < delay * 6);
+        assert_eq!(written.len(), 3);
+        assert!(written.values().all(|messages| messages == &vec![0, 1]));
+    }
+
+    #[test]
+    fn test_write_concurrency() {
+        let buffer = |write_concurrency| BufferWriterConfig {
+            write_concurrency,
+            ..Default::default()
+        };
+        assert_eq!(write_concurrency(&[]), 1);
+        assert_eq!(write_concurrency(&[buffer(1), buffer(4), buffer(2)]), 4);
+    }
+
+    #[test]
+    fn test_nats_headers() {
+        let mut message = Message {
+            keys: vec!["key".to_string()],
+            value: "value".as_bytes().to_vec().into(),
+            offset: None,
+            event_time: Utc::now(),
+            id: MessageID {
+                vertex_name: "vertex".to_string(),
+                offset: "offset".to_string(),
+                index: 0,
+            },
+            headers: HashMap::new(),
+        };
+        assert!(nats_headers(&message).is_empty());
+
+        message.headers = HashMap::from([
+            (EXPIRES_AT_HEADER.to_string(), "1700000030000".to_string()),
+            (
+                CONTENT_TYPE_HEADER.to_string(),
+                "application/json".to_string(),
+            ),
+            ("other".to_string(), "value".to_string()),
+            // owned by the writer, it would make the reader decompress the payload
+            (CONTENT_ENCODING_HEADER.to_string(), "gzip".to_string()),
+        ]);
+        let headers = nats_headers(&message);
+        assert_eq!(
+            headers.get(EXPIRES_AT_HEADER).map(|v| v.as_str()),
+            Some("1700000030000")
+        );
+        assert_eq!(
+            headers.get(CONTENT_TYPE_HEADER).map(|v| v.as_str()),
+            Some("application/json")
+        );
+        assert_eq!(headers.get("other").map(|v| v.as_str()), Some("value"));
+        assert!(headers.get(CONTENT_ENCODING_HEADER).is_none());
+
+        // the headers which would corrupt the NATS header block are skipped
+        message.headers = HashMap::from([
+            ("valid".to_string(), "value".to_string()),
+            ("".to_string(), "value".to_string()),
+            ("with:colon".to_string(), "value".to_string()),
+            ("with space".to_string(), "value".to_string()),
+            ("with\nnewline".to_string(), "value".to_string()),
+            ("crlf".to_string(), "value\r\nInjected: header".to_string()),
+        ]);
+        let headers = nats_headers(&message);
+        assert_eq!(headers.get("valid").map(|v| v.as_str()), Some("value"));
+        for key in ["with:colon", "with space", "with\nnewline", "crlf"] {
+            assert!(headers.get(key).is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_health_check_disconnected() {
+        let client = disconnected_client().await;
+
+        let buffers = vec![BufferWriterConfig {
+            streams: vec![("test_health_check_disconnected".to_string(), 0)],
+            ..Default::default()
+        }];
+        let health = health_check(&client, &buffers, Duration::from_millis(100)).await;
+        assert_eq!(health, ISBHealth::Disconnected);
+        assert!(!health.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_streaming_write_even_distribution() {
+        let cln_token = CancellationToken::new();
+        // nothing is listening, the writes are only recorded
+        let client = disconnected_client().await;
+        let context = jetstream::new(client);
+
+        let streams: Vec<Stream> = (0..3)
+            .map(|i| (format!("test_even_distribution-{}", i), i))
+            .collect();
+        let buffers = vec![BufferWriterConfig::builder()
+            .streams(streams.clone())
+            .partitions(3)
+            .dry_run(true)
+            .build()];
+        let writer = ISBWriter::new(10, buffers, context, cln_token.clone()).await;
+
+        // batches of uneven sizes, the round-robin carries on across the batches
+        let mut written = 0;
+        for batch_size in [2, 4, 1] {
+            let (messages_tx, messages_rx) = tokio::sync::mpsc::channel(batch_size);
+            for i in 0..batch_size {
+                let (ack_tx, _ack_rx) = oneshot::channel();
+                let offset = Offset::Int(IntOffset::new(written + i as u64, 0));
+                messages_tx
+                    .send(ReadMessage {
+                        message: Message::from_bytes("value", offset),
+                        ack: ack_tx,
+                    })
+                    .await
+                    .unwrap();
+            }
+            drop(messages_tx);
+            let handle = writer
+                .streaming_write(ReceiverStream::new(messages_rx))
+                .await
+                .unwrap();
+            handle.await.unwrap().unwrap();
+            written += batch_size as u64;
+            assert_eq!(writer.round_robin_counters(), vec![written]);
+        }
+
+        let mut counts = vec![0; streams.len()];
+        for write in writer.writer.dry_run_writes() {
+            counts[streams.iter().position(|s| *s == write.stream).unwrap()] += 1;
+        }
+        assert_eq!(counts.iter().sum::<u64>(), 7);
+        let (min, max) = (counts.iter().min().unwrap(), counts.iter().max().unwrap());
+        assert!(max - min <= 1, "uneven distribution {counts:?}");
+
+        cln_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_streaming_write_dry_run() {
+        let cln_token = CancellationToken::new();
+        // nothing is listening, so a real publish would never be acked
+        let client = disconnected_client().await;
+        let context = jetstream::new(client);
+
+        let buffers = vec![
+            BufferWriterConfig::builder()
+                .streams(vec![
+                    ("test_dry_run-0".to_string(), 0),
+                    ("test_dry_run-1".to_string(), 1),
+                ])
+                .partitions(2)
+                .dry_run(true)
+                .build(),
+            BufferWriterConfig::builder()
+                .streams(vec![("test_dry_run_other-0".to_string(), 0)])
+                .build(),
+        ];
+        let writer = ISBWriter::new(10, buffers, context, cln_token.clone()).await;
+
+        let (messages_tx, messages_rx) = tokio::sync::mpsc::channel(3);
+        let mut expected_bytes = vec![];
+        let mut ack_receivers = vec![];
+        for i in 0..3 {
+            let message = Message {
+                keys: vec![],
+                value: format!("message {}", i).into_bytes().into(),
+                offset: None,
+                event_time: Utc::now(),
+                id: MessageID {
+                    vertex_name: "vertex".to_string(),
+                    offset: format!("offset_{}", i),
+                    index: i,
+                },
+                headers: HashMap::new(),
+            };
+            let payload: BytesMut = message.clone().try_into().unwrap();
+            expected_bytes.push(payload.len());
+            let (ack_tx, ack_rx) = oneshot::channel();
+            messages_tx
+                .send(ReadMessage {
+                    message,
+                    ack: ack_tx,
+                })
+                .await
+                .unwrap();
+            ack_receivers.push(ack_rx);
+        }
+        drop(messages_tx);
+
+        let handle = writer
+            .streaming_write(ReceiverStream::new(messages_rx))
+            .await
+            .unwrap();
+        for ack_rx in ack_receivers {
+            let ack = tokio::time::timeout(Duration::from_secs(1), ack_rx)
+                .await
+                .expect("a dry run should not wait for the server")
+                .unwrap();
+            assert_eq!(ack, ReadAck::Ack);
+        }
+        handle.await.unwrap().unwrap();
+
+        // the dry-run setting of the first buffer applies to the writer, each message is routed
+        // to one stream of each buffer
+        let written: Vec<(String, usize)> = writer
+            .writer
+            .dry_run_writes()
+            .into_iter()
+            .map(|write| (write.stream.0, write.bytes))
+            .collect();
+        assert_eq!(
+            written,
+            vec![
+                ("test_dry_run-0".to_string(), expected_bytes[0]),
+                ("test_dry_run_other-0".to_string(), expected_bytes[0]),
+                ("test_dry_run-1".to_string(), expected_bytes[1]),
+                ("test_dry_run_other-0".to_string(), expected_bytes[1]),
+                ("test_dry_run-0".to_string(), expected_bytes[2]),
+                ("test_dry_run_other-0".to_string(), expected_bytes[2]),
+            ]
+        );
+        cln_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_streaming_write_retry_deadline() {
+        let cln_token = CancellationToken::new();
+        // nothing is listening, every publish fails before reaching the server anyway
+        let client = disconnected_client().await;
+        let context = jetstream::new(client);
+
+        let buffers = vec![BufferWriterConfig::builder()
+            .streams(vec![(
+                "test_streaming_write_retry_deadline-0".to_string(),
+                0,
+            )])
+            .retry_max_interval(Duration::from_millis(10))
+            .retry_deadline(Some(Duration::from_millis(50)))
+            .flush_timeout(Duration::from_millis(50))
+            .fault_injection(Some(FaultConfig {
+                fail_fraction: 1.0,
+                seed: 0,
+            }))
+            .build()];
+        let writer = ISBWriter::new(10, buffers, context, cln_token.clone()).await;
+
+        let (messages_tx, messages_rx) = tokio::sync::mpsc::channel(2);
+        let mut ack_receivers = vec![];
+        for i in 0..2 {
+            let (ack_tx, ack_rx) = oneshot::channel();
+            messages_tx
+                .send(ReadMessage {
+                    message: Message::from_bytes("value", Offset::Int(IntOffset::new(i, 0))),
+                    ack: ack_tx,
+                })
+                .await
+                .unwrap();
+            ack_receivers.push(ack_rx);
+        }
+        drop(messages_tx);
+
+        let handle = writer
+            .streaming_write(ReceiverStream::new(messages_rx))
+            .await
+            .unwrap();
+        // each of the messages which could not be written is NAK'd, the writer keeps running
+        for ack_rx in ack_receivers {
+            let ack = tokio::time::timeout(Duration::from_secs(1), ack_rx)
+                .await
+                .expect("the write should give up after the deadline")
+                .unwrap();
+            assert_eq!(ack, ReadAck::Nak);
+        }
+        handle.await.unwrap().unwrap();
+
+        cln_token.cancel();
+    }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_streaming_write_partly_written() {
+        let cln_token = CancellationToken::new();
+        let js_url = "localhost:4222";
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_names = [
+            "test_streaming_write_partly_written-0",
+            "test_streaming_write_partly_written-1",
+        ];
+        for stream_name in stream_names {
+            context
+                .get_or_create_stream(stream::Config {
+                    name: stream_name.into(),
+                    subjects: vec![stream_name.into()],
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+        }
+
+        // the message is written to a stream of each of the buffers
+        let buffers = stream_names
+            .iter()
+            .map(|stream_name| {
+                BufferWriterConfig::builder()
+                    .streams(vec![(stream_name.to_string(), 0)])
+                    // make sure the background task does not reset the is_full flag
+                    .refresh_interval(Duration::from_secs(60))
+                    .retry_max_interval(Duration::from_millis(10))
+                    .retry_deadline(Some(Duration::from_millis(50)))
+                    .build()
+            })
+            .collect();
+        let writer = ISBWriter::new(10, buffers, context.clone(), cln_token.clone()).await;
+
+        // let the first refresh of the buffer usage go through before simulating a full buffer
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        writer.writer.set_full(stream_names[0], true);
+
+        let (messages_tx, messages_rx) = tokio::sync::mpsc::channel(1);
+        let (ack_tx, mut ack_rx) = oneshot::channel();
+        messages_tx
+            .send(ReadMessage {
+                message: Message::from_bytes("value", Offset::Int(IntOffset::new(0, 0))),
+                ack: ack_tx,
+            })
+            .await
+            .unwrap();
+        drop(messages_tx);
+        let handle = writer
+            .streaming_write(ReceiverStream::new(messages_rx))
+            .await
+            .unwrap();
+
+        // the message made it to the second buffer, so it is not NAK'd past the deadline
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(ack_rx.try_recv().is_err());
+
+        // the first buffer is retried once it has space, without writing the second one again
+        writer.writer.set_full(stream_names[0], false);
+        let ack = tokio::time::timeout(Duration::from_secs(1), ack_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ack, ReadAck::Ack);
+        handle.await.unwrap().unwrap();
+
+        for stream_name in stream_names {
+            let mut stream = context.get_stream(stream_name).await.unwrap();
+            assert_eq!(stream.info().await.unwrap().state.messages, 1);
+            context.delete_stream(stream_name).await.unwrap();
+        }
+        cln_token.cancel();
+    }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_health_check() {
+        let js_url = "localhost:4222";
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client.clone());
+
+        let stream_name = "test_health_check";
+        let _stream = context
+            .get_or_create_stream(stream::Config {
+                name: stream_name.into(),
+                subjects: vec![stream_name.into()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let timeout = Duration::from_secs(1);
+        let buffers = vec![BufferWriterConfig {
+            streams: vec![(stream_name.to_string(), 0)],
+            ..Default::default()
+        }];
+        let health = health_check(&client, &buffers, timeout).await;
+        assert_eq!(health, ISBHealth::Healthy);
+
+        let missing_stream = ("test_health_check_missing".to_string(), 1);
+        let buffers = vec![BufferWriterConfig {
+            streams: vec![(stream_name.to_string(), 0), missing_stream.clone()],
+            ..Default::default()
+        }];
+        let health = health_check(&client, &buffers, timeout).await;
+        assert_eq!(health, ISBHealth::MissingStreams(vec![missing_stream]));
+
+        context.delete_stream(stream_name).await.unwrap();
+    }
 
     #[cfg(feature = "nats-tests")]
     #[tokio::test]
@@ -213,6 +1207,101 @@ mod tests {
         context.delete_stream(stream_name).await.unwrap();
     }
 
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_publish_messages_with_headers() {
+        let cln_token = CancellationToken::new();
+        let js_url = "localhost:4222";
+        // Create JetStream context
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_publish_messages_with_headers";
+        let _stream = context
+            .get_or_create_stream(stream::Config {
+                name: stream_name.into(),
+                subjects: vec![stream_name.into()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let consumer = context
+            .create_consumer_on_stream(
+                consumer::pull::Config {
+                    name: Some(stream_name.to_string()),
+                    ack_policy: consumer::AckPolicy::Explicit,
+                    ..Default::default()
+                },
+                stream_name,
+            )
+            .await
+            .unwrap();
+
+        let writer = ISBWriter::new(
+            10,
+            vec![BufferWriterConfig {
+                streams: vec![(stream_name.to_string(), 0)],
+                ..Default::default()
+            }],
+            context.clone(),
+            cln_token.clone(),
+        )
+        .await;
+
+        let message = Message {
+            keys: vec!["key".to_string()],
+            value: r#"{"id": 1}"#.as_bytes().to_vec().into(),
+            offset: None,
+            event_time: Utc::now(),
+            id: MessageID {
+                vertex_name: "vertex".to_string(),
+                offset: "offset".to_string(),
+                index: 0,
+            },
+            headers: HashMap::from([(
+                CONTENT_TYPE_HEADER.to_string(),
+                "application/json".to_string(),
+            )]),
+        };
+        let (messages_tx, messages_rx) = tokio::sync::mpsc::channel(1);
+        let (ack_tx, ack_rx) = oneshot::channel();
+        messages_tx
+            .send(ReadMessage {
+                message,
+                ack: ack_tx,
+            })
+            .await
+            .unwrap();
+        drop(messages_tx);
+
+        let _handle = writer
+            .streaming_write(ReceiverStream::new(messages_rx))
+            .await
+            .unwrap();
+        assert_eq!(ack_rx.await.unwrap(), ReadAck::Ack);
+
+        // the content type reaches the consumer both as a NATS header and within the message
+        let mut messages = consumer.fetch().max_messages(1).messages().await.unwrap();
+        let jetstream_message = messages.next().await.unwrap().unwrap();
+        let nats_content_type = jetstream_message
+            .headers
+            .as_ref()
+            .and_then(|headers| headers.get(CONTENT_TYPE_HEADER))
+            .map(|value| value.as_str().to_string());
+        assert_eq!(nats_content_type, Some("application/json".to_string()));
+
+        let message: Message = jetstream_message.payload.clone().try_into().unwrap();
+        assert_eq!(
+            message.headers.get(CONTENT_TYPE_HEADER).map(String::as_str),
+            Some("application/json")
+        );
+        jetstream_message.ack().await.unwrap();
+
+        cln_token.cancel();
+        context.delete_stream(stream_name).await.unwrap();
+    }
+
     #[cfg(feature = "nats-tests")]
     #[tokio::test]
     async fn test_publish_messages_with_cancellation() {
@@ -321,4 +1410,39 @@ mod tests {
         }
         context.delete_stream(stream_name).await.unwrap();
     }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_create_streams() {
+        let js_url = "localhost:4222";
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_create_streams";
+        let _ = context.delete_stream(stream_name).await;
+
+        // streams are not created unless asked for
+        let mut buffers = vec![BufferWriterConfig {
+            streams: vec![(stream_name.to_string(), 0)],
+            max_length: 500,
+            retention: RetentionPolicy::WorkQueue,
+            ..Default::default()
+        }];
+        create_streams(&context, &buffers).await.unwrap();
+        assert!(context.get_stream(stream_name).await.is_err());
+
+        buffers[0].auto_create_streams = true;
+        create_streams(&context, &buffers).await.unwrap();
+
+        let mut stream = context.get_stream(stream_name).await.unwrap();
+        let info = stream.info().await.unwrap();
+        assert_eq!(info.config.subjects, vec![stream_name.to_string()]);
+        assert_eq!(info.config.max_messages, 500);
+        assert_eq!(info.config.retention, stream::RetentionPolicy::WorkQueue);
+
+        // the existing streams are left as is
+        create_streams(&context, &buffers).await.unwrap();
+
+        context.delete_stream(stream_name).await.unwrap();
+    }
 }
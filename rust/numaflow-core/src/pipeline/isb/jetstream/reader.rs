@@ -1,16 +1,19 @@
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_nats::jetstream::{
-    consumer::PullConsumer, AckKind, Context, Message as JetstreamMessage,
+    consumer, consumer::PullConsumer, AckKind, Context, Message as JetstreamMessage,
 };
+use chrono::Utc;
 use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinHandle;
 use tokio::time::{self, Instant};
 use tokio_stream::wrappers::ReceiverStream;
 use tokio_stream::StreamExt;
 use tokio_util::sync::CancellationToken;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::config::pipeline::isb::BufferReaderConfig;
 use crate::config::pipeline::PipelineConfig;
@@ -19,6 +22,9 @@ use crate::message::{IntOffset, Message, Offset, ReadAck, ReadMessage};
 use crate::metrics::{
     pipeline_forward_metric_labels, pipeline_isb_metric_labels, pipeline_metrics,
 };
+use crate::pipeline::isb::jetstream::decompress_payload;
+use crate::reader::LagReader;
+use crate::source::SourceAcker;
 use crate::Result;
 
 /// The JetstreamReader is a handle to the background actor that continuously fetches messages from Jetstream.
@@ -31,6 +37,22 @@ pub(crate) struct JetstreamReader {
     partition_idx: u16,
     config: BufferReaderConfig,
     consumer: PullConsumer,
+    /// messages which are yet to be acked, used to Ack or NAK them before the final Ack is received.
+    in_flight: InFlight,
+}
+
+/// in-flight messages of the [JetstreamReader], keyed by their stream sequence. The value is the
+/// delivery count of the message (to tell apart the redeliveries) and the sender to Ack or NAK the
+/// message.
+type InFlight = Arc<Mutex<HashMap<u64, (i64, oneshot::Sender<InFlightAck>)>>>;
+
+/// InFlightAck is the final acknowledgement of an in-flight message, sent through
+/// [JetstreamReader::ack] or [JetstreamReader::nack].
+#[derive(Debug)]
+enum InFlightAck {
+    Ack,
+    /// NAK with an optional delay after which the message is redelivered.
+    Nak(Option<Duration>),
 }
 
 impl JetstreamReader {
@@ -42,18 +64,64 @@ impl JetstreamReader {
     ) -> Result<Self> {
         let mut config = config;
 
-        let mut consumer: PullConsumer = js_ctx
-            .get_consumer_from_stream(&stream_name, &stream_name)
+        let stream = js_ctx
+            .get_stream(stream_name)
+            .await
+            .map_err(|e| Error::ISB(format!("Failed to get stream {}", e)))?;
+
+        // The consumer is usually created by the controller, in which case the existing one is
+        // returned and updated below if its settings differ from the configured ones.
+        let filter_subject = config.filter_subject(stream_name).unwrap_or_default();
+        let mut consumer: PullConsumer = stream
+            .get_or_create_consumer(
+                stream_name,
+                consumer::pull::Config {
+                    durable_name: Some(stream_name.to_string()),
+                    ack_policy: consumer::AckPolicy::Explicit,
+                    ack_wait: config.ack_wait,
+                    max_ack_pending: config.max_ack_pending as i64,
+                    // only the subject filter (or the prefixed subject) is consumed if set
+                    filter_subject: filter_subject.clone(),
+                    ..Default::default()
+                },
+            )
             .await
             .map_err(|e| Error::ISB(format!("Failed to get consumer for stream {}", e)))?;
 
-        let consumer_info = consumer
+        let mut consumer_config = consumer
             .info()
             .await
-            .map_err(|e| Error::ISB(format!("Failed to get consumer info {}", e)))?;
+            .map_err(|e| Error::ISB(format!("Failed to get consumer info {}", e)))?
+            .config
+            .clone();
+
+        // the settings of an existing consumer would otherwise be silently ignored.
+        if consumer_config.ack_wait != config.ack_wait
+            || consumer_config.max_ack_pending != config.max_ack_pending as i64
+            || consumer_config.filter_subject != filter_subject
+        {
+            warn!(
+                stream_name,
+                ack_wait = ?consumer_config.ack_wait,
+                max_ack_pending = consumer_config.max_ack_pending,
+                filter_subject = consumer_config.filter_subject,
+                "Consumer settings differ from the reader config, updating the consumer"
+            );
+            consumer_config.ack_wait = config.ack_wait;
+            consumer_config.max_ack_pending = config.max_ack_pending as i64;
+            consumer_config.filter_subject = filter_subject;
+            stream
+                .create_consumer(consumer_config)
+                .await
+                .map_err(|e| Error::ISB(format!("Failed to update consumer {}", e)))?;
+            consumer = stream
+                .get_consumer(stream_name)
+                .await
+                .map_err(|e| Error::ISB(format!("Failed to get consumer for stream {}", e)))?;
+        }
 
         // Calculate inProgressTickSeconds based on the ack_wait_seconds.
-        let ack_wait_seconds = consumer_info.config.ack_wait.as_secs();
+        let ack_wait_seconds = config.ack_wait.as_secs();
         let wip_ack_interval = Duration::from_secs(std::cmp::max(
             config.wip_ack_interval.as_secs(),
             ack_wait_seconds * 2 / 3,
@@ -65,9 +133,75 @@ impl JetstreamReader {
             partition_idx,
             config: config.clone(),
             consumer,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
         })
     }
 
+    /// Acks the in-flight messages at the given offsets without waiting for their [ReadAck].
+    /// Offsets which are no longer in-flight (already Ack'd or NAK'd, e.g., an overlapping ack)
+    /// are skipped, so that a message is acked only once. The number of messages which were acked
+    /// is returned. None of the offsets are acked if any of them is invalid.
+    pub(crate) async fn ack(&self, offsets: Vec<Offset>) -> Result<usize> {
+        let sequences = self.sequences(offsets)?;
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let mut acked = 0;
+        for sequence in sequences {
+            let Some((_, done_tx)) = in_flight.remove(&sequence) else {
+                continue;
+            };
+            if done_tx.send(InFlightAck::Ack).is_ok() {
+                acked += 1;
+            }
+        }
+        Ok(acked)
+    }
+
+    /// NAKs the in-flight messages at the given offsets so that they are redelivered after the
+    /// delay (or immediately if there is no delay) instead of waiting for the AckWait to expire.
+    /// Offsets which are no longer in-flight (already Ack'd or NAK'd) are ignored. None of the
+    /// offsets are NAK'd if any of them is invalid.
+    pub(crate) async fn nack(&self, offsets: Vec<Offset>, delay: Option<Duration>) -> Result<()> {
+        let sequences = self.sequences(offsets)?;
+        let mut in_flight = self.in_flight.lock().unwrap();
+        for sequence in sequences {
+            if let Some((_, done_tx)) = in_flight.remove(&sequence) {
+                // the message could have been Ack'd concurrently, in which case there is nothing
+                // to be NAK'd.
+                let _ = done_tx.send(InFlightAck::Nak(delay));
+            }
+        }
+        Ok(())
+    }
+
+    /// returns the stream sequences of the offsets, an error is returned if any of them is invalid.
+    fn sequences(&self, offsets: Vec<Offset>) -> Result<Vec<u64>> {
+        offsets
+            .into_iter()
+            .map(|offset| self.sequence(offset))
+            .collect()
+    }
+
+    /// returns the stream sequence of the offset, only the int offsets of the reader's partition
+    /// are valid.
+    fn sequence(&self, offset: Offset) -> Result<u64> {
+        let Offset::Int(IntOffset {
+            offset: sequence,
+            partition_idx,
+        }) = offset
+        else {
+            return Err(Error::ISB(format!(
+                "Invalid offset {offset} for Jetstream, expected an int offset"
+            )));
+        };
+        if partition_idx != self.partition_idx {
+            return Err(Error::ISB(format!(
+                "Offset {sequence} belongs to partition {partition_idx}, reader is for partition {}",
+                self.partition_idx
+            )));
+        }
+        Ok(sequence)
+    }
+
     /// streaming_read is a background task that continuously fetches messages from Jetstream and
     /// emits them on a channel. When we encounter an error, we log the error and return from the
     /// function. This drops the sender end of the channel. The closing of the channel should propagate
@@ -86,6 +220,17 @@ impl JetstreamReader {
             let partition_idx = self.partition_idx;
             let config = self.config.clone();
             let cancel_token = cancel_token.clone();
+            let in_flight = Arc::clone(&self.in_flight);
+            // the final acks are sent in batches by a background task if batching is enabled
+            let ack_batcher = (config.ack_batch_size > 1).then(|| {
+                let (ack_batch_tx, ack_batch_rx) = mpsc::channel(config.ack_batch_size);
+                tokio::spawn(Self::batch_acks(
+                    ack_batch_rx,
+                    config.ack_batch_size,
+                    config.ack_batch_interval,
+                ));
+                ack_batch_tx
+            });
 
             let stream_name = self.stream_name;
             async move {
@@ -128,7 +273,17 @@ impl JetstreamReader {
                                 }
                             };
 
-                            let mut message: Message = match jetstream_message.payload.clone().try_into() {
+                            let payload = match decompress_payload(
+                                jetstream_message.headers.as_ref(),
+                                jetstream_message.payload.clone(),
+                            ) {
+                                Ok(payload) => payload,
+                                Err(e) => {
+                                    error!(?e, ?stream_name, "Failed to decompress the payload received from Jetstream");
+                                    continue;
+                                }
+                            };
+                            let mut message: Message = match payload.try_into() {
                                 Ok(message) => message,
                                 Err(e) => {
                                     error!(
@@ -139,16 +294,46 @@ impl JetstreamReader {
                                 }
                             };
 
+                            // the filtered out messages are acked right away, so that they are not redelivered
+                            if let Some(header_filter) = config.header_filter.as_ref() {
+                                if !header_filter.matches(&message.headers) {
+                                    if let Err(e) = jetstream_message.ack().await {
+                                        error!(?e, ?stream_name, "Failed to Ack the filtered out message");
+                                    }
+                                    continue;
+                                }
+                            }
+
+                            // the expired messages are dropped the same way as the filtered out ones
+                            if config.enforce_ttl && message.is_expired(Utc::now()) {
+                                if let Err(e) = jetstream_message.ack().await {
+                                    error!(?e, ?stream_name, "Failed to Ack the expired message");
+                                }
+                                continue;
+                            }
+
                             message.offset = Some(Offset::Int(IntOffset::new(
                                 msg_info.stream_sequence,
                                 partition_idx,
                             )));
 
                             let (ack_tx, ack_rx) = oneshot::channel();
+                            let (done_tx, done_rx) = oneshot::channel();
+                            in_flight
+                                .lock()
+                                .unwrap()
+                                .insert(msg_info.stream_sequence, (msg_info.delivered, done_tx));
                             tokio::spawn(Self::start_work_in_progress(
                                 jetstream_message,
                                 ack_rx,
+                                done_rx,
                                 config.wip_ack_interval,
+                                ack_batcher.clone(),
+                                InFlightGuard {
+                                    in_flight: Arc::clone(&in_flight),
+                                    sequence: msg_info.stream_sequence,
+                                    delivered: msg_info.delivered,
+                                },
                             ));
 
                             let read_message = ReadMessage {
@@ -187,10 +372,19 @@ impl JetstreamReader {
     // Intended to be run as background task which will continuously send InProgress acks to Jetstream.
     // We will continuously retry if there is an error in acknowledging the message as work-in-progress.
     // If the sender end of the ack_rx channel was dropped before sending a final Ack or Nak (due to some unhandled/unknown failure), we will send a Nak to Jetstream.
+    // The message can also be Ack'd or NAK'd (with a delay) through the done_rx channel, see [JetstreamReader::ack]
+    // and [JetstreamReader::nack].
+    // If the acks are batched, the message is handed over to the ack_batcher instead of being acked right away, along
+    // with the time it was read at so that the ack metrics are recorded once it is acked.
+    #[allow(clippy::too_many_arguments)]
     async fn start_work_in_progress(
         msg: JetstreamMessage,
         mut ack_rx: oneshot::Receiver<ReadAck>,
+        mut done_rx: oneshot::Receiver<InFlightAck>,
         tick: Duration,
+        ack_batcher: Option<mpsc::Sender<(JetstreamMessage, Instant)>>,
+        // the message is no longer in-flight once the final Ack or Nak has been sent.
+        in_flight_guard: InFlightGuard,
     ) {
         let mut interval = time::interval_at(Instant::now() + tick, tick);
         let start = Instant::now();
@@ -208,6 +402,16 @@ impl JetstreamReader {
 
             let ack = tokio::select! {
                 ack = &mut ack_rx => ack,
+                Ok(done) = &mut done_rx => match done {
+                    InFlightAck::Ack => Ok(ReadAck::Ack),
+                    InFlightAck::Nak(delay) => {
+                        let ack_result = msg.ack_with(AckKind::Nak(delay)).await;
+                        if let Err(e) = ack_result {
+                            error!(?e, "Failed to send Nak to Jetstream for message");
+                        }
+                        return;
+                    }
+                },
                 _ = wip => continue,
             };
 
@@ -218,21 +422,20 @@ impl JetstreamReader {
 
             match ack {
                 ReadAck::Ack => {
-                    let ack_result = msg.ack().await;
-                    if let Err(e) = ack_result {
-                        error!(?e, "Failed to send Ack to Jetstream for message");
+                    match ack_batcher {
+                        Some(ack_batcher) => {
+                            if ack_batcher.send((msg, start)).await.is_err() {
+                                error!("Failed to hand over the message to the ack batcher");
+                            }
+                        }
+                        None => {
+                            let ack_result = msg.ack().await;
+                            if let Err(e) = ack_result {
+                                error!(?e, "Failed to send Ack to Jetstream for message");
+                            }
+                            Self::record_ack(start);
+                        }
                     }
-                    pipeline_metrics()
-                        .forwarder
-                        .ack_time
-                        .get_or_create(pipeline_isb_metric_labels())
-                        .observe(start.elapsed().as_micros() as f64);
-
-                    pipeline_metrics()
-                        .forwarder
-                        .ack_total
-                        .get_or_create(pipeline_isb_metric_labels())
-                        .inc();
                     return;
                 }
                 ReadAck::Nak => {
@@ -245,6 +448,73 @@ impl JetstreamReader {
             }
         }
     }
+
+    // Intended to be run as background task which acks the messages in batches, a batch is acked once it is full or
+    // its oldest message has waited for the interval. The pending batch is acked once all the senders are dropped.
+    async fn batch_acks(
+        mut ack_batch_rx: mpsc::Receiver<(JetstreamMessage, Instant)>,
+        size: usize,
+        interval: Duration,
+    ) {
+        let mut batch = AckBatch::new(size, interval);
+        loop {
+            let deadline = batch.deadline();
+            let messages = tokio::select! {
+                message = ack_batch_rx.recv() => match message {
+                    Some(message) => batch.push(message, Instant::now()),
+                    None => {
+                        Self::ack_all(batch.take()).await;
+                        return;
+                    }
+                },
+                _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    batch.take_due(Instant::now())
+                }
+            };
+            if let Some(messages) = messages {
+                Self::ack_all(messages).await;
+            }
+        }
+    }
+
+    /// acks all the messages together, the acks are published one after the other without
+    /// waiting for the server and flushed once at the end. JetStream has no batch ack for the
+    /// explicitly acked messages (acking the last sequence with `AckPolicy::All` would also ack the
+    /// messages which are still being processed), hence each message still gets an ack of its own.
+    /// What the batching buys is a single flush (one round trip to the server) per batch instead of
+    /// one per message, at the cost of the acks being held back for up to `ack_batch_interval`.
+    async fn ack_all(messages: Vec<(JetstreamMessage, Instant)>) {
+        let Some((first, _)) = messages.first() else {
+            return;
+        };
+        let client = first.context.client();
+        for (msg, _) in &messages {
+            if let Err(e) = msg.ack_with(AckKind::Ack).await {
+                error!(?e, "Failed to send Ack to Jetstream for message");
+            }
+        }
+        if let Err(e) = client.flush().await {
+            error!(?e, "Failed to flush the Acks to Jetstream");
+        }
+        for (_, start) in messages {
+            Self::record_ack(start);
+        }
+    }
+
+    /// records the ack of a message which was read at `start`.
+    fn record_ack(start: Instant) {
+        pipeline_metrics()
+            .forwarder
+            .ack_time
+            .get_or_create(pipeline_isb_metric_labels())
+            .observe(start.elapsed().as_micros() as f64);
+
+        pipeline_metrics()
+            .forwarder
+            .ack_total
+            .get_or_create(pipeline_isb_metric_labels())
+            .inc();
+    }
 }
 
 impl fmt::Display for JetstreamReader {
@@ -257,19 +527,239 @@ impl fmt::Display for JetstreamReader {
     }
 }
 
+/// The in-flight messages can be acked or NAK'd by their offsets through the [SourceAcker], see
+/// [JetstreamReader::ack] and [JetstreamReader::nack].
+impl SourceAcker for JetstreamReader {
+    async fn ack(&mut self, offsets: Vec<Offset>) -> Result<()> {
+        JetstreamReader::ack(self, offsets).await.map(|_| ())
+    }
+
+    async fn nack(&mut self, offsets: Vec<Offset>, delay: Option<Duration>) -> Result<()> {
+        JetstreamReader::nack(self, offsets, delay).await
+    }
+}
+
+/// InFlightGuard removes the message from the in-flight messages when dropped, unless it has
+/// already been redelivered.
+struct InFlightGuard {
+    in_flight: InFlight,
+    sequence: u64,
+    delivered: i64,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight
+            .get(&self.sequence)
+            .is_some_and(|(delivered, _)| *delivered == self.delivered)
+        {
+            in_flight.remove(&self.sequence);
+        }
+    }
+}
+
+/// AckBatch accumulates the messages to be acked, so that they can be acked together once the batch
+/// is full or its oldest message has waited for the interval.
+struct AckBatch<T> {
+    messages: Vec<T>,
+    size: usize,
+    interval: Duration,
+    /// time at which the oldest message of the batch was added.
+    oldest: Option<Instant>,
+}
+
+impl<T> AckBatch<T> {
+    fn new(size: usize, interval: Duration) -> Self {
+        Self {
+            messages: Vec::with_capacity(size),
+            size: size.max(1),
+            interval,
+            oldest: None,
+        }
+    }
+
+    /// adds the message to the batch, the batch is returned if it is full.
+    fn push(&mut self, message: T, now: Instant) -> Option<Vec<T>> {
+        self.oldest.get_or_insert(now);
+        self.messages.push(message);
+        (self.messages.len() >= self.size).then(|| self.take())
+    }
+
+    /// returns the time by which the batch has to be acked, if it is not empty.
+    fn deadline(&self) -> Option<Instant> {
+        self.oldest.map(|oldest| oldest + self.interval)
+    }
+
+    /// returns the batch if its oldest message has waited for the interval.
+    fn take_due(&mut self, now: Instant) -> Option<Vec<T>> {
+        let deadline = self.deadline()?;
+        (now >= deadline).then(|| self.take())
+    }
+
+    /// empties the batch.
+    fn take(&mut self) -> Vec<T> {
+        self.oldest = None;
+        std::mem::replace(&mut self.messages, Vec::with_capacity(self.size))
+    }
+}
+
+/// ConsumerPending reports the number of messages pending for the consumer of a stream, i.e., the
+/// ones yet to be delivered along with the ones yet to be acked.
+#[trait_variant::make(ConsumerPending: Send)]
+#[allow(dead_code)]
+pub(crate) trait LocalConsumerPending {
+    async fn consumer_pending(&self, stream_name: &'static str) -> Result<usize>;
+}
+
+impl ConsumerPending for Context {
+    async fn consumer_pending(&self, stream_name: &'static str) -> Result<usize> {
+        // the durable consumer is named after the stream, see [JetstreamReader::new]
+        let mut consumer: PullConsumer = self
+            .get_consumer_from_stream(stream_name, stream_name)
+            .await
+            .map_err(|e| Error::ISB(format!("Failed to get consumer for stream {}", e)))?;
+        let consumer_info = consumer
+            .info()
+            .await
+            .map_err(|e| Error::ISB(format!("Failed to get consumer info {}", e)))?;
+        Ok(consumer_info.num_pending as usize + consumer_info.num_ack_pending)
+    }
+}
+
+/// JetstreamLagReader reports the pending messages summed across all the partitions (streams) of
+/// the buffer being read, to feed the auto-scaler.
+#[derive(Clone)]
+pub(crate) struct JetstreamLagReader<C = Context> {
+    streams: Vec<&'static str>,
+    consumers: C,
+}
+
+impl<C> JetstreamLagReader<C> {
+    pub(crate) fn new(consumers: C, config: &BufferReaderConfig) -> Self {
+        Self {
+            streams: config.streams.iter().map(|(stream, _)| *stream).collect(),
+            consumers,
+        }
+    }
+}
+
+impl<C: ConsumerPending + Sync> LagReader for JetstreamLagReader<C> {
+    async fn pending(&mut self) -> Result<Option<usize>> {
+        let pending = futures::future::try_join_all(
+            self.streams
+                .iter()
+                .map(|stream| self.consumers.consumer_pending(*stream)),
+        )
+        .await?;
+        Ok(Some(pending.into_iter().sum()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
     use async_nats::jetstream;
-    use async_nats::jetstream::{consumer, stream};
+    use async_nats::jetstream::stream;
+    use async_nats::HeaderMap;
     use bytes::BytesMut;
     use chrono::Utc;
 
     use super::*;
+    use crate::config::components::source::Compression;
+    use crate::config::pipeline::isb::{BufferWriterConfig, HeaderFilter};
     use crate::message::ReadAck::Ack;
-    use crate::message::{Message, MessageID};
-    use crate::pipeline::isb::jetstream::writer::JetstreamWriter;
+    use crate::message::{Message, MessageID, StringOffset, EXPIRES_AT_HEADER};
+    use crate::pipeline::isb::jetstream::writer::{JetstreamWriter, WriteResult};
+
+    /// MockConsumers is the pending count of the consumer of each of the streams.
+    struct MockConsumers(HashMap<&'static str, usize>);
+
+    impl ConsumerPending for MockConsumers {
+        async fn consumer_pending(&self, stream_name: &'static str) -> Result<usize> {
+            self.0
+                .get(stream_name)
+                .copied()
+                .ok_or_else(|| Error::ISB(format!("No consumer for stream {stream_name}")))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jetstream_lag_reader() {
+        let config = BufferReaderConfig {
+            partitions: 3,
+            streams: vec![("buffer-0", 0), ("buffer-1", 1), ("buffer-2", 2)],
+            ..Default::default()
+        };
+
+        let consumers = MockConsumers(HashMap::from([
+            ("buffer-0", 10),
+            ("buffer-1", 0),
+            ("buffer-2", 32),
+        ]));
+        let mut lag_reader = JetstreamLagReader::new(consumers, &config);
+        assert_eq!(lag_reader.pending().await.unwrap(), Some(42));
+
+        // pending is not available if any of the partitions cannot be queried
+        let consumers = MockConsumers(HashMap::from([("buffer-0", 10), ("buffer-2", 32)]));
+        let mut lag_reader = JetstreamLagReader::new(consumers, &config);
+        assert!(matches!(lag_reader.pending().await, Err(Error::ISB(_))));
+    }
+
+    /// config of the `out` vertex of a simple pipeline, used by the tests which read from
+    /// Jetstream.
+    #[cfg(feature = "nats-tests")]
+    const PIPELINE_CFG_BASE64: &str = "eyJtZXRhZGF0YSI6eyJuYW1lIjoic2ltcGxlLXBpcGVsaW5lLW91dCIsIm5hbWVzcGFjZSI6ImRlZmF1bHQiLCJjcmVhdGlvblRpbWVzdGFtcCI6bnVsbH0sInNwZWMiOnsibmFtZSI6Im91dCIsInNpbmsiOnsiYmxhY2tob2xlIjp7fSwicmV0cnlTdHJhdGVneSI6eyJvbkZhaWx1cmUiOiJyZXRyeSJ9fSwibGltaXRzIjp7InJlYWRCYXRjaFNpemUiOjUwMCwicmVhZFRpbWVvdXQiOiIxcyIsImJ1ZmZlck1heExlbmd0aCI6MzAwMDAsImJ1ZmZlclVzYWdlTGltaXQiOjgwfSwic2NhbGUiOnsibWluIjoxfSwidXBkYXRlU3RyYXRlZ3kiOnsidHlwZSI6IlJvbGxpbmdVcGRhdGUiLCJyb2xsaW5nVXBkYXRlIjp7Im1heFVuYXZhaWxhYmxlIjoiMjUlIn19LCJwaXBlbGluZU5hbWUiOiJzaW1wbGUtcGlwZWxpbmUiLCJpbnRlclN0ZXBCdWZmZXJTZXJ2aWNlTmFtZSI6IiIsInJlcGxpY2FzIjowLCJmcm9tRWRnZXMiOlt7ImZyb20iOiJpbiIsInRvIjoib3V0IiwiY29uZGl0aW9ucyI6bnVsbCwiZnJvbVZlcnRleFR5cGUiOiJTb3VyY2UiLCJmcm9tVmVydGV4UGFydGl0aW9uQ291bnQiOjEsImZyb21WZXJ0ZXhMaW1pdHMiOnsicmVhZEJhdGNoU2l6ZSI6NTAwLCJyZWFkVGltZW91dCI6IjFzIiwiYnVmZmVyTWF4TGVuZ3RoIjozMDAwMCwiYnVmZmVyVXNhZ2VMaW1pdCI6ODB9LCJ0b1ZlcnRleFR5cGUiOiJTaW5rIiwidG9WZXJ0ZXhQYXJ0aXRpb25Db3VudCI6MSwidG9WZXJ0ZXhMaW1pdHMiOnsicmVhZEJhdGNoU2l6ZSI6NTAwLCJyZWFkVGltZW91dCI6IjFzIiwiYnVmZmVyTWF4TGVuZ3RoIjozMDAwMCwiYnVmZmVyVXNhZ2VMaW1pdCI6ODB9fV0sIndhdGVybWFyayI6eyJtYXhEZWxheSI6IjBzIn19LCJzdGF0dXMiOnsicGhhc2UiOiIiLCJyZXBsaWNhcyI6MCwiZGVzaXJlZFJlcGxpY2FzIjowLCJsYXN0U2NhbGVkQXQiOm51bGx9fQ==";
+
+    /// creates the stream (and its consumer) of the given name and starts reading from it with a
+    /// [JetstreamReader] of the given config. The reader is returned along with the stream of the
+    /// read messages and the handle of the read task.
+    #[cfg(feature = "nats-tests")]
+    async fn start_reader(
+        context: &Context,
+        stream_name: &'static str,
+        buf_reader_config: BufferReaderConfig,
+        cancel_token: CancellationToken,
+    ) -> (
+        JetstreamReader,
+        ReceiverStream<ReadMessage>,
+        JoinHandle<Result<()>>,
+    ) {
+        context
+            .get_or_create_stream(stream::Config {
+                name: stream_name.into(),
+                subjects: vec![stream_name.into()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let _consumer = context
+            .create_consumer_on_stream(
+                consumer::Config {
+                    name: Some(stream_name.to_string()),
+                    ack_policy: consumer::AckPolicy::Explicit,
+                    ..Default::default()
+                },
+                stream_name,
+            )
+            .await
+            .unwrap();
+
+        let js_reader = JetstreamReader::new(stream_name, 0, context.clone(), buf_reader_config)
+            .await
+            .unwrap();
+
+        let env_vars = [("NUMAFLOW_ISBSVC_JETSTREAM_URL", "localhost:4222")];
+        let pipeline_config =
+            PipelineConfig::load(PIPELINE_CFG_BASE64.to_string(), env_vars).unwrap();
+        let (js_reader_rx, js_reader_task) = js_reader
+            .streaming_read(cancel_token, &pipeline_config)
+            .await
+            .unwrap();
+        (js_reader, js_reader_rx, js_reader_task)
+    }
 
     #[cfg(feature = "nats-tests")]
     #[tokio::test]
@@ -306,6 +796,7 @@ mod tests {
             partitions: 0,
             streams: vec![],
             wip_ack_interval: Duration::from_millis(5),
+            ..Default::default()
         };
         let js_reader = JetstreamReader::new(stream_name, 0, context.clone(), buf_reader_config)
             .await
@@ -343,11 +834,18 @@ mod tests {
                 headers: HashMap::new(),
             };
             let message_bytes: BytesMut = message.try_into().unwrap();
-            writer
-                .write((stream_name.to_string(), 0), message_bytes.into())
-                .await
+            let WriteResult::Published(paf) = writer
+                .write(
+                    (stream_name.to_string(), 0),
+                    message_bytes.into(),
+                    HeaderMap::new(),
+                )
                 .await
-                .unwrap();
+                .unwrap()
+            else {
+                panic!("message should not be discarded");
+            };
+            paf.await.unwrap();
         }
 
         // Cancel the token to exit the retry loop
@@ -375,53 +873,31 @@ mod tests {
 
     #[cfg(feature = "nats-tests")]
     #[tokio::test]
-    async fn test_jetstream_ack() {
+    async fn test_jetstream_read_with_header_filter() {
         let js_url = "localhost:4222";
         // Create JetStream context
         let client = async_nats::connect(js_url).await.unwrap();
         let context = jetstream::new(client);
 
-        let stream_name = "test_ack";
-        context
-            .get_or_create_stream(stream::Config {
-                name: stream_name.into(),
-                subjects: vec![stream_name.into()],
-                max_message_size: 1024,
-                ..Default::default()
-            })
-            .await
-            .unwrap();
-
-        let _consumer = context
-            .create_consumer_on_stream(
-                consumer::Config {
-                    name: Some(stream_name.to_string()),
-                    ack_policy: consumer::AckPolicy::Explicit,
-                    ..Default::default()
-                },
-                stream_name,
-            )
-            .await
-            .unwrap();
-
+        let stream_name = "test_jetstream_read_with_header_filter";
         let buf_reader_config = BufferReaderConfig {
             partitions: 0,
             streams: vec![],
             wip_ack_interval: Duration::from_millis(5),
+            header_filter: Some(HeaderFilter::Equals {
+                key: "replay".to_string(),
+                value: "true".to_string(),
+            }),
+            ..Default::default()
         };
-        let js_reader = JetstreamReader::new(stream_name, 0, context.clone(), buf_reader_config)
-            .await
-            .unwrap();
-
-        let pipeline_cfg_base64 = "eyJtZXRhZGF0YSI6eyJuYW1lIjoic2ltcGxlLXBpcGVsaW5lLW91dCIsIm5hbWVzcGFjZSI6ImRlZmF1bHQiLCJjcmVhdGlvblRpbWVzdGFtcCI6bnVsbH0sInNwZWMiOnsibmFtZSI6Im91dCIsInNpbmsiOnsiYmxhY2tob2xlIjp7fSwicmV0cnlTdHJhdGVneSI6eyJvbkZhaWx1cmUiOiJyZXRyeSJ9fSwibGltaXRzIjp7InJlYWRCYXRjaFNpemUiOjUwMCwicmVhZFRpbWVvdXQiOiIxcyIsImJ1ZmZlck1heExlbmd0aCI6MzAwMDAsImJ1ZmZlclVzYWdlTGltaXQiOjgwfSwic2NhbGUiOnsibWluIjoxfSwidXBkYXRlU3RyYXRlZ3kiOnsidHlwZSI6IlJvbGxpbmdVcGRhdGUiLCJyb2xsaW5nVXBkYXRlIjp7Im1heFVuYXZhaWxhYmxlIjoiMjUlIn19LCJwaXBlbGluZU5hbWUiOiJzaW1wbGUtcGlwZWxpbmUiLCJpbnRlclN0ZXBCdWZmZXJTZXJ2aWNlTmFtZSI6IiIsInJlcGxpY2FzIjowLCJmcm9tRWRnZXMiOlt7ImZyb20iOiJpbiIsInRvIjoib3V0IiwiY29uZGl0aW9ucyI6bnVsbCwiZnJvbVZlcnRleFR5cGUiOiJTb3VyY2UiLCJmcm9tVmVydGV4UGFydGl0aW9uQ291bnQiOjEsImZyb21WZXJ0ZXhMaW1pdHMiOnsicmVhZEJhdGNoU2l6ZSI6NTAwLCJyZWFkVGltZW91dCI6IjFzIiwiYnVmZmVyTWF4TGVuZ3RoIjozMDAwMCwiYnVmZmVyVXNhZ2VMaW1pdCI6ODB9LCJ0b1ZlcnRleFR5cGUiOiJTaW5rIiwidG9WZXJ0ZXhQYXJ0aXRpb25Db3VudCI6MSwidG9WZXJ0ZXhMaW1pdHMiOnsicmVhZEJhdGNoU2l6ZSI6NTAwLCJyZWFkVGltZW91dCI6IjFzIiwiYnVmZmVyTWF4TGVuZ3RoIjozMDAwMCwiYnVmZmVyVXNhZ2VMaW1pdCI6ODB9fV0sIndhdGVybWFyayI6eyJtYXhEZWxheSI6IjBzIn19LCJzdGF0dXMiOnsicGhhc2UiOiIiLCJyZXBsaWNhcyI6MCwiZGVzaXJlZFJlcGxpY2FzIjowLCJsYXN0U2NhbGVkQXQiOm51bGx9fQ==".to_string();
-
-        let env_vars = [("NUMAFLOW_ISBSVC_JETSTREAM_URL", "localhost:4222")];
-        let pipeline_config = PipelineConfig::load(pipeline_cfg_base64, env_vars).unwrap();
         let reader_cancel_token = CancellationToken::new();
-        let (mut js_reader_rx, js_reader_task) = js_reader
-            .streaming_read(reader_cancel_token.clone(), &pipeline_config)
-            .await
-            .unwrap();
+        let (_, mut js_reader_rx, js_reader_task) = start_reader(
+            &context,
+            stream_name,
+            buf_reader_config,
+            reader_cancel_token.clone(),
+        )
+        .await;
 
         let writer_cancel_token = CancellationToken::new();
         let writer = JetstreamWriter::new(
@@ -431,8 +907,13 @@ mod tests {
             writer_cancel_token.clone(),
         );
 
-        // write 5 messages
-        for i in 0..5 {
+        // every third message is to be replayed, the rest either have a different value or no header
+        for i in 0..9 {
+            let headers = match i % 3 {
+                0 => HashMap::from([("replay".to_string(), "true".to_string())]),
+                1 => HashMap::from([("replay".to_string(), "false".to_string())]),
+                _ => HashMap::new(),
+            };
             let message = Message {
                 keys: vec![format!("key_{}", i)],
                 value: format!("message {}", i).as_bytes().to_vec().into(),
@@ -443,38 +924,783 @@ mod tests {
                     offset: format!("offset_{}", i),
                     index: i,
                 },
-                headers: HashMap::new(),
+                headers,
             };
             let message_bytes: BytesMut = message.try_into().unwrap();
-            writer
-                .write((stream_name.to_string(), 0), message_bytes.into())
+            let WriteResult::Published(paf) = writer
+                .write(
+                    (stream_name.to_string(), 0),
+                    message_bytes.into(),
+                    HeaderMap::new(),
+                )
                 .await
-                .await
-                .unwrap();
+                .unwrap()
+            else {
+                panic!("message should not be discarded");
+            };
+            paf.await.unwrap();
         }
-        // Cancel the token to exit the retry loop
         writer_cancel_token.cancel();
 
-        for _ in 0..5 {
+        // only the matching messages are delivered
+        let mut buffer = vec![];
+        for _ in 0..3 {
             let Some(val) = js_reader_rx.next().await else {
                 break;
             };
-            val.ack.send(Ack).unwrap()
+            buffer.push(val);
         }
+        let values: Vec<_> = buffer
+            .iter()
+            .map(|read_message| read_message.message.value.clone())
+            .collect();
+        assert_eq!(
+            values,
+            vec!["message 0", "message 3", "message 6"]
+                .into_iter()
+                .map(|value| bytes::Bytes::from(value.as_bytes()))
+                .collect::<Vec<_>>()
+        );
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), js_reader_rx.next())
+                .await
+                .is_err(),
+            "the filtered out messages should not be delivered"
+        );
 
+        // the filtered out messages are acked, only the delivered ones are pending an ack
         let mut consumer: PullConsumer = context
             .get_consumer_from_stream(stream_name, stream_name)
             .await
             .unwrap();
-
         let consumer_info = consumer.info().await.unwrap();
-
         assert_eq!(consumer_info.num_pending, 0);
-        assert_eq!(consumer_info.num_ack_pending, 0);
+        assert_eq!(consumer_info.num_ack_pending, 3);
 
         reader_cancel_token.cancel();
         js_reader_task.await.unwrap().unwrap();
 
         context.delete_stream(stream_name).await.unwrap();
     }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_jetstream_read_with_ttl() {
+        let js_url = "localhost:4222";
+        // Create JetStream context
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_jetstream_read_with_ttl";
+        let buf_reader_config = BufferReaderConfig {
+            partitions: 0,
+            streams: vec![],
+            wip_ack_interval: Duration::from_millis(5),
+            enforce_ttl: true,
+            ..Default::default()
+        };
+        let reader_cancel_token = CancellationToken::new();
+        let (_, mut js_reader_rx, js_reader_task) = start_reader(
+            &context,
+            stream_name,
+            buf_reader_config,
+            reader_cancel_token.clone(),
+        )
+        .await;
+
+        let writer_cancel_token = CancellationToken::new();
+        let writer = JetstreamWriter::new(
+            vec![(stream_name.to_string(), 0)],
+            Default::default(),
+            context.clone(),
+            writer_cancel_token.clone(),
+        );
+
+        // the odd messages have expired, the even ones are yet to expire
+        for i in 0..6 {
+            let expires_at = if i % 2 == 0 {
+                Utc::now() + chrono::Duration::minutes(10)
+            } else {
+                Utc::now() - chrono::Duration::minutes(10)
+            };
+            let headers = HashMap::from([(
+                EXPIRES_AT_HEADER.to_string(),
+                expires_at.timestamp_millis().to_string(),
+            )]);
+            let message = Message {
+                keys: vec![format!("key_{}", i)],
+                value: format!("message {}", i).as_bytes().to_vec().into(),
+                offset: None,
+                event_time: Utc::now(),
+                id: MessageID {
+                    vertex_name: "vertex".to_string(),
+                    offset: format!("offset_{}", i),
+                    index: i,
+                },
+                headers,
+            };
+            let message_bytes: BytesMut = message.try_into().unwrap();
+            let WriteResult::Published(paf) = writer
+                .write(
+                    (stream_name.to_string(), 0),
+                    message_bytes.into(),
+                    HeaderMap::new(),
+                )
+                .await
+                .unwrap()
+            else {
+                panic!("message should not be discarded");
+            };
+            paf.await.unwrap();
+        }
+        writer_cancel_token.cancel();
+
+        // only the live messages are delivered
+        let mut buffer = vec![];
+        for _ in 0..3 {
+            let Some(val) = js_reader_rx.next().await else {
+                break;
+            };
+            buffer.push(val);
+        }
+        let values: Vec<_> = buffer
+            .iter()
+            .map(|read_message| read_message.message.value.clone())
+            .collect();
+        assert_eq!(
+            values,
+            vec!["message 0", "message 2", "message 4"]
+                .into_iter()
+                .map(|value| bytes::Bytes::from(value.as_bytes()))
+                .collect::<Vec<_>>()
+        );
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), js_reader_rx.next())
+                .await
+                .is_err(),
+            "the expired messages should not be delivered"
+        );
+
+        // the expired messages are acked, only the delivered ones are pending an ack
+        let mut consumer: PullConsumer = context
+            .get_consumer_from_stream(stream_name, stream_name)
+            .await
+            .unwrap();
+        let consumer_info = consumer.info().await.unwrap();
+        assert_eq!(consumer_info.num_pending, 0);
+        assert_eq!(consumer_info.num_ack_pending, 3);
+
+        reader_cancel_token.cancel();
+        js_reader_task.await.unwrap().unwrap();
+
+        context.delete_stream(stream_name).await.unwrap();
+    }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_jetstream_ack() {
+        let js_url = "localhost:4222";
+        // Create JetStream context
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_ack";
+        context
+            .get_or_create_stream(stream::Config {
+                name: stream_name.into(),
+                subjects: vec![stream_name.into()],
+                max_message_size: 1024,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let _consumer = context
+            .create_consumer_on_stream(
+                consumer::Config {
+                    name: Some(stream_name.to_string()),
+                    ack_policy: consumer::AckPolicy::Explicit,
+                    ..Default::default()
+                },
+                stream_name,
+            )
+            .await
+            .unwrap();
+
+        let buf_reader_config = BufferReaderConfig {
+            partitions: 0,
+            streams: vec![],
+            wip_ack_interval: Duration::from_millis(5),
+            ..Default::default()
+        };
+        let js_reader = JetstreamReader::new(stream_name, 0, context.clone(), buf_reader_config)
+            .await
+            .unwrap();
+
+        let pipeline_cfg_base64 = "eyJtZXRhZGF0YSI6eyJuYW1lIjoic2ltcGxlLXBpcGVsaW5lLW91dCIsIm5hbWVzcGFjZSI6ImRlZmF1bHQiLCJjcmVhdGlvblRpbWVzdGFtcCI6bnVsbH0sInNwZWMiOnsibmFtZSI6Im91dCIsInNpbmsiOnsiYmxhY2tob2xlIjp7fSwicmV0cnlTdHJhdGVneSI6eyJvbkZhaWx1cmUiOiJyZXRyeSJ9fSwibGltaXRzIjp7InJlYWRCYXRjaFNpemUiOjUwMCwicmVhZFRpbWVvdXQiOiIxcyIsImJ1ZmZlck1heExlbmd0aCI6MzAwMDAsImJ1ZmZlclVzYWdlTGltaXQiOjgwfSwic2NhbGUiOnsibWluIjoxfSwidXBkYXRlU3RyYXRlZ3kiOnsidHlwZSI6IlJvbGxpbmdVcGRhdGUiLCJyb2xsaW5nVXBkYXRlIjp7Im1heFVuYXZhaWxhYmxlIjoiMjUlIn19LCJwaXBlbGluZU5hbWUiOiJzaW1wbGUtcGlwZWxpbmUiLCJpbnRlclN0ZXBCdWZmZXJTZXJ2aWNlTmFtZSI6IiIsInJlcGxpY2FzIjowLCJmcm9tRWRnZXMiOlt7ImZyb20iOiJpbiIsInRvIjoib3V0IiwiY29uZGl0aW9ucyI6bnVsbCwiZnJvbVZlcnRleFR5cGUiOiJTb3VyY2UiLCJmcm9tVmVydGV4UGFydGl0aW9uQ291bnQiOjEsImZyb21WZXJ0ZXhMaW1pdHMiOnsicmVhZEJhdGNoU2l6ZSI6NTAwLCJyZWFkVGltZW91dCI6IjFzIiwiYnVmZmVyTWF4TGVuZ3RoIjozMDAwMCwiYnVmZmVyVXNhZ2VMaW1pdCI6ODB9LCJ0b1ZlcnRleFR5cGUiOiJTaW5rIiwidG9WZXJ0ZXhQYXJ0aXRpb25Db3VudCI6MSwidG9WZXJ0ZXhMaW1pdHMiOnsicmVhZEJhdGNoU2l6ZSI6NTAwLCJyZWFkVGltZW91dCI6IjFzIiwiYnVmZmVyTWF4TGVuZ3RoIjozMDAwMCwiYnVmZmVyVXNhZ2VMaW1pdCI6ODB9fV0sIndhdGVybWFyayI6eyJtYXhEZWxheSI6IjBzIn19LCJzdGF0dXMiOnsicGhhc2UiOiIiLCJyZXBsaWNhcyI6MCwiZGVzaXJlZFJlcGxpY2FzIjowLCJsYXN0U2NhbGVkQXQiOm51bGx9fQ==".to_string();
+
+        let env_vars = [("NUMAFLOW_ISBSVC_JETSTREAM_URL", "localhost:4222")];
+        let pipeline_config = PipelineConfig::load(pipeline_cfg_base64, env_vars).unwrap();
+        let reader_cancel_token = CancellationToken::new();
+        let (mut js_reader_rx, js_reader_task) = js_reader
+            .streaming_read(reader_cancel_token.clone(), &pipeline_config)
+            .await
+            .unwrap();
+
+        let writer_cancel_token = CancellationToken::new();
+        let writer = JetstreamWriter::new(
+            vec![(stream_name.to_string(), 0)],
+            Default::default(),
+            context.clone(),
+            writer_cancel_token.clone(),
+        );
+
+        // write 5 messages
+        for i in 0..5 {
+            let message = Message {
+                keys: vec![format!("key_{}", i)],
+                value: format!("message {}", i).as_bytes().to_vec().into(),
+                offset: None,
+                event_time: Utc::now(),
+                id: MessageID {
+                    vertex_name: "vertex".to_string(),
+                    offset: format!("offset_{}", i),
+                    index: i,
+                },
+                headers: HashMap::new(),
+            };
+            let message_bytes: BytesMut = message.try_into().unwrap();
+            let WriteResult::Published(paf) = writer
+                .write(
+                    (stream_name.to_string(), 0),
+                    message_bytes.into(),
+                    HeaderMap::new(),
+                )
+                .await
+                .unwrap()
+            else {
+                panic!("message should not be discarded");
+            };
+            paf.await.unwrap();
+        }
+        // Cancel the token to exit the retry loop
+        writer_cancel_token.cancel();
+
+        for _ in 0..5 {
+            let Some(val) = js_reader_rx.next().await else {
+                break;
+            };
+            val.ack.send(Ack).unwrap()
+        }
+
+        let mut consumer: PullConsumer = context
+            .get_consumer_from_stream(stream_name, stream_name)
+            .await
+            .unwrap();
+
+        let consumer_info = consumer.info().await.unwrap();
+
+        assert_eq!(consumer_info.num_pending, 0);
+        assert_eq!(consumer_info.num_ack_pending, 0);
+
+        reader_cancel_token.cancel();
+        js_reader_task.await.unwrap().unwrap();
+
+        context.delete_stream(stream_name).await.unwrap();
+    }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_jetstream_nack() {
+        let js_url = "localhost:4222";
+        // Create JetStream context
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_nack";
+        let buf_reader_config = BufferReaderConfig {
+            partitions: 0,
+            streams: vec![],
+            wip_ack_interval: Duration::from_millis(5),
+            ..Default::default()
+        };
+        let reader_cancel_token = CancellationToken::new();
+        let (js_reader, mut js_reader_rx, js_reader_task) = start_reader(
+            &context,
+            stream_name,
+            buf_reader_config,
+            reader_cancel_token.clone(),
+        )
+        .await;
+
+        let writer_cancel_token = CancellationToken::new();
+        let writer = JetstreamWriter::new(
+            vec![(stream_name.to_string(), 0)],
+            Default::default(),
+            context.clone(),
+            writer_cancel_token.clone(),
+        );
+
+        let message = Message {
+            keys: vec!["key_0".to_string()],
+            value: "message 0".as_bytes().to_vec().into(),
+            offset: None,
+            event_time: Utc::now(),
+            id: MessageID {
+                vertex_name: "vertex".to_string(),
+                offset: "offset_0".to_string(),
+                index: 0,
+            },
+            headers: HashMap::new(),
+        };
+        let message_bytes: BytesMut = message.try_into().unwrap();
+        let WriteResult::Published(paf) = writer
+            .write(
+                (stream_name.to_string(), 0),
+                message_bytes.into(),
+                HeaderMap::new(),
+            )
+            .await
+            .unwrap()
+        else {
+            panic!("message should not be discarded");
+        };
+        paf.await.unwrap();
+        writer_cancel_token.cancel();
+
+        let first = js_reader_rx.next().await.unwrap();
+        let offset = first.message.offset.clone().unwrap();
+
+        // only the int offsets of the partition can be NAK'd
+        let invalid = Offset::String(StringOffset::new("0".to_string(), 0));
+        assert!(js_reader.nack(vec![invalid], None).await.is_err());
+
+        // the message NAK'd through the source acker is redelivered without waiting for the
+        // AckWait
+        SourceAcker::nack(&mut js_reader.clone(), vec![offset.clone()], None)
+            .await
+            .unwrap();
+        let redelivered = tokio::time::timeout(Duration::from_secs(5), js_reader_rx.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(redelivered.message.offset, Some(offset));
+        redelivered.ack.send(Ack).unwrap();
+        drop(first);
+
+        // wait for the ack to be sent
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let mut consumer: PullConsumer = context
+            .get_consumer_from_stream(stream_name, stream_name)
+            .await
+            .unwrap();
+        let consumer_info = consumer.info().await.unwrap();
+        assert_eq!(consumer_info.num_pending, 0);
+        assert_eq!(consumer_info.num_ack_pending, 0);
+
+        reader_cancel_token.cancel();
+        js_reader_task.await.unwrap().unwrap();
+
+        context.delete_stream(stream_name).await.unwrap();
+    }
+
+    #[test]
+    fn test_ack_batch_size_threshold() {
+        let start = Instant::now();
+        let mut batch = AckBatch::new(3, Duration::from_secs(1));
+        assert_eq!(batch.deadline(), None);
+
+        assert_eq!(batch.push(1, start), None);
+        assert_eq!(batch.push(2, start), None);
+        // flushed as soon as the batch is full
+        assert_eq!(batch.push(3, start), Some(vec![1, 2, 3]));
+        assert_eq!(batch.deadline(), None);
+
+        assert_eq!(batch.push(4, start), None);
+        assert_eq!(batch.push(5, start), None);
+        assert_eq!(batch.push(6, start), Some(vec![4, 5, 6]));
+    }
+
+    #[test]
+    fn test_ack_batch_time_threshold() {
+        let start = Instant::now();
+        let interval = Duration::from_secs(1);
+        let mut batch = AckBatch::new(10, interval);
+        assert_eq!(batch.take_due(start + interval), None);
+
+        assert_eq!(batch.push(1, start), None);
+        // the deadline is set by the oldest message of the batch
+        assert_eq!(batch.push(2, start + interval / 2), None);
+        assert_eq!(batch.deadline(), Some(start + interval));
+
+        assert_eq!(batch.take_due(start + interval / 2), None);
+        assert_eq!(batch.take_due(start + interval), Some(vec![1, 2]));
+        assert_eq!(batch.deadline(), None);
+        assert_eq!(batch.take_due(start + interval * 2), None);
+    }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_jetstream_batched_ack() {
+        let js_url = "localhost:4222";
+        // Create JetStream context
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_batched_ack";
+        let buf_reader_config = BufferReaderConfig {
+            partitions: 0,
+            streams: vec![],
+            wip_ack_interval: Duration::from_millis(5),
+            ack_batch_size: 5,
+            ack_batch_interval: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let reader_cancel_token = CancellationToken::new();
+        let (_js_reader, mut js_reader_rx, js_reader_task) = start_reader(
+            &context,
+            stream_name,
+            buf_reader_config,
+            reader_cancel_token.clone(),
+        )
+        .await;
+
+        for i in 0..5 {
+            let message = Message {
+                keys: vec![format!("key_{}", i)],
+                value: format!("message {}", i).as_bytes().to_vec().into(),
+                offset: None,
+                event_time: Utc::now(),
+                id: MessageID {
+                    vertex_name: "vertex".to_string(),
+                    offset: format!("offset_{}", i),
+                    index: i,
+                },
+                headers: HashMap::new(),
+            };
+            let message_bytes: BytesMut = message.try_into().unwrap();
+            context
+                .publish(stream_name, message_bytes.freeze())
+                .await
+                .unwrap()
+                .await
+                .unwrap();
+        }
+
+        let mut read_messages = vec![];
+        for _ in 0..5 {
+            read_messages.push(js_reader_rx.next().await.unwrap());
+        }
+        let ack_pending = || async {
+            let mut consumer: PullConsumer = context
+                .get_consumer_from_stream(stream_name, stream_name)
+                .await
+                .unwrap();
+            consumer.info().await.unwrap().num_ack_pending
+        };
+
+        // the acks are held back till the batch is full
+        let last = read_messages.pop().unwrap();
+        for read_message in read_messages {
+            read_message.ack.send(Ack).unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(ack_pending().await, 5);
+
+        // and are sent together once it is
+        last.ack.send(Ack).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(ack_pending().await, 0);
+
+        reader_cancel_token.cancel();
+        js_reader_task.await.unwrap().unwrap();
+
+        context.delete_stream(stream_name).await.unwrap();
+    }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_jetstream_ack_dedup() {
+        let js_url = "localhost:4222";
+        // Create JetStream context
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_ack_dedup";
+        let buf_reader_config = BufferReaderConfig {
+            partitions: 0,
+            streams: vec![],
+            wip_ack_interval: Duration::from_millis(5),
+            ..Default::default()
+        };
+        let reader_cancel_token = CancellationToken::new();
+        let (js_reader, mut js_reader_rx, js_reader_task) = start_reader(
+            &context,
+            stream_name,
+            buf_reader_config,
+            reader_cancel_token.clone(),
+        )
+        .await;
+
+        let writer_cancel_token = CancellationToken::new();
+        let writer = JetstreamWriter::new(
+            vec![(stream_name.to_string(), 0)],
+            Default::default(),
+            context.clone(),
+            writer_cancel_token.clone(),
+        );
+
+        for i in 0..5 {
+            let message = Message {
+                keys: vec![format!("key_{}", i)],
+                value: format!("message {}", i).as_bytes().to_vec().into(),
+                offset: None,
+                event_time: Utc::now(),
+                id: MessageID {
+                    vertex_name: "vertex".to_string(),
+                    offset: format!("offset_{}", i),
+                    index: i,
+                },
+                headers: HashMap::new(),
+            };
+            let message_bytes: BytesMut = message.try_into().unwrap();
+            let WriteResult::Published(paf) = writer
+                .write(
+                    (stream_name.to_string(), 0),
+                    message_bytes.into(),
+                    HeaderMap::new(),
+                )
+                .await
+                .unwrap()
+            else {
+                panic!("message should not be discarded");
+            };
+            paf.await.unwrap();
+        }
+        writer_cancel_token.cancel();
+
+        let mut read_messages = vec![];
+        for _ in 0..5 {
+            read_messages.push(js_reader_rx.next().await.unwrap());
+        }
+        let offsets: Vec<Offset> = read_messages
+            .iter()
+            .map(|m| m.message.offset.clone().unwrap())
+            .collect();
+
+        // nothing is acked if any of the offsets is invalid
+        let invalid = Offset::Int(IntOffset::new(1, 1));
+        assert!(js_reader
+            .ack(vec![offsets[0].clone(), invalid])
+            .await
+            .is_err());
+
+        // the overlapping offsets are acked only once
+        assert_eq!(js_reader.ack(offsets[..3].to_vec()).await.unwrap(), 3);
+        assert_eq!(js_reader.ack(offsets[1..4].to_vec()).await.unwrap(), 1);
+        assert_eq!(js_reader.ack(offsets[..4].to_vec()).await.unwrap(), 0);
+
+        // the redelivery of a NAK'd message still gets its own ack
+        js_reader
+            .nack(vec![offsets[4].clone()], None)
+            .await
+            .unwrap();
+        let redelivered = tokio::time::timeout(Duration::from_secs(5), js_reader_rx.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(redelivered.message.offset, Some(offsets[4].clone()));
+        assert_eq!(js_reader.ack(vec![offsets[4].clone()]).await.unwrap(), 1);
+        assert_eq!(js_reader.ack(vec![offsets[4].clone()]).await.unwrap(), 0);
+
+        // wait for the acks to be sent
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        drop(read_messages);
+        drop(redelivered);
+        let mut consumer: PullConsumer = context
+            .get_consumer_from_stream(stream_name, stream_name)
+            .await
+            .unwrap();
+        let consumer_info = consumer.info().await.unwrap();
+        assert_eq!(consumer_info.num_pending, 0);
+        assert_eq!(consumer_info.num_ack_pending, 0);
+
+        reader_cancel_token.cancel();
+        js_reader_task.await.unwrap().unwrap();
+
+        context.delete_stream(stream_name).await.unwrap();
+    }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_jetstream_read_compressed() {
+        let js_url = "localhost:4222";
+        // Create JetStream context
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_read_compressed";
+        let buf_reader_config = BufferReaderConfig {
+            partitions: 0,
+            streams: vec![],
+            wip_ack_interval: Duration::from_millis(5),
+            ..Default::default()
+        };
+        let reader_cancel_token = CancellationToken::new();
+        let (_, mut js_reader_rx, js_reader_task) = start_reader(
+            &context,
+            stream_name,
+            buf_reader_config,
+            reader_cancel_token.clone(),
+        )
+        .await;
+
+        let writer_cancel_token = CancellationToken::new();
+        let writer = JetstreamWriter::new(
+            vec![(stream_name.to_string(), 0)],
+            BufferWriterConfig::builder()
+                .compression(Some(Compression::Gzip))
+                .build(),
+            context.clone(),
+            writer_cancel_token.clone(),
+        );
+
+        let message = Message {
+            keys: vec!["key_0".to_string()],
+            value: "message 0 ".repeat(100).into_bytes().into(),
+            offset: None,
+            event_time: Utc::now(),
+            id: MessageID {
+                vertex_name: "vertex".to_string(),
+                offset: "offset_0".to_string(),
+                index: 0,
+            },
+            headers: HashMap::new(),
+        };
+        let message_bytes: BytesMut = message.clone().try_into().unwrap();
+        let WriteResult::Published(paf) = writer
+            .write(
+                (stream_name.to_string(), 0),
+                message_bytes.into(),
+                HeaderMap::new(),
+            )
+            .await
+            .unwrap()
+        else {
+            panic!("message should not be discarded");
+        };
+        paf.await.unwrap();
+        writer_cancel_token.cancel();
+
+        // the payload is decompressed by the reader
+        let read_message = js_reader_rx.next().await.unwrap();
+        assert_eq!(read_message.message.value, message.value);
+        assert_eq!(read_message.message.keys, message.keys);
+        read_message.ack.send(Ack).unwrap();
+
+        reader_cancel_token.cancel();
+        js_reader_task.await.unwrap().unwrap();
+
+        context.delete_stream(stream_name).await.unwrap();
+    }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_jetstream_reader_creates_consumer() {
+        let js_url = "localhost:4222";
+        // Create JetStream context
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_jetstream_reader_creates_consumer";
+        context
+            .get_or_create_stream(stream::Config {
+                name: stream_name.into(),
+                subjects: vec![stream_name.into()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let buf_reader_config = BufferReaderConfig {
+            partitions: 0,
+            streams: vec![],
+            ack_wait: Duration::from_secs(12),
+            max_ack_pending: 100,
+            ..Default::default()
+        };
+        let _js_reader = JetstreamReader::new(stream_name, 0, context.clone(), buf_reader_config)
+            .await
+            .unwrap();
+
+        let mut consumer: PullConsumer = context
+            .get_consumer_from_stream(stream_name, stream_name)
+            .await
+            .unwrap();
+        let consumer_info = consumer.info().await.unwrap();
+        assert_eq!(consumer_info.config.ack_wait, Duration::from_secs(12));
+        assert_eq!(consumer_info.config.max_ack_pending, 100);
+
+        context.delete_stream(stream_name).await.unwrap();
+    }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_jetstream_reader_updates_consumer() {
+        let js_url = "localhost:4222";
+        // Create JetStream context
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_jetstream_reader_updates_consumer";
+        context
+            .get_or_create_stream(stream::Config {
+                name: stream_name.into(),
+                subjects: vec![format!("{stream_name}.>")],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        // the consumer created by the controller, with different settings
+        context
+            .create_consumer_on_stream(
+                consumer::pull::Config {
+                    durable_name: Some(stream_name.to_string()),
+                    ack_policy: consumer::AckPolicy::Explicit,
+                    ack_wait: Duration::from_secs(60),
+                    max_ack_pending: 10,
+                    ..Default::default()
+                },
+                stream_name,
+            )
+            .await
+            .unwrap();
+
+        let buf_reader_config = BufferReaderConfig {
+            partitions: 0,
+            streams: vec![],
+            ack_wait: Duration::from_secs(12),
+            max_ack_pending: 100,
+            subject_filter: Some(format!("{stream_name}.orders")),
+            ..Default::default()
+        };
+        let _js_reader = JetstreamReader::new(stream_name, 0, context.clone(), buf_reader_config)
+            .await
+            .unwrap();
+
+        let mut consumer: PullConsumer = context
+            .get_consumer_from_stream(stream_name, stream_name)
+            .await
+            .unwrap();
+        let consumer_info = consumer.info().await.unwrap();
+        assert_eq!(consumer_info.config.ack_wait, Duration::from_secs(12));
+        assert_eq!(consumer_info.config.max_ack_pending, 100);
+        assert_eq!(
+            consumer_info.config.filter_subject,
+            format!("{stream_name}.orders")
+        );
+
+        context.delete_stream(stream_name).await.unwrap();
+    }
 }
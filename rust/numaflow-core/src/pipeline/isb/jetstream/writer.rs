@@ -1,6 +1,7 @@
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use async_nats::jetstream::consumer::PullConsumer;
@@ -8,19 +9,78 @@ use async_nats::jetstream::context::PublishAckFuture;
 use async_nats::jetstream::publish::PublishAck;
 use async_nats::jetstream::stream::RetentionPolicy::Limits;
 use async_nats::jetstream::Context;
+use async_nats::HeaderMap;
 use bytes::Bytes;
-use tokio::sync::{oneshot, Semaphore};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tokio::sync::{oneshot, watch, Semaphore};
 use tokio::time::{sleep, Instant};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 
-use crate::config::pipeline::isb::BufferWriterConfig;
+use crate::config::pipeline::isb::{BufferFullStrategy, BufferWriterConfig, FaultConfig};
 use crate::error::Error;
 use crate::message::{IntOffset, Offset, ReadAck};
 use crate::metrics::{pipeline_isb_metric_labels, pipeline_metrics};
-use crate::pipeline::isb::jetstream::Stream;
+use crate::pipeline::isb::jetstream::{content_encoding, Stream, CONTENT_ENCODING_HEADER};
 use crate::Result;
 
+/// WriterObserver is notified of the events on the write path of the [JetstreamWriter], so that a
+/// custom metrics backend can be plugged in. All the callbacks are no-ops by default.
+pub(crate) trait WriterObserver: Debug + Send + Sync {
+    /// a message of `bytes` size has been published to the stream.
+    fn on_write(&self, _stream: &Stream, _bytes: usize) {}
+
+    /// writing to the stream failed (or the buffer is full) and will be retried.
+    fn on_retry(&self, _stream: &Stream) {}
+
+    /// a message has been discarded because the buffer of the stream is full.
+    fn on_discard(&self, _stream: &Stream) {}
+
+    /// the buffer of the stream was found to be full while writing. It is notified once per
+    /// transition into full, not on every retry while the buffer stays full.
+    fn on_buffer_full(&self, _stream: &Stream) {}
+}
+
+/// NoopObserver ignores all the events.
+#[derive(Debug)]
+pub(crate) struct NoopObserver;
+
+impl WriterObserver for NoopObserver {}
+
+/// returns the backpressure of a buffer with the given usage, i.e. the usage relative to the
+/// usage limit within `[0.0, 1.0]`.
+fn buffer_pressure(usage: f64, usage_limit: f64) -> f64 {
+    if usage_limit <= 0.0 {
+        return 1.0;
+    }
+    (usage / usage_limit).clamp(0.0, 1.0)
+}
+
+/// FaultInjector decides which of the publishes have to fail, as per the [FaultConfig].
+#[derive(Debug)]
+struct FaultInjector {
+    fail_fraction: f64,
+    rng: Mutex<StdRng>,
+}
+
+impl FaultInjector {
+    fn new(config: FaultConfig) -> Self {
+        Self {
+            fail_fraction: config.fail_fraction.clamp(0.0, 1.0),
+            rng: Mutex::new(StdRng::seed_from_u64(config.seed)),
+        }
+    }
+
+    /// returns true if the next publish has to fail.
+    fn should_fail(&self) -> bool {
+        self.rng
+            .lock()
+            .expect("fault injector lock poisoned")
+            .gen_bool(self.fail_fraction)
+    }
+}
+
 #[derive(Clone, Debug)]
 /// Writes to JetStream ISB. Exposes both write and blocking methods to write messages.
 /// It accepts a cancellation token to stop infinite retries during shutdown.
@@ -31,7 +91,27 @@ pub(crate) struct JetstreamWriter {
     config: BufferWriterConfig,
     js_ctx: Context,
     is_full: HashMap<String, Arc<AtomicBool>>,
+    /// pending (NumPending + NumAckPending) count of each stream as of the last refresh.
+    pending: HashMap<String, Arc<AtomicUsize>>,
     cancel_token: CancellationToken,
+    observer: Arc<dyn WriterObserver>,
+    /// backpressure of the streams as of the last refresh, see [JetstreamWriter::pressure].
+    pressure: Arc<watch::Sender<f64>>,
+    /// writes which would have been published, recorded only if the `dry_run` is set.
+    #[cfg(test)]
+    dry_run_writes: Arc<Mutex<Vec<DryRunWrite>>>,
+    /// fails the publishes on purpose, set only if the `fault_injection` is configured.
+    fault_injector: Option<Arc<FaultInjector>>,
+}
+
+/// DryRunWrite is a message which would have been published to the stream if the writer was not in
+/// the dry-run mode.
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct DryRunWrite {
+    pub(crate) stream: Stream,
+    /// size of the payload as it would have been published, i.e. after the compression.
+    pub(crate) bytes: usize,
 }
 
 impl JetstreamWriter {
@@ -42,18 +122,48 @@ impl JetstreamWriter {
         config: BufferWriterConfig,
         js_ctx: Context,
         cancel_token: CancellationToken,
+    ) -> Self {
+        Self::with_observer(
+            streams,
+            config,
+            js_ctx,
+            cancel_token,
+            Arc::new(NoopObserver),
+        )
+    }
+
+    /// Same as [JetstreamWriter::new], the observer is notified of the events on the write path.
+    pub(crate) fn with_observer(
+        streams: Vec<Stream>,
+        config: BufferWriterConfig,
+        js_ctx: Context,
+        cancel_token: CancellationToken,
+        observer: Arc<dyn WriterObserver>,
     ) -> Self {
         let is_full = streams
             .iter()
             .map(|stream| (stream.0.clone(), Arc::new(AtomicBool::new(false))))
             .collect::<HashMap<_, _>>();
+        let pending = streams
+            .iter()
+            .map(|stream| (stream.0.clone(), Arc::new(AtomicUsize::new(0))))
+            .collect::<HashMap<_, _>>();
 
+        let fault_injector = config
+            .fault_injection
+            .map(|fault_config| Arc::new(FaultInjector::new(fault_config)));
         let this = Self {
             streams,
             config,
             js_ctx,
             is_full,
+            pending,
             cancel_token,
+            observer,
+            pressure: Arc::new(watch::channel(0.0).0),
+            #[cfg(test)]
+            dry_run_writes: Arc::new(Mutex::new(vec![])),
+            fault_injector,
         };
 
         // spawn a task for checking whether buffer is_full
@@ -68,15 +178,23 @@ impl JetstreamWriter {
     }
 
     /// Checks the buffer usage metrics (soft and solid usage) for each stream in the streams vector.
-    /// If the usage is greater than the bufferUsageLimit, it sets the is_full flag to true.
+    /// If the usage is greater than the bufferUsageLimit, it sets the is_full flag to true. The usage
+    /// is computed against the max length of each stream.
     async fn check_stream_status(&mut self) {
         let mut interval = tokio::time::interval(self.config.refresh_interval);
         loop {
             tokio::select! {
                 _ = interval.tick() => {
+                    // pressure of the fullest stream
+                    let mut pressure: f64 = 0.0;
                     for stream in &self.streams {
-                        match Self::fetch_buffer_usage(self.js_ctx.clone(), stream.0.as_str(), self.config.max_length).await {
-                            Ok((soft_usage, solid_usage)) => {
+                        let max_length = self.config.stream_max_length(stream.0.as_str());
+                        match Self::fetch_buffer_usage(self.js_ctx.clone(), stream.0.as_str(), max_length).await {
+                            Ok((pending, soft_usage, solid_usage)) => {
+                                if let Some(stream_pending) = self.pending.get(stream.0.as_str()) {
+                                    stream_pending.store(pending, Ordering::Relaxed);
+                                }
+                                pressure = pressure.max(buffer_pressure(soft_usage.min(solid_usage), self.config.usage_limit));
                                 if solid_usage >= self.config.usage_limit && soft_usage >= self.config.usage_limit {
                                     if let Some(is_full) = self.is_full.get(stream.0.as_str()) {
                                         is_full.store(true, Ordering::Relaxed);
@@ -90,9 +208,11 @@ impl JetstreamWriter {
                                 if let Some(is_full) = self.is_full.get(stream.0.as_str()) {
                                     is_full.store(true, Ordering::Relaxed);
                                 }
+                                pressure = 1.0;
                             }
                         }
                     }
+                    self.pressure.send_replace(pressure);
                 }
                 _ = self.cancel_token.cancelled() => {
                     return;
@@ -101,7 +221,67 @@ impl JetstreamWriter {
         }
     }
 
-    /// Fetches the buffer usage metrics (soft and solid usage) for the given stream.
+    /// Returns the fraction of the buffer used (pending / max_length) for each stream the writer
+    /// writes to. It is as of the last refresh, which happens every `refresh_interval`.
+    #[cfg(test)]
+    pub(crate) fn usage(&self) -> HashMap<String, f64> {
+        self.pending
+            .iter()
+            .map(|(stream, pending)| {
+                let max_length = self.config.stream_max_length(stream);
+                let usage = pending.load(Ordering::Relaxed) as f64 / max_length as f64;
+                (stream.clone(), usage)
+            })
+            .collect()
+    }
+
+    /// marks the stream as full (or not) till the next refresh of the buffer usage.
+    #[cfg(test)]
+    pub(super) fn set_full(&self, stream: &str, full: bool) {
+        self.is_full
+            .get(stream)
+            .expect("unknown stream")
+            .store(full, Ordering::Relaxed);
+    }
+
+    /// Returns the backpressure signal of the streams, it is the usage of the fullest stream
+    /// relative to the `usage_limit`, within `[0.0, 1.0]`. 1.0 means a stream is full (or its
+    /// usage could not be fetched). It is updated every `refresh_interval`, so that the source
+    /// can slow down before the buffers are full.
+    pub(crate) fn pressure(&self) -> watch::Receiver<f64> {
+        self.pressure.subscribe()
+    }
+
+    /// Returns the writes recorded in the dry-run mode so far, in the order they were made.
+    #[cfg(test)]
+    pub(crate) fn dry_run_writes(&self) -> Vec<DryRunWrite> {
+        self.dry_run_writes
+            .lock()
+            .expect("dry-run writes lock poisoned")
+            .clone()
+    }
+
+    /// records the write instead of publishing it, the stream has to be one of the streams of the
+    /// writer like in a real write.
+    fn dry_run_write(
+        &self,
+        stream: Stream,
+        bytes: usize,
+    ) -> std::result::Result<WriteResult, IsbWriteError> {
+        if !self.is_full.contains_key(&stream.0) {
+            return Err(IsbWriteError::StreamNotFound(stream.0));
+        }
+        info!(stream = ?stream.0, bytes, "Dry run, skipping the publish");
+        #[cfg(test)]
+        self.dry_run_writes
+            .lock()
+            .expect("dry-run writes lock poisoned")
+            .push(DryRunWrite { stream, bytes });
+        Ok(WriteResult::DryRun)
+    }
+
+    /// Fetches the pending count and the buffer usage metrics (soft and solid usage) for the
+    /// given stream.
     ///
     /// Soft Usage:
     /// Formula: (NumPending + NumAckPending) / maxLength
@@ -119,7 +299,7 @@ impl JetstreamWriter {
         js_ctx: Context,
         stream_name: &str,
         max_length: usize,
-    ) -> Result<(f64, f64)> {
+    ) -> Result<(usize, f64, f64)> {
         let mut stream = js_ctx
             .get_stream(stream_name)
             .await
@@ -140,84 +320,190 @@ impl JetstreamWriter {
             .await
             .map_err(|e| Error::ISB(format!("Failed to get the consumer info {:?}", e)))?;
 
-        let soft_usage = (consumer_info.num_pending as f64 + consumer_info.num_ack_pending as f64)
-            / max_length as f64;
+        let pending = consumer_info.num_pending as usize + consumer_info.num_ack_pending;
+        let soft_usage = pending as f64 / max_length as f64;
         let solid_usage = if stream_info.config.retention == Limits {
             soft_usage
         } else {
             stream_info.state.messages as f64 / max_length as f64
         };
 
-        Ok((soft_usage, solid_usage))
+        Ok((pending, soft_usage, solid_usage))
+    }
+
+    /// returns an error if the writes have been retried for longer than the retry deadline.
+    /// the `cause` of the last failure is returned as the error.
+    fn check_retry_deadline(
+        &self,
+        start: Instant,
+        cause: IsbWriteError,
+    ) -> std::result::Result<(), IsbWriteError> {
+        match self.config.retry_deadline {
+            Some(deadline) if start.elapsed() >= deadline => Err(cause),
+            _ => Ok(()),
+        }
+    }
+
+    /// compresses the payload if a compression is configured, the compression is set in the
+    /// [CONTENT_ENCODING_HEADER] so that the reader can decompress it.
+    fn compress(&self, payload: Vec<u8>, mut headers: HeaderMap) -> (Vec<u8>, HeaderMap) {
+        let Some(compression) = self.config.compression else {
+            return (payload, headers);
+        };
+        headers.insert(CONTENT_ENCODING_HEADER, content_encoding(compression));
+        (compression.compress(&payload), headers)
+    }
+
+    /// publishes the payload to the stream, the publish fails without reaching NATS if a fault is
+    /// injected.
+    async fn publish(
+        &self,
+        stream: &Stream,
+        headers: HeaderMap,
+        payload: Bytes,
+    ) -> std::result::Result<PublishAckFuture, String> {
+        if let Some(fault_injector) = self.fault_injector.as_ref() {
+            if fault_injector.should_fail() {
+                return Err("injected fault".to_string());
+            }
+        }
+        self.js_ctx
+            .publish_with_headers(self.config.subject(&stream.0), headers, payload)
+            .await
+            .map_err(|e| e.to_string())
     }
 
     /// Writes the message to the JetStream ISB and returns a future which can be
     /// awaited to get the PublishAck. It will do infinite retries until the message
-    /// gets published successfully. If it returns an error it means it is fatal error
-    pub(super) async fn write(&self, stream: Stream, payload: Vec<u8>) -> PublishAckFuture {
-        let js_ctx = self.js_ctx.clone();
+    /// gets published successfully, unless a retry deadline is configured in which case an error
+    /// is returned once it is exceeded. If the buffer is full and the [BufferFullStrategy] is
+    /// DiscardLatest, the write is retried till the discard grace elapses, after which the message
+    /// is not written and [WriteResult::Discarded] is returned.
+    /// The headers are set as the NATS headers of the message. In the dry-run mode, the message is
+    /// only recorded and [WriteResult::DryRun] is returned.
+    pub(super) async fn write(
+        &self,
+        stream: Stream,
+        payload: Vec<u8>,
+        headers: HeaderMap,
+    ) -> std::result::Result<WriteResult, IsbWriteError> {
+        let (payload, headers) = self.compress(payload, headers);
+        if self.config.dry_run {
+            return self.dry_run_write(stream, payload.len());
+        }
+        let start = Instant::now();
 
         let mut counter = 500u64;
+        let mut attempt = 0;
+        // whether the buffer was full on the previous attempt, the observer is only notified when
+        // the buffer turns full.
+        let mut was_full = false;
 
         // loop till we get a PAF, there could be other reasons why PAFs cannot be created.
         let paf = loop {
             // let's write only if the buffer is not full for the stream
-            match self
+            let cause = match self
                 .is_full
                 .get(&stream.0)
                 .map(|is_full| is_full.load(Ordering::Relaxed))
             {
                 Some(true) => {
-                    // FIXME: add metrics
+                    if !was_full {
+                        self.observer.on_buffer_full(&stream);
+                        was_full = true;
+                    }
                     if counter >= 500 {
                         warn!(stream=?stream.0, "stream is full (throttled logging)");
                         counter = 0;
                     }
                     counter += 1;
 
-                    // FIXME: consider buffer-full strategy
-                }
-                Some(false) => match js_ctx
-                    .publish(stream.0.clone(), Bytes::from(payload.clone()))
-                    .await
-                {
-                    Ok(paf) => {
-                        break paf;
+                    if self.config.buffer_full_strategy == BufferFullStrategy::DiscardLatest
+                        && start.elapsed() >= self.config.discard_grace
+                    {
+                        self.observer.on_discard(&stream);
+                        return Ok(WriteResult::Discarded);
                     }
-                    Err(e) => {
-                        error!(?e, "publishing failed, retrying");
+                    IsbWriteError::BufferFull(stream.0.clone())
+                }
+                Some(false) => {
+                    was_full = false;
+                    match self
+                        .publish(&stream, headers.clone(), Bytes::from(payload.clone()))
+                        .await
+                    {
+                        Ok(paf) => {
+                            self.observer.on_write(&stream, payload.len());
+                            break paf;
+                        }
+                        Err(e) => {
+                            error!(?e, "publishing failed, retrying");
+                            IsbWriteError::PublishFailed {
+                                stream: stream.0.clone(),
+                                reason: e,
+                            }
+                        }
                     }
-                },
+                }
+                // the stream is not one of the streams of the writer, retrying will not help
                 None => {
                     error!("Stream {} not found in is_full map", stream.0);
+                    return Err(IsbWriteError::StreamNotFound(stream.0.clone()));
                 }
-            }
+            };
             // short-circuit out in failure mode if shutdown has been initiated
             if self.cancel_token.is_cancelled() {
                 error!("Shutdown signal received, exiting write loop");
             }
 
+            self.check_retry_deadline(start, cause)?;
+
             // sleep to avoid busy looping
-            sleep(self.config.retry_interval).await;
+            self.observer.on_retry(&stream);
+            let retry_after = self.config.retry_backoff(attempt, &mut rand::thread_rng());
+            attempt += 1;
+            sleep(retry_after).await;
         };
 
-        paf
+        Ok(WriteResult::Published(paf))
+    }
+
+    /// Flushes the publishes buffered by the NATS client, so that the messages written so far have
+    /// reached the server. An error is returned if the flush does not complete within the
+    /// `flush_timeout`.
+    pub(crate) async fn flush(&self) -> Result<()> {
+        // nothing is published in the dry-run mode
+        if self.config.dry_run {
+            return Ok(());
+        }
+        let flush = self.js_ctx.client().flush();
+        tokio::time::timeout(self.config.flush_timeout, flush)
+            .await
+            .map_err(|_| {
+                Error::ISB(format!(
+                    "Flush did not complete within {:?}",
+                    self.config.flush_timeout
+                ))
+            })?
+            .map_err(|e| Error::ISB(format!("Failed to flush: {e}")))
     }
 
     /// Writes the message to the JetStream ISB and returns the PublishAck. It will do
-    /// infinite retries until the message gets published successfully. If it returns
-    /// an error it means it is fatal non-retryable error.
+    /// infinite retries until the message gets published successfully, or till the retry deadline
+    /// if configured. If it returns an error it means it is fatal non-retryable error.
     pub(super) async fn blocking_write(
         &self,
         stream: Stream,
         payload: Vec<u8>,
+        headers: HeaderMap,
     ) -> Result<PublishAck> {
-        let js_ctx = self.js_ctx.clone();
+        let (payload, headers) = self.compress(payload, headers);
         let start_time = Instant::now();
         info!("Blocking write for stream {}", stream.0);
+        let mut attempt = 0;
         loop {
-            match js_ctx
-                .publish(stream.0.clone(), Bytes::from(payload.clone()))
+            match self
+                .publish(&stream, headers.clone(), Bytes::from(payload.clone()))
                 .await
             {
                 Ok(paf) => match paf.await {
@@ -232,16 +518,32 @@ impl JetstreamWriter {
                             elapsed_ms = start_time.elapsed().as_millis(),
                             "Blocking write successful in",
                         );
+                        self.observer.on_write(&stream, payload.len());
                         return Ok(ack);
                     }
                     Err(e) => {
                         error!(?e, "awaiting publish ack failed, retrying");
+                        self.check_retry_deadline(
+                            start_time,
+                            IsbWriteError::Timeout(stream.0.clone()),
+                        )?;
+                        self.observer.on_retry(&stream);
                         sleep(Duration::from_millis(10)).await;
                     }
                 },
                 Err(e) => {
                     error!(?e, "publishing failed, retrying");
-                    sleep(self.config.retry_interval).await;
+                    self.check_retry_deadline(
+                        start_time,
+                        IsbWriteError::PublishFailed {
+                            stream: stream.0.clone(),
+                            reason: e,
+                        },
+                    )?;
+                    self.observer.on_retry(&stream);
+                    let retry_after = self.config.retry_backoff(attempt, &mut rand::thread_rng());
+                    attempt += 1;
+                    sleep(retry_after).await;
                 }
             }
             if self.cancel_token.is_cancelled() {
@@ -251,6 +553,66 @@ impl JetstreamWriter {
     }
 }
 
+/// Outcome of [JetstreamWriter::write].
+#[derive(Debug)]
+pub(crate) enum WriteResult {
+    /// Message has been published, the future resolves to the PublishAck.
+    Published(PublishAckFuture),
+    /// Message has been discarded because the buffer is full and the strategy is DiscardLatest.
+    Discarded,
+    /// Message has only been recorded because the writer is in the dry-run mode.
+    DryRun,
+}
+
+impl WriteResult {
+    /// returns the future of the PublishAck, a discarded message is an error.
+    pub(crate) fn into_paf(
+        self,
+        stream: &Stream,
+    ) -> std::result::Result<PublishAckFuture, IsbWriteError> {
+        match self {
+            WriteResult::Published(paf) => Ok(paf),
+            WriteResult::Discarded => Err(IsbWriteError::Discarded(stream.0.clone())),
+            WriteResult::DryRun => Err(IsbWriteError::DryRun(stream.0.clone())),
+        }
+    }
+}
+
+/// IsbWriteError is the cause of a failed write to the ISB, so that the callers can branch on it.
+/// It is converted to [Error::ISB] when propagated as a crate error.
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub(crate) enum IsbWriteError {
+    /// the buffer stayed full till the retry deadline.
+    #[error("Buffer of stream {0} is full")]
+    BufferFull(String),
+
+    /// the message was discarded because the buffer is full.
+    #[error("Message discarded, buffer of stream {0} is full")]
+    Discarded(String),
+
+    /// publishing failed till the retry deadline.
+    #[error("Failed to publish to stream {stream} - {reason}")]
+    PublishFailed { stream: String, reason: String },
+
+    /// the publish was not acknowledged till the retry deadline.
+    #[error("Timed out waiting for the publish ack from stream {0}")]
+    Timeout(String),
+
+    /// the stream is not one of the streams of the writer.
+    #[error("Stream {0} not found")]
+    StreamNotFound(String),
+
+    /// the message was not published because the writer is in the dry-run mode.
+    #[error("Message not published to stream {0}, dry run")]
+    DryRun(String),
+}
+
+impl From<IsbWriteError> for Error {
+    fn from(e: IsbWriteError) -> Self {
+        Error::ISB(e.to_string())
+    }
+}
+
 /// ResolveAndPublishResult resolves the result of the write PAF operation.
 /// It contains the list of pafs(one message can be written to multiple streams)
 /// and the payload that was written. Once the PAFs for all the streams have been
@@ -259,6 +621,8 @@ impl JetstreamWriter {
 pub(crate) struct ResolveAndPublishResult {
     pub(crate) pafs: Vec<(Stream, PublishAckFuture)>,
     pub(crate) payload: Vec<u8>,
+    /// NATS headers of the message.
+    pub(crate) headers: HeaderMap,
     // Acknowledgement oneshot to notify the reader that the message has been written
     pub(crate) ack_tx: oneshot::Sender<ReadAck>,
 }
@@ -268,6 +632,7 @@ pub(crate) struct ResolveAndPublishResult {
 /// the top-level callee via the oneshot rx.
 pub(crate) struct PafResolver {
     sem: Arc<Semaphore>,
+    concurrency: usize,
     js_writer: JetstreamWriter,
 }
 
@@ -275,10 +640,31 @@ impl PafResolver {
     pub(crate) fn new(concurrency: usize, js_writer: JetstreamWriter) -> Self {
         PafResolver {
             sem: Arc::new(Semaphore::new(concurrency)), // concurrency limit for resolving PAFs
+            concurrency,
             js_writer,
         }
     }
 
+    /// Waits for all the PAFs being resolved (i.e. the pending publish acks) and flushes the
+    /// writer, so that everything written so far is durably stored. An error is returned if it
+    /// does not complete within the `flush_timeout` of the writer.
+    pub(crate) async fn flush(&self) -> Result<()> {
+        let flush_timeout = self.js_writer.config.flush_timeout;
+        // all the permits are available once the in-flight resolutions are done
+        let _permits = tokio::time::timeout(
+            flush_timeout,
+            self.sem.acquire_many(self.concurrency as u32),
+        )
+        .await
+        .map_err(|_| {
+            Error::ISB(format!(
+                "Pending publish acks were not resolved within {flush_timeout:?}"
+            ))
+        })?
+        .map_err(|_e| Error::ISB("Failed to acquire semaphore permit".to_string()))?;
+        self.js_writer.flush().await
+    }
+
     /// resolve_pafs resolves the PAFs for the given result. It will try to resolve the PAFs
     /// asynchronously, if it fails it will do a blocking write to resolve the PAFs.
     /// At any point in time, we will only have X PAF resolvers running, this will help us create a
@@ -315,7 +701,11 @@ impl PafResolver {
                             stream.0
                         );
                         match js_writer
-                            .blocking_write(stream.clone(), result.payload.clone())
+                            .blocking_write(
+                                stream.clone(),
+                                result.payload.clone(),
+                                result.headers.clone(),
+                            )
                             .await
                         {
                             Ok(ack) => {
@@ -361,6 +751,7 @@ impl PafResolver {
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::sync::Mutex;
     use std::time::Instant;
 
     use async_nats::jetstream;
@@ -371,6 +762,25 @@ mod tests {
     use super::*;
     use crate::message::{Message, MessageID};
 
+    /// returns a client which is never connected since nothing is listening, it keeps connecting in
+    /// the background and the publishes are buffered by the client till then.
+    async fn disconnected_client() -> async_nats::Client {
+        async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .connect("localhost:1")
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_buffer_pressure() {
+        assert_eq!(buffer_pressure(0.0, 0.8), 0.0);
+        assert_eq!(buffer_pressure(0.4, 0.8), 0.5);
+        assert_eq!(buffer_pressure(0.8, 0.8), 1.0);
+        assert_eq!(buffer_pressure(1.2, 0.8), 1.0);
+        assert_eq!(buffer_pressure(0.1, 0.0), 1.0);
+    }
+
     #[cfg(feature = "nats-tests")]
     #[tokio::test]
     async fn test_async_write() {
@@ -423,9 +833,17 @@ mod tests {
         };
 
         let message_bytes: BytesMut = message.try_into().unwrap();
-        let paf = writer
-            .write((stream_name.to_string(), 0), message_bytes.into())
-            .await;
+        let WriteResult::Published(paf) = writer
+            .write(
+                (stream_name.to_string(), 0),
+                message_bytes.into(),
+                HeaderMap::new(),
+            )
+            .await
+            .unwrap()
+        else {
+            panic!("message should not be discarded");
+        };
         assert!(paf.await.is_ok());
 
         context.delete_stream(stream_name).await.unwrap();
@@ -484,7 +902,11 @@ mod tests {
 
         let message_bytes: BytesMut = message.try_into().unwrap();
         let result = writer
-            .blocking_write((stream_name.to_string(), 0), message_bytes.into())
+            .blocking_write(
+                (stream_name.to_string(), 0),
+                message_bytes.into(),
+                HeaderMap::new(),
+            )
             .await;
         assert!(result.is_ok());
 
@@ -549,9 +971,17 @@ mod tests {
                 headers: HashMap::new(),
             };
             let message_bytes: BytesMut = message.try_into().unwrap();
-            let paf = writer
-                .write((stream_name.to_string(), 0), message_bytes.into())
-                .await;
+            let WriteResult::Published(paf) = writer
+                .write(
+                    (stream_name.to_string(), 0),
+                    message_bytes.into(),
+                    HeaderMap::new(),
+                )
+                .await
+                .unwrap()
+            else {
+                panic!("message should not be discarded");
+            };
             result_receivers.push(paf);
         }
 
@@ -570,9 +1000,17 @@ mod tests {
             headers: HashMap::new(),
         };
         let message_bytes: BytesMut = message.try_into().unwrap();
-        let paf = writer
-            .write((stream_name.to_string(), 0), message_bytes.into())
-            .await;
+        let WriteResult::Published(paf) = writer
+            .write(
+                (stream_name.to_string(), 0),
+                message_bytes.into(),
+                HeaderMap::new(),
+            )
+            .await
+            .unwrap()
+        else {
+            panic!("message should not be discarded");
+        };
         result_receivers.push(paf);
 
         // Cancel the token to exit the retry loop
@@ -597,6 +1035,554 @@ mod tests {
         context.delete_stream(stream_name).await.unwrap();
     }
 
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_write_discard_latest_when_full() {
+        let cln_token = CancellationToken::new();
+        let js_url = "localhost:4222";
+        // Create JetStream context
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_discard_latest";
+        let _stream = context
+            .get_or_create_stream(stream::Config {
+                name: stream_name.into(),
+                subjects: vec![stream_name.into()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let _consumer = context
+            .create_consumer_on_stream(
+                consumer::Config {
+                    name: Some(stream_name.to_string()),
+                    ack_policy: consumer::AckPolicy::Explicit,
+                    ..Default::default()
+                },
+                stream_name,
+            )
+            .await
+            .unwrap();
+
+        let writer = JetstreamWriter::new(
+            vec![(stream_name.to_string(), 0)],
+            BufferWriterConfig {
+                buffer_full_strategy: BufferFullStrategy::DiscardLatest,
+                // make sure the background task does not reset the is_full flag
+                refresh_interval: Duration::from_secs(60),
+                ..Default::default()
+            },
+            context.clone(),
+            cln_token.clone(),
+        );
+
+        // let the first refresh of the buffer usage go through before simulating a full buffer
+        sleep(Duration::from_millis(100)).await;
+        writer
+            .is_full
+            .get(stream_name)
+            .unwrap()
+            .store(true, Ordering::Relaxed);
+
+        let message = Message {
+            keys: vec!["key_0".to_string()],
+            value: "message 0".as_bytes().to_vec().into(),
+            offset: None,
+            event_time: Utc::now(),
+            id: MessageID {
+                vertex_name: "vertex".to_string(),
+                offset: "offset_0".to_string(),
+                index: 0,
+            },
+            headers: HashMap::new(),
+        };
+        let message_bytes: BytesMut = message.try_into().unwrap();
+
+        // write should return immediately instead of retrying till the buffer has space
+        let result = tokio::time::timeout(
+            Duration::from_secs(1),
+            writer.write(
+                (stream_name.to_string(), 0),
+                message_bytes.into(),
+                HeaderMap::new(),
+            ),
+        )
+        .await
+        .expect("write should not block when the strategy is DiscardLatest");
+        assert!(matches!(result, Ok(WriteResult::Discarded)));
+
+        cln_token.cancel();
+        context.delete_stream(stream_name).await.unwrap();
+    }
+
+    /// records the events of the writer.
+    #[derive(Debug, Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl RecordingObserver {
+        fn count(&self, event: &str) -> usize {
+            let events = self.events.lock().unwrap();
+            events.iter().filter(|e| e.starts_with(event)).count()
+        }
+
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    impl WriterObserver for RecordingObserver {
+        fn on_write(&self, stream: &Stream, bytes: usize) {
+            self.record(format!("write:{}:{}", stream.0, bytes));
+        }
+
+        fn on_retry(&self, stream: &Stream) {
+            self.record(format!("retry:{}", stream.0));
+        }
+
+        fn on_discard(&self, stream: &Stream) {
+            self.record(format!("discard:{}", stream.0));
+        }
+
+        fn on_buffer_full(&self, stream: &Stream) {
+            self.record(format!("buffer_full:{}", stream.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_discard_grace() {
+        let client = disconnected_client().await;
+        let context = jetstream::new(client);
+        let stream = ("test_write_discard_grace".to_string(), 0);
+        let cln_token = CancellationToken::new();
+
+        let writer = JetstreamWriter::new(
+            vec![stream.clone()],
+            BufferWriterConfig::builder()
+                .streams(vec![stream.clone()])
+                .buffer_full_strategy(BufferFullStrategy::DiscardLatest)
+                .discard_grace(Duration::from_millis(200))
+                // make sure the background task does not reset the is_full flag
+                .refresh_interval(Duration::from_secs(60))
+                .retry_max_interval(Duration::from_millis(10))
+                .build(),
+            context,
+            cln_token.clone(),
+        );
+        // let the first refresh of the buffer usage go through before simulating a full buffer
+        sleep(Duration::from_millis(100)).await;
+        let is_full = Arc::clone(&writer.is_full[&stream.0]);
+
+        // the buffer frees up within the grace, so the message is written
+        is_full.store(true, Ordering::Relaxed);
+        tokio::spawn({
+            let is_full = Arc::clone(&is_full);
+            async move {
+                sleep(Duration::from_millis(50)).await;
+                is_full.store(false, Ordering::Relaxed);
+            }
+        });
+        let result = writer
+            .write(stream.clone(), vec![0; 16], HeaderMap::new())
+            .await;
+        assert!(matches!(result, Ok(WriteResult::Published(_))));
+
+        // the buffer stays full, so the message is discarded once the grace elapses
+        is_full.store(true, Ordering::Relaxed);
+        let start = Instant::now();
+        let result = writer
+            .write(stream.clone(), vec![0; 16], HeaderMap::new())
+            .await;
+        assert!(matches!(result, Ok(WriteResult::Discarded)));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        cln_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_writer_observer() {
+        let client = disconnected_client().await;
+        let context = jetstream::new(client);
+        let stream = ("test_writer_observer".to_string(), 0);
+        let cln_token = CancellationToken::new();
+
+        let new_writer = |buffer_full_strategy, observer: Arc<RecordingObserver>| {
+            let writer = JetstreamWriter::with_observer(
+                vec![stream.clone()],
+                BufferWriterConfig {
+                    buffer_full_strategy,
+                    // make sure the background task does not reset the is_full flag
+                    refresh_interval: Duration::from_secs(60),
+                    retry_max_interval: Duration::from_millis(10),
+                    ..Default::default()
+                },
+                context.clone(),
+                cln_token.clone(),
+                observer,
+            );
+            writer.is_full[&stream.0].store(true, Ordering::Relaxed);
+            writer
+        };
+
+        // the write is retried while the buffer is full
+        let observer = Arc::new(RecordingObserver::default());
+        let writer = new_writer(BufferFullStrategy::RetryUntilSuccess, observer.clone());
+        let result = tokio::time::timeout(
+            Duration::from_millis(100),
+            writer.write(stream.clone(), vec![0; 16], HeaderMap::new()),
+        )
+        .await;
+        assert!(
+            result.is_err(),
+            "write should retry while the buffer is full"
+        );
+        // notified once although the write is retried many times
+        assert_eq!(observer.count("buffer_full:test_writer_observer"), 1);
+        assert!(observer.count("retry:test_writer_observer") > 1);
+        assert_eq!(observer.count("discard"), 0);
+        assert_eq!(observer.count("write"), 0);
+
+        // the message is discarded if the buffer is full
+        let observer = Arc::new(RecordingObserver::default());
+        let writer = new_writer(BufferFullStrategy::DiscardLatest, observer.clone());
+        let result = writer
+            .write(stream.clone(), vec![0; 16], HeaderMap::new())
+            .await;
+        assert!(matches!(result, Ok(WriteResult::Discarded)));
+        assert_eq!(observer.count("buffer_full:test_writer_observer"), 1);
+        assert_eq!(observer.count("discard:test_writer_observer"), 1);
+
+        // and written once there is space, after the first refresh of the buffer usage
+        sleep(Duration::from_millis(100)).await;
+        writer.is_full[&stream.0].store(false, Ordering::Relaxed);
+        let result = writer
+            .write(stream.clone(), vec![0; 16], HeaderMap::new())
+            .await;
+        assert!(matches!(result, Ok(WriteResult::Published(_))));
+        assert_eq!(observer.count("write:test_writer_observer:16"), 1);
+        assert_eq!(observer.count("retry"), 0);
+
+        cln_token.cancel();
+    }
+
+    #[test]
+    fn test_isb_write_error() {
+        let stream = "stream-0".to_string();
+        let cases = [
+            (
+                IsbWriteError::BufferFull(stream.clone()),
+                "Buffer of stream stream-0 is full",
+            ),
+            (
+                IsbWriteError::Discarded(stream.clone()),
+                "Message discarded, buffer of stream stream-0 is full",
+            ),
+            (
+                IsbWriteError::PublishFailed {
+                    stream: stream.clone(),
+                    reason: "connection closed".to_string(),
+                },
+                "Failed to publish to stream stream-0 - connection closed",
+            ),
+            (
+                IsbWriteError::Timeout(stream.clone()),
+                "Timed out waiting for the publish ack from stream stream-0",
+            ),
+            (
+                IsbWriteError::StreamNotFound(stream.clone()),
+                "Stream stream-0 not found",
+            ),
+            (
+                IsbWriteError::DryRun(stream.clone()),
+                "Message not published to stream stream-0, dry run",
+            ),
+        ];
+        for (error, expected) in cases {
+            assert_eq!(error.to_string(), expected);
+            let Error::ISB(message) = Error::from(error) else {
+                panic!("write errors should be mapped to ISB errors");
+            };
+            assert_eq!(message, expected);
+        }
+
+        assert_eq!(
+            WriteResult::Discarded
+                .into_paf(&(stream.clone(), 0))
+                .unwrap_err(),
+            IsbWriteError::Discarded(stream)
+        );
+    }
+
+    #[test]
+    fn test_fault_injector() {
+        let config = FaultConfig {
+            fail_fraction: 0.25,
+            seed: 42,
+        };
+        let injector = FaultInjector::new(config);
+        let failures = (0..10_000).filter(|_| injector.should_fail()).count();
+        assert!(
+            (2300..=2700).contains(&failures),
+            "about 25% of the publishes should fail, got {failures}"
+        );
+
+        // the failures are reproducible with the same seed
+        let run = |injector: FaultInjector| -> Vec<bool> {
+            (0..100).map(|_| injector.should_fail()).collect()
+        };
+        assert_eq!(
+            run(FaultInjector::new(config)),
+            run(FaultInjector::new(config))
+        );
+
+        // the fraction is clamped
+        let injector = FaultInjector::new(FaultConfig {
+            fail_fraction: 1.5,
+            seed: 42,
+        });
+        assert!(injector.should_fail());
+    }
+
+    #[tokio::test]
+    async fn test_write_with_fault_injection() {
+        let client = disconnected_client().await;
+        let context = jetstream::new(client);
+        let stream = ("test_write_with_fault_injection".to_string(), 0);
+        let cln_token = CancellationToken::new();
+
+        let observer = Arc::new(RecordingObserver::default());
+        let writer = JetstreamWriter::with_observer(
+            vec![stream.clone()],
+            BufferWriterConfig::builder()
+                // make sure the background task does not reset the is_full flag
+                .refresh_interval(Duration::from_secs(60))
+                .retry_interval(Duration::from_millis(1))
+                .retry_max_interval(Duration::from_millis(1))
+                .fault_injection(Some(FaultConfig {
+                    fail_fraction: 0.3,
+                    seed: 7,
+                }))
+                .build(),
+            context,
+            cln_token.clone(),
+            observer.clone(),
+        );
+
+        // every write eventually succeeds by retrying the injected failures
+        let writes = 200;
+        for _ in 0..writes {
+            let result = tokio::time::timeout(
+                Duration::from_secs(1),
+                writer.write(stream.clone(), vec![0; 16], HeaderMap::new()),
+            )
+            .await
+            .expect("write should succeed after retrying");
+            assert!(matches!(result, Ok(WriteResult::Published(_))));
+        }
+        assert_eq!(observer.count("write"), writes);
+
+        // each attempt fails with the configured probability
+        let failures = observer.count("retry");
+        let fail_fraction = failures as f64 / (failures + writes) as f64;
+        assert!(
+            (0.2..=0.4).contains(&fail_fraction),
+            "about 30% of the publishes should fail, got {fail_fraction}"
+        );
+        assert_eq!(observer.count("buffer_full"), 0);
+
+        cln_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_write_retry_deadline() {
+        // we never talk to the server in this test.
+        let client = disconnected_client().await;
+        let context = jetstream::new(client);
+        let stream = ("test_write_retry_deadline".to_string(), 0);
+        let cln_token = CancellationToken::new();
+
+        let new_writer = |retry_deadline| {
+            let writer = JetstreamWriter::new(
+                vec![stream.clone()],
+                BufferWriterConfig {
+                    // make sure the background task does not reset the is_full flag
+                    refresh_interval: Duration::from_secs(60),
+                    retry_max_interval: Duration::from_millis(10),
+                    retry_deadline,
+                    ..Default::default()
+                },
+                context.clone(),
+                cln_token.clone(),
+            );
+            // the buffer is always full
+            writer.is_full[&stream.0].store(true, Ordering::Relaxed);
+            writer
+        };
+
+        // the write gives up once the deadline is exceeded
+        let writer = new_writer(Some(Duration::from_millis(50)));
+        let start = Instant::now();
+        let result = tokio::time::timeout(
+            Duration::from_secs(1),
+            writer.write(stream.clone(), vec![0; 16], HeaderMap::new()),
+        )
+        .await
+        .expect("write should give up after the deadline");
+        assert_eq!(
+            result.unwrap_err(),
+            IsbWriteError::BufferFull("test_write_retry_deadline".to_string())
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // without a deadline, the write is retried forever
+        let writer = new_writer(None);
+        let result = tokio::time::timeout(
+            Duration::from_millis(200),
+            writer.write(stream.clone(), vec![0; 16], HeaderMap::new()),
+        )
+        .await;
+        assert!(
+            result.is_err(),
+            "write should retry while the buffer is full"
+        );
+
+        cln_token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_flush_timeout() {
+        // the client never connects, so the flush cannot complete.
+        let client = disconnected_client().await;
+        let context = jetstream::new(client);
+        let stream = ("test_flush_timeout".to_string(), 0);
+        let cln_token = CancellationToken::new();
+
+        let writer = JetstreamWriter::new(
+            vec![stream],
+            BufferWriterConfig {
+                refresh_interval: Duration::from_secs(60),
+                flush_timeout: Duration::from_millis(50),
+                ..Default::default()
+            },
+            context,
+            cln_token.clone(),
+        );
+
+        let start = Instant::now();
+        assert!(writer.flush().await.is_err());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let paf_resolver = PafResolver::new(10, writer);
+        assert!(paf_resolver.flush().await.is_err());
+
+        cln_token.cancel();
+    }
+
+    #[cfg(feature = "nats-tests")]
+    #[tokio::test]
+    async fn test_flush() {
+        let cln_token = CancellationToken::new();
+        let js_url = "localhost:4222";
+        // Create JetStream context
+        let client = async_nats::connect(js_url).await.unwrap();
+        let context = jetstream::new(client);
+
+        let stream_name = "test_flush";
+        let _stream = context
+            .get_or_create_stream(stream::Config {
+                name: stream_name.into(),
+                subjects: vec![stream_name.into()],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        let writer = JetstreamWriter::new(
+            vec![(stream_name.to_string(), 0)],
+            Default::default(),
+            context.clone(),
+            cln_token.clone(),
+        );
+
+        // the PAFs are not awaited, flush makes sure the messages are written
+        let mut pafs = vec![];
+        for i in 0..10 {
+            let message = Message {
+                keys: vec![format!("key_{}", i)],
+                value: format!("message {}", i).as_bytes().to_vec().into(),
+                offset: None,
+                event_time: Utc::now(),
+                id: MessageID {
+                    vertex_name: "vertex".to_string(),
+                    offset: format!("offset_{}", i),
+                    index: i,
+                },
+                headers: HashMap::new(),
+            };
+            let message_bytes: BytesMut = message.try_into().unwrap();
+            let WriteResult::Published(paf) = writer
+                .write(
+                    (stream_name.to_string(), 0),
+                    message_bytes.into(),
+                    HeaderMap::new(),
+                )
+                .await
+                .unwrap()
+            else {
+                panic!("message should not be discarded");
+            };
+            pafs.push(paf);
+        }
+        writer.flush().await.unwrap();
+
+        let mut stream = context.get_stream(stream_name).await.unwrap();
+        assert_eq!(stream.info().await.unwrap().state.messages, 10);
+        for paf in pafs {
+            assert!(paf.await.is_ok());
+        }
+
+        cln_token.cancel();
+        context.delete_stream(stream_name).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_usage() {
+        // we never talk to the server in this test.
+        let client = async_nats::ConnectOptions::new()
+            .retry_on_initial_connect()
+            .connect("localhost:4222")
+            .await
+            .unwrap();
+        let context = jetstream::new(client);
+
+        let config = BufferWriterConfig {
+            max_length: 100,
+            per_stream_max_length: HashMap::from([("stream-1".to_string(), 50)]),
+            ..Default::default()
+        };
+        let cln_token = CancellationToken::new();
+        let writer = JetstreamWriter::new(
+            vec![("stream-0".to_string(), 0), ("stream-1".to_string(), 1)],
+            config,
+            context,
+            cln_token.clone(),
+        );
+
+        // simulate the pending counts of the last refresh
+        writer.pending["stream-0"].store(25, Ordering::Relaxed);
+        writer.pending["stream-1"].store(40, Ordering::Relaxed);
+
+        let usage = writer.usage();
+        assert_eq!(usage.len(), 2);
+        assert_eq!(usage["stream-0"], 0.25);
+        assert_eq!(usage["stream-1"], 0.8);
+
+        cln_token.cancel();
+    }
+
     #[cfg(feature = "nats-tests")]
     #[tokio::test]
     async fn test_fetch_buffer_usage() {
@@ -653,12 +1639,13 @@ mod tests {
         }
 
         // Fetch buffer usage
-        let (soft_usage, _) =
+        let (pending, soft_usage, _) =
             JetstreamWriter::fetch_buffer_usage(context.clone(), stream_name, max_length)
                 .await
                 .unwrap();
 
         // Verify the buffer usage metrics
+        assert_eq!(pending, 80);
         assert_eq!(soft_usage, 0.8);
         assert_eq!(soft_usage, 0.8);
 
@@ -749,6 +1736,8 @@ mod tests {
                 .unwrap(),
             "Buffer should be full after publishing messages"
         );
+        // the usage has reached the usage limit
+        assert_eq!(*writer.pressure().borrow(), 1.0);
 
         // Clean up
         context.delete_stream(stream_name).await.unwrap();
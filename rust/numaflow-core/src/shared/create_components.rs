@@ -3,6 +3,7 @@ use std::time::Duration;
 use numaflow_pb::clients::sink::sink_client::SinkClient;
 use numaflow_pb::clients::source::source_client::SourceClient;
 use numaflow_pb::clients::sourcetransformer::source_transform_client::SourceTransformClient;
+use tokio::sync::watch;
 use tokio_util::sync::CancellationToken;
 use tonic::transport::Channel;
 
@@ -12,7 +13,6 @@ use crate::config::components::transformer::TransformerConfig;
 use crate::shared::grpc;
 use crate::shared::server_info::{sdk_server_info, ContainerType};
 use crate::sink::{SinkClientType, SinkWriter, SinkWriterBuilder};
-use crate::source::generator::new_generator;
 use crate::source::pulsar::new_pulsar_source;
 use crate::source::user_defined::new_source;
 use crate::source::Source;
@@ -182,17 +182,22 @@ pub async fn create_transformer(
     Ok((None, None))
 }
 
-/// Creates a source type based on the configuration
+/// Creates a source type based on the configuration. The generator is throttled by the
+/// backpressure of the downstream buffers, if any.
 pub async fn create_source(
     batch_size: usize,
     read_timeout: Duration,
     source_config: &SourceConfig,
+    backpressure: Option<watch::Receiver<f64>>,
     cln_token: CancellationToken,
 ) -> error::Result<(Source, Option<SourceClient<Channel>>)> {
     match &source_config.source_type {
         SourceType::Generator(generator_config) => {
-            let (generator_read, generator_ack, generator_lag) =
-                new_generator(generator_config.clone(), batch_size)?;
+            let (mut generator_read, generator_ack, generator_lag) =
+                generator_config.clone().try_into_generator(batch_size)?;
+            if let Some(backpressure) = backpressure {
+                generator_read = generator_read.with_backpressure(backpressure);
+            }
             Ok((
                 Source::new(
                     batch_size,
@@ -8,7 +8,6 @@ use crate::config::components::metrics::MetricsConfig;
 use crate::metrics::{
     start_metrics_https_server, PendingReader, PendingReaderBuilder, UserDefinedContainerState,
 };
-use crate::source::Source;
 
 /// Starts the metrics server
 pub(crate) async fn start_metrics_server(
@@ -29,11 +28,11 @@ pub(crate) async fn start_metrics_server(
 }
 
 /// Creates a pending reader
-pub(crate) async fn create_pending_reader(
+pub(crate) async fn create_pending_reader<L>(
     metrics_config: &MetricsConfig,
-    lag_reader_grpc_client: Source,
-) -> PendingReader {
-    PendingReaderBuilder::new(lag_reader_grpc_client)
+    lag_reader: L,
+) -> PendingReader<L> {
+    PendingReaderBuilder::new(lag_reader)
         .lag_checking_interval(Duration::from_secs(
             metrics_config.lag_check_interval_in_secs.into(),
         ))
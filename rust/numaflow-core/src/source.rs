@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use numaflow_pulsar::source::PulsarSource;
 use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinHandle;
@@ -39,8 +41,17 @@ pub(crate) trait SourceReader {
     /// Name of the source.
     fn name(&self) -> &'static str;
 
+    /// reads a batch of messages. A source which has stopped (e.g., a bounded source) should return
+    /// an empty batch instead of an error or panic, so that the shutdown is not disruptive, and
+    /// report it through [SourceReader::is_exhausted].
     async fn read(&mut self) -> Result<Vec<Message>>;
 
+    /// returns true once the source has stopped for good and no more messages are to be read. An
+    /// empty batch from a source which is not exhausted only means that it is idle.
+    fn is_exhausted(&self) -> bool {
+        false
+    }
+
     #[allow(dead_code)]
     /// number of partitions processed by this source.
     fn partitions(&self) -> Vec<u16>;
@@ -50,6 +61,46 @@ pub(crate) trait SourceReader {
 pub(crate) trait SourceAcker {
     /// acknowledge an offset. The implementor might choose to do it in an asynchronous way.
     async fn ack(&mut self, _: Vec<Offset>) -> Result<()>;
+
+    /// acknowledge the offsets and return the outcome of each offset (in the same order), so that
+    /// the caller can retry only the failed ones. By default, the offsets are acked using
+    /// [SourceAcker::ack] and all of them are considered successful if it succeeds.
+    async fn ack_with_results(&mut self, offsets: Vec<Offset>) -> Result<Vec<AckResult>> {
+        let count = offsets.len();
+        self.ack(offsets).await?;
+        Ok(vec![Ok(()); count])
+    }
+
+    /// negatively acknowledge the offsets, so that they are redelivered after the delay (or
+    /// immediately if there is no delay). It is a no-op by default, for the sources which do not
+    /// support redelivery.
+    async fn nack(&mut self, _offsets: Vec<Offset>, _delay: Option<Duration>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// AckObserver is notified after a batch of offsets has been acknowledged by a [SourceAcker], so
+/// that the ack latency can be tracked. The callback is a no-op by default.
+pub(crate) trait AckObserver: std::fmt::Debug + Send + Sync {
+    /// `count` offsets have been acknowledged and the ack took `duration`.
+    fn on_ack(&self, _count: usize, _duration: Duration) {}
+}
+
+/// NoopAckObserver ignores all the acks.
+#[derive(Debug)]
+pub(crate) struct NoopAckObserver;
+
+impl AckObserver for NoopAckObserver {}
+
+/// Outcome of acknowledging a single offset.
+pub(crate) type AckResult = std::result::Result<(), AckError>;
+
+/// AckError is the failure to acknowledge a single offset.
+#[derive(thiserror::Error, Debug, Clone)]
+#[error("Failed to ack offset {offset} - {reason}")]
+pub(crate) struct AckError {
+    pub(crate) offset: Offset,
+    pub(crate) reason: String,
 }
 
 pub(crate) enum SourceType {
@@ -71,13 +122,18 @@ enum ActorMessage {
     Name {
         respond_to: oneshot::Sender<&'static str>,
     },
+    /// `None` is returned once the source is exhausted.
     Read {
-        respond_to: oneshot::Sender<Result<Vec<Message>>>,
+        respond_to: oneshot::Sender<Result<Option<Vec<Message>>>>,
     },
     Ack {
         respond_to: oneshot::Sender<Result<()>>,
         offsets: Vec<Offset>,
     },
+    Nack {
+        respond_to: oneshot::Sender<Result<()>>,
+        offsets: Vec<Offset>,
+    },
     Pending {
         respond_to: oneshot::Sender<Result<Option<usize>>>,
     },
@@ -112,16 +168,36 @@ where
                 let _ = respond_to.send(name);
             }
             ActorMessage::Read { respond_to } => {
-                let msgs = self.reader.read().await;
+                let msgs = self.reader.read().await.map(|msgs| {
+                    // idle sources return empty batches too, only the exhausted ones have stopped
+                    (!msgs.is_empty() || !self.reader.is_exhausted()).then_some(msgs)
+                });
                 let _ = respond_to.send(msgs);
             }
             ActorMessage::Ack {
                 respond_to,
                 offsets,
             } => {
-                let ack = self.acker.ack(offsets).await;
+                // the first offset which could not be acked fails the whole ack
+                let ack = self
+                    .acker
+                    .ack_with_results(offsets)
+                    .await
+                    .and_then(|results| {
+                        results
+                            .into_iter()
+                            .collect::<std::result::Result<(), _>>()
+                            .map_err(|e| crate::error::Error::Source(e.to_string()))
+                    });
                 let _ = respond_to.send(ack);
             }
+            ActorMessage::Nack {
+                respond_to,
+                offsets,
+            } => {
+                let nack = self.acker.nack(offsets, None).await;
+                let _ = respond_to.send(nack);
+            }
             ActorMessage::Pending { respond_to } => {
                 let pending = self.lag_reader.pending().await;
                 let _ = respond_to.send(pending);
@@ -178,8 +254,9 @@ impl Source {
         }
     }
 
-    /// read messages from the source by communicating with the read actor.
-    async fn read(source_handle: mpsc::Sender<ActorMessage>) -> Result<Vec<Message>> {
+    /// read messages from the source by communicating with the read actor, `None` is returned once
+    /// the source is exhausted.
+    async fn read(source_handle: mpsc::Sender<ActorMessage>) -> Result<Option<Vec<Message>>> {
         let (sender, receiver) = oneshot::channel();
         let msg = ActorMessage::Read { respond_to: sender };
         // Ignore send errors. If send fails, so does the recv.await below. There's no reason
@@ -205,6 +282,22 @@ impl Source {
             .map_err(|e| crate::error::Error::ActorPatternRecv(e.to_string()))?
     }
 
+    /// nack the offsets by communicating with the ack actor, so that they are redelivered right
+    /// away if the source supports it.
+    async fn nack(source_handle: mpsc::Sender<ActorMessage>, offsets: Vec<Offset>) -> Result<()> {
+        let (sender, receiver) = oneshot::channel();
+        let msg = ActorMessage::Nack {
+            respond_to: sender,
+            offsets,
+        };
+        // Ignore send errors. If send fails, so does the recv.await below. There's no reason
+        // to check for the same failure twice.
+        let _ = source_handle.send(msg).await;
+        receiver
+            .await
+            .map_err(|e| crate::error::Error::ActorPatternRecv(e.to_string()))?
+    }
+
     /// get the pending messages count by communicating with the pending actor.
     pub(crate) async fn pending(&self) -> Result<Option<usize>> {
         let (sender, receiver) = oneshot::channel();
@@ -259,7 +352,15 @@ impl Source {
 
                 let read_start_time = tokio::time::Instant::now();
                 let messages = match Self::read(source_handle.clone()).await {
-                    Ok(messages) => messages,
+                    Ok(Some(messages)) => messages,
+                    Ok(None) => {
+                        // nothing more to read, we stay idle (instead of reading in a loop) till
+                        // we are cancelled so that the shutdown is not disruptive.
+                        info!("Source is exhausted, waiting for the cancellation.");
+                        drop(permit);
+                        cln_token.cancelled().await;
+                        return Ok(());
+                    }
                     Err(e) => {
                         error!("Error while reading messages: {:?}", e);
                         return Err(e);
@@ -335,7 +436,8 @@ impl Source {
         Ok((ReceiverStream::new(messages_rx), handle))
     }
 
-    /// Listens to the oneshot receivers and invokes ack on the source for the offsets that are acked.
+    /// Listens to the oneshot receivers and invokes ack on the source for the offsets that are acked,
+    /// and nack for the ones that are NAK'd (e.g., failed to be processed).
     async fn invoke_ack(
         e2e_start_time: time::Instant,
         source_handle: mpsc::Sender<ActorMessage>,
@@ -343,6 +445,7 @@ impl Source {
     ) -> Result<()> {
         let n = ack_rx_batch.len();
         let mut offsets_to_ack = Vec::with_capacity(n);
+        let mut offsets_to_nack = vec![];
 
         for (offset, oneshot_rx) in ack_rx_batch {
             match oneshot_rx.await {
@@ -351,6 +454,7 @@ impl Source {
                 }
                 Ok(ReadAck::Nak) => {
                     error!("Nak received for offset: {:?}", offset);
+                    offsets_to_nack.push(offset);
                 }
                 Err(e) => {
                     error!(
@@ -361,6 +465,10 @@ impl Source {
             }
         }
 
+        if !offsets_to_nack.is_empty() {
+            Self::nack(source_handle.clone(), offsets_to_nack).await?;
+        }
+
         let start = time::Instant::now();
         if !offsets_to_ack.is_empty() {
             Self::ack(source_handle, offsets_to_ack).await?;
@@ -404,23 +512,37 @@ impl Source {
     }
 }
 
+/// the pending messages of the source are read through the actor, so that the [Source] can be
+/// used by the [crate::metrics::PendingReader].
+impl LagReader for Source {
+    async fn pending(&mut self) -> Result<Option<usize>> {
+        Source::pending(self).await
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
     use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
     use std::time::Duration;
 
+    use bytes::Bytes;
     use chrono::Utc;
-    use futures::StreamExt;
+    use futures::{FutureExt, StreamExt};
     use numaflow::source;
     use numaflow::source::{Message, Offset, SourceReadRequest};
     use numaflow_pb::clients::source::source_client::SourceClient;
     use tokio::sync::mpsc::Sender;
+    use tokio::sync::{mpsc, oneshot};
     use tokio_util::sync::CancellationToken;
 
+    use crate::config::components::source::GeneratorConfig;
+    use crate::message::ReadAck;
     use crate::shared::grpc::create_rpc_channel;
+    use crate::source::generator::new_generator;
     use crate::source::user_defined::new_source;
-    use crate::source::{Source, SourceType};
+    use crate::source::{Source, SourceAcker, SourceActor, SourceType};
 
     struct SimpleSource {
         num: usize,
@@ -547,4 +669,137 @@ mod tests {
         let _ = shutdown_tx.send(());
         server_handle.await.unwrap();
     }
+
+    struct CountingAcker {
+        acked: usize,
+    }
+
+    impl SourceAcker for CountingAcker {
+        async fn ack(&mut self, offsets: Vec<crate::message::Offset>) -> crate::Result<()> {
+            self.acked += offsets.len();
+            Ok(())
+        }
+    }
+
+    /// RecordingAcker records the acked and the NAK'd offsets.
+    #[derive(Clone, Default)]
+    struct RecordingAcker {
+        acked: Arc<Mutex<Vec<crate::message::Offset>>>,
+        nacked: Arc<Mutex<Vec<crate::message::Offset>>>,
+    }
+
+    impl SourceAcker for RecordingAcker {
+        async fn ack(&mut self, offsets: Vec<crate::message::Offset>) -> crate::Result<()> {
+            self.acked.lock().unwrap().extend(offsets);
+            Ok(())
+        }
+
+        async fn nack(
+            &mut self,
+            offsets: Vec<crate::message::Offset>,
+            _delay: Option<Duration>,
+        ) -> crate::Result<()> {
+            self.nacked.lock().unwrap().extend(offsets);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_source_nack() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("hello"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let (src_read, _, lag_reader) = new_generator(cfg, 5).unwrap();
+        let acker = RecordingAcker::default();
+        let (sender, receiver) = mpsc::channel(5);
+        let mut actor = SourceActor::new(receiver, src_read, acker.clone(), lag_reader);
+        tokio::spawn(async move {
+            while let Some(msg) = actor.receiver.recv().await {
+                actor.handle_message(msg).await;
+            }
+        });
+
+        let offsets: Vec<crate::message::Offset> = (0..3)
+            .map(|i| {
+                crate::message::Offset::String(crate::message::StringOffset::new(
+                    format!("offset-{}", i),
+                    0,
+                ))
+            })
+            .collect();
+        let mut ack_batch = vec![];
+        for (offset, ack) in offsets
+            .iter()
+            .zip([ReadAck::Ack, ReadAck::Nak, ReadAck::Ack])
+        {
+            let (ack_tx, ack_rx) = oneshot::channel();
+            ack_tx.send(ack).unwrap();
+            ack_batch.push((offset.clone(), ack_rx));
+        }
+
+        // the NAK'd offsets are handed over to the acker to be redelivered
+        Source::invoke_ack(tokio::time::Instant::now(), sender, ack_batch)
+            .await
+            .unwrap();
+        assert_eq!(
+            *acker.acked.lock().unwrap(),
+            vec![offsets[0].clone(), offsets[2].clone()]
+        );
+        assert_eq!(*acker.nacked.lock().unwrap(), vec![offsets[1].clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_default_ack_with_results() {
+        let mut acker = CountingAcker { acked: 0 };
+        let offsets = (0..3)
+            .map(|i| {
+                crate::message::Offset::String(crate::message::StringOffset::new(
+                    format!("offset-{}", i),
+                    0,
+                ))
+            })
+            .collect();
+
+        let results = acker.ack_with_results(offsets).await.unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(acker.acked, 3);
+    }
+
+    #[tokio::test]
+    async fn test_source_bounded_generator_eof() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("hello"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            max_messages: Some(3),
+            ..Default::default()
+        };
+        let (src_read, src_ack, lag_reader) = new_generator(cfg, 5).unwrap();
+        let source = Source::new(5, SourceType::Generator(src_read, src_ack, lag_reader));
+
+        let cln_token = CancellationToken::new();
+        let (mut stream, handle) = source.streaming_read(cln_token.clone()).unwrap();
+        for _ in 0..3 {
+            let message = stream.next().await.unwrap();
+            assert_eq!(message.message.value, "hello".as_bytes());
+            message.ack.send(ReadAck::Ack).unwrap();
+        }
+
+        // the generator has stopped, the source should keep running (and not panic) till it
+        // is cancelled, without emitting anything.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!handle.is_finished());
+        assert!(stream.next().now_or_never().is_none());
+
+        // the actor reports the end of the source instead of an empty batch
+        let read = Source::read(source.sender.clone()).await.unwrap();
+        assert!(read.is_none());
+
+        cln_token.cancel();
+        handle.await.unwrap().unwrap();
+    }
 }
@@ -1,17 +1,58 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::task::AtomicWaker;
 use futures::StreamExt;
+use tokio::sync::watch;
 
 use crate::config::components::source::GeneratorConfig;
-use crate::message::{Message, Offset};
+use crate::error::Error;
+use crate::message::{Message, MessageID, Offset};
 use crate::reader;
 use crate::source;
+use crate::source::generator::clock::{Clock, SystemClock};
+
+/// GeneratedRecord is the protobuf message generated for [PayloadFormat::Protobuf].
+///
+/// [PayloadFormat::Protobuf]: crate::config::components::source::PayloadFormat::Protobuf
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct GeneratedRecord {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<GeneratedEntry>,
+}
+
+/// GeneratedEntry is a key/value entry of the [GeneratedRecord].
+#[derive(Clone, PartialEq, prost::Message)]
+pub(crate) struct GeneratedEntry {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(int64, tag = "2")]
+    pub value: i64,
+}
+
+/// Clock of the generator, can be replaced to control the time in the tests.
+pub(crate) mod clock;
+
+/// Replay of a recorded sequence of payloads.
+#[cfg(test)]
+pub(crate) mod replay;
+
+/// Minimum supported unit of time-period (`duration`) of the generator, the ticks are not reliable
+/// below it.
+pub(crate) const MIN_DURATION: Duration = Duration::from_millis(10);
+
+/// Header set on the heartbeat messages emitted while the generator is idle, see
+/// [GeneratorConfig::idle_heartbeat].
+pub(crate) const HEARTBEAT_HEADER: &str = "heartbeat";
 
 /// Stream Generator returns a set of messages for every `.next` call. It will throttle itself if
 /// the call exceeds the RPU. It will return a max (batch size, RPU) till the quota for that unit of
 /// time is over. If `.next` is called after the quota is over, it will park itself so that it won't
 /// return more than the RPU. Once parked, it will unpark itself and return as soon as the next poll
 /// happens.
-/// We skip the missed ticks because there is no point to give a burst, most likely that burst cannot
-/// be absorbed.
+/// We skip the missed ticks by default because there is no point to give a burst, most likely that
+/// burst cannot be absorbed. It can be changed via `missed_tick_behavior` for catch-up load tests.
 /// ```text
 ///       Ticks: |     1     |     2     |     3     |     4     |     5     |     6     |
 ///              =========================================================================> time
@@ -19,26 +60,51 @@ use crate::source;
 ///                2 batches   only 1 batch (no reread)      5         5           5
 ///                 
 /// ```
-/// NOTE: The minimum granularity of duration is 10ms.
+/// If `max_messages` is configured, the stream ends once that many messages have been generated.
+/// The generator can be paused and resumed, and its RPU can be changed on the fly using the
+/// [super::GeneratorControl]. The RPU can also be ramped up linearly using `ramp`. Ticks can be
+/// randomized using `tick_jitter` to simulate irregular arrivals. All the time keeping is done using
+/// the injected [Clock].
+/// NOTE: The minimum granularity of duration is [super::MIN_DURATION].
 mod stream_generator {
+    use std::collections::{HashMap, HashSet};
+    use std::ops::RangeInclusive;
     use std::pin::Pin;
+    use std::sync::Arc;
     use std::task::{Context, Poll};
     use std::time::Duration;
 
     use bytes::Bytes;
+    use chrono::{DateTime, Utc};
     use futures::Stream;
     use pin_project::pin_project;
-    use rand::Rng;
-    use tokio::time::MissedTickBehavior;
-    use tracing::warn;
+    use prost::Message as _;
+    use rand::distributions::Alphanumeric;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use tokio::sync::watch;
+    use tracing::{trace, warn};
+
+    use crate::config::components::source::{
+        Compression, EventTimeMode, EventTimeSkew, GeneratorConfig, JsonFieldType, KeySelection,
+        OffsetType, PayloadFormat, RampConfig,
+    };
+    use crate::config::VertexContext;
+    use crate::message::{
+        IntOffset, Message, MessageID, Offset, StringOffset, CONTENT_TYPE_HEADER, EXPIRES_AT_HEADER,
+    };
+    use crate::source::generator::clock::{Clock, Ticker};
+    #[cfg(test)]
+    use crate::source::generator::GeneratorState;
+    use crate::source::generator::{
+        GeneratedEntry, GeneratedRecord, GeneratorControl, GeneratorTrigger, HEARTBEAT_HEADER,
+    };
 
-    use crate::config::components::source::GeneratorConfig;
-    use crate::config::{get_vertex_name, get_vertex_replica};
-    use crate::message::{Message, MessageID, Offset, StringOffset};
     #[pin_project]
     pub(super) struct StreamGenerator {
-        /// the content generated by Generator.
-        content: Bytes,
+        /// the contents generated by Generator and the index of the next one to be used. The
+        /// contents are used round-robin, an empty list means the payload will be generated.
+        contents: (Vec<Bytes>, usize),
         /// requests per unit of time-period.
         rpu: usize,
         /// batch size per read
@@ -47,7 +113,7 @@ mod stream_generator {
         /// remaining = (rpu - used) for that time-period
         used: usize,
         /// const int data to be send in the payload if provided by the user.
-        /// If `content` is present, this will be ignored.
+        /// If `contents` is present, this will be ignored.
         /// This is a simple way used by users to test Reduce feature.
         value: Option<i64>,
         /// total message size to be created, will be padded with random u8. Size is
@@ -60,52 +126,394 @@ mod stream_generator {
         /// All possible keys are generated in the constructor.
         /// The index is incremented (treating key list as cyclic) when a message is generated.
         keys: (Vec<String>, usize),
-        #[pin]
-        tick: tokio::time::Interval,
+        /// how the next key is picked from `keys`.
+        key_selection: KeySelection,
+        /// synthetic event-time clock, the event-time for the next message and the step by
+        /// which it advances for every message. `None` means wall-clock event-time is used.
+        synthetic_clock: Option<(DateTime<Utc>, Duration)>,
+        /// total number of messages to be generated, the stream ends once it is reached.
+        max_messages: Option<usize>,
+        /// total number of messages generated so far.
+        emitted: usize,
+        /// type of the offsets to be assigned to the messages.
+        offset_type: OffsetType,
+        /// partitions along with the count of the messages assigned to each of them so far, and
+        /// the index of the partition of the next message. Partitions are used round-robin.
+        partitions: (Vec<(u16, u64)>, usize),
+        /// sequence to be used for the next [IntOffset].
+        next_seq: u64,
+        /// number of the heartbeats emitted so far, the heartbeats have their own offsets so that
+        /// they do not take up the sequences of the data.
+        heartbeats: u64,
+        /// pause/resume control for the generator.
+        control: GeneratorControl,
+        /// unit of time-period for the rpu.
+        unit: Duration,
+        /// each tick is randomized within `[unit - tick_jitter, unit + tick_jitter]`.
+        tick_jitter: Duration,
+        /// static headers to be set on every message.
+        headers: HashMap<String, String>,
+        /// header carrying the per message sequence number.
+        sequence_header: Option<String>,
+        /// header carrying the replica which generated the message.
+        replica_header: Option<String>,
+        /// time-to-live of the messages.
+        ttl: Option<Duration>,
+        /// range of the payload sizes and the rng used to draw the size of the next payload.
+        /// `None` means the payloads are not resized.
+        payload_sizes: Option<(RangeInclusive<usize>, StdRng)>,
+        /// pattern used to pad the resized payloads, the payload itself is repeated if empty.
+        fill_pattern: Vec<u8>,
+        /// ramp schedule of the rpu and the time at which the ramp started, `None` once the
+        /// ramp is complete or if it is not configured.
+        ramp: Option<(RampConfig, DateTime<Utc>)>,
+        /// format of the generated payloads and the rng used to populate them.
+        payload_format: (PayloadFormat, StdRng),
+        /// while draining, no new quota is granted and the stream ends once the quota of the
+        /// current time-period is used up.
+        draining: bool,
+        /// prefix every payload with the big-endian u64 sequence number of the message.
+        sequence_prefix: bool,
+        /// prefix and suffix wrapping every payload (before the compression).
+        framing: (Bytes, Bytes),
+        /// compression applied to the payloads.
+        compression: Option<Compression>,
+        /// skew of the event-times and the rng used to pick the late messages.
+        event_time_skew: Option<(EventTimeSkew, StdRng)>,
+        /// messages yet to be sent right away on the first polls, ignoring the rpu.
+        warmup_burst: usize,
+        /// start of the current time-period.
+        period_start: DateTime<Utc>,
+        /// name and replica of the vertex generating the messages.
+        vertex: VertexContext,
+        /// source of the time for the event-times, ramp and the ticks.
+        clock: Arc<dyn Clock>,
+        /// rng for the randomized decisions which do not have a dedicated rng.
+        rng: StdRng,
+        tick: Box<dyn Ticker>,
+        /// backpressure (0.0 to 1.0) of the downstream buffers, the rpu is scaled down by it.
+        pressure: Option<watch::Receiver<f64>>,
+        /// distinct keys emitted so far and the maximum number of keys tracked, `None` if the keys
+        /// are not tracked.
+        distinct_keys: Option<(HashSet<String>, usize)>,
+        /// if set, a single batch is emitted for each trigger instead of on the ticks.
+        trigger: Option<GeneratorTrigger>,
+        /// fraction of the messages emitted with an empty value.
+        tombstone_fraction: f64,
+        /// cap on the total size of the payloads of a batch.
+        max_batch_bytes: Option<usize>,
+        /// message which did not fit in the previous batch due to `max_batch_bytes`, it is the
+        /// first message of the next batch.
+        held_back: Option<Message>,
+        /// per key quota of the current time-period, if the keys are rate limited.
+        key_quota: Option<KeyQuota>,
+        /// interval of the heartbeats while idle, along with the ticker to check for the idleness.
+        idle_heartbeat: Option<(Duration, Box<dyn Ticker>)>,
+        /// time at which the data (not the heartbeat) was generated last.
+        last_data_at: DateTime<Utc>,
+        /// ticker which completes once the start delay elapses, `None` if there is no delay or
+        /// it has already elapsed.
+        start_delay: Option<Box<dyn Ticker>>,
     }
 
-    impl StreamGenerator {
-        pub(super) fn new(cfg: GeneratorConfig, batch_size: usize) -> Self {
-            let mut tick = tokio::time::interval(cfg.duration);
-            tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
-
-            let mut rpu = cfg.rpu;
-            // Key count cannot be more than RPU.
-            // If rpu is not a multiple of the key_count, we floor the rpu to the nearest multiple of key_count
-            // We cap the key_count to u8::MAX in config.rs
-            let key_count = std::cmp::min(cfg.key_count as usize, cfg.rpu) as u8;
-            if key_count != cfg.key_count {
-                warn!(
-                    "Specified KeyCount({}) is higher than RPU ({}). KeyCount is changed to {}",
-                    cfg.key_count, cfg.rpu, key_count
-                );
+    /// KeyQuota tracks the number of messages emitted for each key within the current
+    /// time-period. The keys are used round-robin, skipping the ones which have used up their rpu.
+    struct KeyQuota {
+        /// key, its rpu and the number of messages emitted in the current time-period.
+        keys: Vec<(String, usize, usize)>,
+        /// index of the key to be tried first for the next message.
+        next: usize,
+    }
+
+    impl KeyQuota {
+        fn new(key_rpu: Vec<(String, usize)>) -> Self {
+            Self {
+                keys: key_rpu
+                    .into_iter()
+                    .map(|(key, rpu)| (key, rpu, 0))
+                    .collect(),
+                next: 0,
             }
-            if key_count > 0 && rpu % key_count as usize != 0 {
-                let new_rpu = rpu - (rpu % key_count as usize);
-                warn!(rpu, key_count, "Specified RPU is not a multiple of the KeyCount. This may lead to uneven distribution of messages across keys. RPUs will be adjusted to {}", new_rpu);
-                rpu = new_rpu;
+        }
+
+        /// returns true if any of the keys has quota left.
+        fn has_quota(&self) -> bool {
+            self.keys.iter().any(|(_, rpu, used)| used < rpu)
+        }
+
+        /// returns the next key which has quota left and uses up one of its quota.
+        fn next_key(&mut self) -> Option<String> {
+            let len = self.keys.len();
+            let idx = (0..len)
+                .map(|i| (self.next + i) % len)
+                .find(|&idx| self.keys[idx].2 < self.keys[idx].1)?;
+            self.next = (idx + 1) % len;
+            let (key, _, used) = &mut self.keys[idx];
+            *used += 1;
+            Some(key.clone())
+        }
+
+        /// resets the quota of all the keys at the start of a time-period.
+        fn reset(&mut self) {
+            for (_, _, used) in self.keys.iter_mut() {
+                *used = 0;
             }
+        }
+    }
+
+    impl StreamGenerator {
+        #[cfg(test)]
+        pub(super) fn new(cfg: GeneratorConfig, batch_size: usize) -> Self {
+            Self::with_clock(cfg, batch_size, Arc::new(super::SystemClock))
+        }
+
+        pub(super) fn with_clock(
+            cfg: GeneratorConfig,
+            batch_size: usize,
+            clock: Arc<dyn Clock>,
+        ) -> Self {
+            let tick = clock.interval(cfg.duration, cfg.missed_tick_behavior);
+            let idle_heartbeat = cfg.idle_heartbeat.map(|interval| {
+                let ticker = clock.interval(interval, tokio::time::MissedTickBehavior::Delay);
+                (interval, ticker)
+            });
+            let start_delay = (!cfg.start_delay.is_zero()).then(|| {
+                let mut ticker =
+                    clock.interval(cfg.start_delay, tokio::time::MissedTickBehavior::Delay);
+                // the first tick completes right away, it should only complete after the delay
+                ticker.reset_after(cfg.start_delay);
+                ticker
+            });
+            let now = clock.now();
+            let vertex = cfg.vertex.clone().unwrap_or_else(VertexContext::current);
 
-            // Generate all possible keys
-            let keys = (0..key_count).map(|i| format!("key-{}", i)).collect();
+            // share of this replica if the rpu is for the whole vertex.
+            let replica_rpu = cfg.replica_rpu(vertex.replica);
+            // with a ramp, we start from the start_rpu of the ramp.
+            let mut rpu = cfg.ramp.as_ref().map_or(replica_rpu, |ramp| ramp.start_rpu);
+            let (keys, key_selection) = match cfg.key_spec {
+                // user provided keys are used as is
+                Some(key_spec) => (key_spec.keys, key_spec.selection),
+                None => {
+                    // Key count cannot be more than RPU.
+                    // If rpu is not a multiple of the key_count, we floor the rpu to the nearest multiple of key_count
+                    // We cap the key_count to u8::MAX in config.rs
+                    let key_count = std::cmp::min(cfg.key_count as usize, replica_rpu) as u8;
+                    if key_count != cfg.key_count {
+                        warn!(
+                            "Specified KeyCount({}) is higher than RPU ({}). KeyCount is changed to {}",
+                            cfg.key_count, replica_rpu, key_count
+                        );
+                    }
+                    if key_count > 0 && rpu % key_count as usize != 0 {
+                        let new_rpu = rpu - (rpu % key_count as usize);
+                        warn!(rpu, key_count, "Specified RPU is not a multiple of the KeyCount. This may lead to uneven distribution of messages across keys. RPUs will be adjusted to {}", new_rpu);
+                        rpu = new_rpu;
+                    }
+
+                    // Generate all possible keys
+                    let keys = (0..key_count).map(|i| format!("key-{}", i)).collect();
+                    (keys, KeySelection::RoundRobin)
+                }
+            };
+
+            let synthetic_clock = match cfg.event_time {
+                EventTimeMode::Now => None,
+                EventTimeMode::Synthetic { start, step } => Some((start, step)),
+            };
+
+            // all the rngs are derived from the generator seed, so that a seeded generator is
+            // reproducible.
+            let mut rng = seeded_rng(cfg.seed);
+
+            let payload_sizes = cfg.size_distribution.map(|dist| {
+                let max_bytes = dist.max_bytes.max(dist.min_bytes);
+                (dist.min_bytes..=max_bytes, derive_rng(dist.seed, &mut rng))
+            });
+
+            let payload_rng = match cfg.payload_format {
+                PayloadFormat::Raw | PayloadFormat::Protobuf { .. } => derive_rng(None, &mut rng),
+                PayloadFormat::Csv { seed, .. } | PayloadFormat::Json { seed, .. } => {
+                    derive_rng(seed, &mut rng)
+                }
+            };
+
+            let event_time_skew = cfg.event_time_skew.map(|skew| {
+                let skew_rng = derive_rng(skew.seed, &mut rng);
+                (skew, skew_rng)
+            });
+
+            // every replica is a partition of its own unless configured otherwise.
+            let partitions = cfg
+                .partitions
+                .filter(|partitions| !partitions.is_empty())
+                .unwrap_or_else(|| vec![vertex.replica])
+                .into_iter()
+                .map(|partition| (partition, 0))
+                .collect();
+
+            // a single static content is same as a list with just one payload.
+            let contents = if !cfg.payloads.is_empty() {
+                cfg.payloads
+            } else if !cfg.content.is_empty() {
+                vec![cfg.content]
+            } else {
+                vec![]
+            };
 
             Self {
-                content: cfg.content,
+                contents: (contents, 0),
                 rpu,
-                // batch cannot > rpu
-                batch: std::cmp::min(cfg.rpu, batch_size),
+                batch: batch_size,
                 used: 0,
                 tick,
                 value: cfg.value,
                 msg_size_bytes: cfg.msg_size_bytes,
                 keys: (keys, 0),
+                key_selection,
+                synthetic_clock,
+                max_messages: cfg.max_messages,
+                emitted: 0,
+                offset_type: cfg.offset_type,
+                partitions: (partitions, 0),
+                next_seq: 0,
+                heartbeats: 0,
+                control: GeneratorControl::new(rpu),
+                unit: cfg.duration,
+                tick_jitter: cfg.tick_jitter,
+                headers: cfg.headers,
+                sequence_header: cfg.sequence_header,
+                replica_header: cfg.replica_header,
+                ttl: cfg.ttl,
+                payload_sizes,
+                fill_pattern: cfg.fill_pattern,
+                ramp: cfg.ramp.map(|ramp| (ramp, now)),
+                payload_format: (cfg.payload_format, payload_rng),
+                draining: false,
+                sequence_prefix: cfg.sequence_prefix,
+                framing: (cfg.payload_prefix, cfg.payload_suffix),
+                compression: cfg.compression,
+                event_time_skew,
+                warmup_burst: cfg.warmup_burst,
+                period_start: now,
+                vertex,
+                clock,
+                rng,
+                pressure: None,
+                distinct_keys: cfg.distinct_keys_cap.map(|cap| (HashSet::new(), cap)),
+                trigger: None,
+                tombstone_fraction: cfg.tombstone_fraction,
+                max_batch_bytes: cfg.max_batch_bytes,
+                key_quota: (!cfg.key_rpu.is_empty()).then(|| KeyQuota::new(cfg.key_rpu)),
+                idle_heartbeat,
+                last_data_at: now,
+                start_delay,
+                held_back: None,
                 jitter: cfg.jitter,
             }
         }
 
+        /// returns a handle to pause and resume the generator.
+        #[cfg(test)]
+        pub(super) fn control(&self) -> GeneratorControl {
+            self.control.clone()
+        }
+
+        /// returns the partitions of the generator.
+        pub(super) fn partitions(&self) -> Vec<u16> {
+            self.partitions
+                .0
+                .iter()
+                .map(|(partition, _)| *partition)
+                .collect()
+        }
+
+        /// throttles the generator by the backpressure signal, the effective rpu is
+        /// `rpu * (1 - pressure)`.
+        pub(super) fn set_backpressure(&mut self, pressure: watch::Receiver<f64>) {
+            self.pressure = Some(pressure);
+        }
+
+        /// emits a single batch for each trigger instead of on the ticks.
+        #[cfg(test)]
+        pub(super) fn set_trigger(&mut self, trigger: GeneratorTrigger) {
+            self.trigger = Some(trigger);
+        }
+
+        /// returns a snapshot of the counters of the generator.
+        #[cfg(test)]
+        pub(super) fn debug_state(&self) -> GeneratorState {
+            GeneratorState {
+                used: self.used,
+                rpu: self.rpu,
+                batch: self.batch,
+                // the held back message is yet to be emitted
+                emitted_total: self.emitted - usize::from(self.held_back.is_some()),
+                current_period_start: self.period_start,
+            }
+        }
+
+        /// returns the rpu in effect right now, i.e. the rpu of the ramp at this time (or the rpu
+        /// set on the fly) throttled by the backpressure.
+        #[cfg(test)]
+        pub(super) fn current_rpu(&self) -> usize {
+            let rpu = match self.ramp.as_ref() {
+                Some((ramp, start)) => ramp.rpu_at(elapsed_since(self.clock.as_ref(), *start)),
+                None => self.control.rpu(),
+            };
+            throttled_rpu(rpu, self.pressure.as_ref())
+        }
+
+        /// returns the time it takes to emit the rest of the messages of a bounded generator at the
+        /// current rpu, `None` if the generator is unbounded or is not emitting at all.
+        #[cfg(test)]
+        pub(super) fn estimated_drain_time(&self) -> Option<Duration> {
+            let max = self.max_messages?;
+            let rpu = self.current_rpu();
+            if rpu == 0 {
+                return None;
+            }
+            // the held back message is yet to be emitted
+            let emitted = self.emitted - usize::from(self.held_back.is_some());
+            let remaining = max.saturating_sub(emitted);
+            Some(self.unit.mul_f64(remaining as f64 / rpu as f64))
+        }
+
+        /// resets the quota, the current time-period is restarted with none of the quota used.
+        /// The tick is pushed out by a whole time-period, so that the next tick does not grant
+        /// a second quota within the same time-period.
+        pub(super) fn reset_quota(&mut self) {
+            self.used = 0;
+            if let Some(key_quota) = self.key_quota.as_mut() {
+                key_quota.reset();
+            }
+            self.period_start = self.clock.now();
+            self.tick.reset_after(self.unit);
+        }
+
+        /// returns true if the per key quota (if any) is not used up yet.
+        fn has_key_quota(&self) -> bool {
+            self.key_quota.as_ref().map_or(true, KeyQuota::has_quota)
+        }
+
+        /// stops granting new quota, the stream ends once the current quota is used up.
+        #[cfg(test)]
+        pub(super) fn drain(&mut self) {
+            self.draining = true;
+        }
+
+        /// fraction of the current time-period that has elapsed, within `[0, 1]`.
+        fn elapsed_fraction(&self) -> f64 {
+            if self.unit.is_zero() {
+                return 1.0;
+            }
+            let elapsed = elapsed_since(self.clock.as_ref(), self.period_start);
+            (elapsed.as_secs_f64() / self.unit.as_secs_f64()).min(1.0)
+        }
+
         /// Generates a similar payload as the Go implementation.
         /// This is only needed if the user has not specified `valueBlob` in the generator source configuration in the pipeline
-        fn generate_payload(&self, value: i64) -> Vec<u8> {
+        fn generate_payload(&mut self, value: i64) -> Vec<u8> {
             #[derive(serde::Serialize)]
             struct Data {
                 value: i64,
@@ -114,23 +522,30 @@ mod stream_generator {
                 padding: Vec<u8>,
             }
 
-            let padding: Vec<u8> = (self.msg_size_bytes > 8)
-                .then(|| {
-                    let size = self.msg_size_bytes - 8;
-                    let mut bytes = vec![0; size as usize];
-                    rand::thread_rng().fill(&mut bytes[..]);
-                    bytes
-                })
-                .unwrap_or_default();
+            let mut padding = vec![];
+            if self.msg_size_bytes > 8 {
+                padding = vec![0; (self.msg_size_bytes - 8) as usize];
+                self.rng.fill(&mut padding[..]);
+            }
 
             let data = Data { value, padding };
             serde_json::to_vec(&data).unwrap()
         }
 
         /// we have a global array of prepopulated keys, we just have to fetch the next in line.
-        /// to fetch the next one, we idx++ whenever we fetch.
+        /// to fetch the next one, we idx++ whenever we fetch. With [KeySelection::Random], the
+        /// next key is picked at random instead.
         /// This will be a single element vector at the most.
         fn next_key_to_be_fetched(&mut self) -> Vec<String> {
+            if let Some(key_quota) = self.key_quota.as_mut() {
+                return key_quota.next_key().into_iter().collect();
+            }
+
+            if self.key_selection == KeySelection::Random && !self.keys.0.is_empty() {
+                let idx = self.rng.gen_range(0..self.keys.0.len());
+                return vec![self.keys.0[idx].clone()];
+            }
+
             let idx = self.keys.1;
             // fetches the next key from the predefined set of keys.
             match self.keys.0.get(idx) {
@@ -142,21 +557,203 @@ mod stream_generator {
             }
         }
 
-        /// creates a single message that can be returned by the generator.
-        fn create_message(&mut self) -> Message {
-            let id = chrono::Utc::now()
-                .timestamp_nanos_opt()
-                .unwrap_or_default()
-                .to_string();
+        /// records the keys as emitted if the distinct keys are tracked, the new keys are ignored
+        /// once the cap is reached.
+        fn track_keys(&mut self, keys: &[String]) {
+            let Some((seen, cap)) = self.distinct_keys.as_mut() else {
+                return;
+            };
+            for key in keys {
+                if seen.len() < *cap && !seen.contains(key) {
+                    seen.insert(key.clone());
+                }
+            }
+        }
+
+        /// returns the number of the distinct keys emitted so far, 0 if they are not tracked.
+        #[cfg(test)]
+        pub(super) fn distinct_keys(&self) -> usize {
+            self.distinct_keys
+                .as_ref()
+                .map_or(0, |(seen, _)| seen.len())
+        }
+
+        /// returns the next content in line, an empty content is returned if no contents are
+        /// configured.
+        fn next_content(&mut self) -> Bytes {
+            let idx = self.contents.1;
+            match self.contents.0.get(idx) {
+                Some(content) => {
+                    self.contents.1 = (idx + 1) % self.contents.0.len();
+                    content.clone()
+                }
+                None => Bytes::new(),
+            }
+        }
+
+        /// returns the event-time for the next message. If configured, a fraction of the messages
+        /// are made late by moving their event-time back.
+        fn next_event_time(&mut self) -> DateTime<Utc> {
+            let event_time = self.base_event_time();
+            let Some((skew, rng)) = self.event_time_skew.as_mut() else {
+                return event_time;
+            };
+
+            let max_lateness_ms = skew.max_lateness.as_millis() as u64;
+            if max_lateness_ms == 0 || !rng.gen_bool(skew.late_fraction.clamp(0.0, 1.0)) {
+                return event_time;
+            }
+            event_time - Duration::from_millis(rng.gen_range(1..=max_lateness_ms))
+        }
 
-            let offset = Offset::String(StringOffset::new(id.clone(), *get_vertex_replica()));
+        /// returns the event-time for the next message without the skew. In synthetic mode, the
+        /// clock is advanced by the configured step for every message.
+        fn base_event_time(&mut self) -> DateTime<Utc> {
+            if let Some((next, step)) = self.synthetic_clock.as_mut() {
+                let event_time = *next;
+                *next += *step;
+                return event_time;
+            }
 
             // rng.gen_range(0..0) panics with "cannot sample empty range"
             // rng.gen_range(0..1) will always produce 0
             let jitter = self.jitter.as_secs().max(1);
-            let event_time =
-                chrono::Utc::now() - Duration::from_secs(rand::thread_rng().gen_range(0..jitter));
-            let mut data = self.content.to_vec();
+            self.clock.now() - Duration::from_secs(self.rng.gen_range(0..jitter))
+        }
+
+        /// returns the partition of the next message and the number of messages assigned to
+        /// that partition before it.
+        fn next_partition(&mut self) -> (u16, u64) {
+            let idx = self.partitions.1;
+            self.partitions.1 = (idx + 1) % self.partitions.0.len();
+            let (partition, count) = &mut self.partitions.0[idx];
+            *count += 1;
+            (*partition, *count - 1)
+        }
+
+        /// returns the offset for the next message. String offsets are unique per partition even
+        /// if they are created at the same instant.
+        fn next_offset(&mut self) -> Offset {
+            let (partition, counter) = self.next_partition();
+            match self.offset_type {
+                OffsetType::String => {
+                    let timestamp = self.clock.now().timestamp_nanos_opt().unwrap_or_default();
+                    Offset::String(StringOffset::sequenced(
+                        timestamp,
+                        self.vertex.replica,
+                        counter,
+                        partition,
+                    ))
+                }
+                OffsetType::Int => {
+                    let seq = self.next_seq;
+                    self.next_seq += 1;
+                    Offset::Int(IntOffset::new(seq, partition))
+                }
+                OffsetType::Deterministic { seed } => Offset::String(StringOffset::sequenced(
+                    deterministic_offset_base(seed, partition),
+                    self.vertex.replica,
+                    counter,
+                    partition,
+                )),
+            }
+        }
+
+        /// returns the offset for the next heartbeat, it is a string offset `heartbeat.{replica}.{n}`
+        /// on the first partition regardless of the `offset_type`. The partition counters and the
+        /// sequence of the data are left untouched.
+        fn next_heartbeat_offset(&mut self) -> Offset {
+            let heartbeat = self.heartbeats;
+            self.heartbeats += 1;
+            let partition = self
+                .partitions
+                .0
+                .first()
+                .map_or(0, |(partition, _)| *partition);
+            Offset::String(StringOffset::new(
+                format!("heartbeat.{}.{:020}", self.vertex.replica, heartbeat),
+                partition,
+            ))
+        }
+
+        /// returns the headers for the next message, the static headers along with the sequence
+        /// and the expiry headers if configured.
+        fn next_headers(&self) -> HashMap<String, String> {
+            let mut headers = self.headers.clone();
+            if let Some(sequence_header) = &self.sequence_header {
+                headers.insert(sequence_header.clone(), self.emitted.to_string());
+            }
+            if let Some(replica_header) = &self.replica_header {
+                headers.insert(replica_header.clone(), self.vertex.replica.to_string());
+            }
+            if let Some(content_type) = self.payload_format.0.content_type() {
+                headers.insert(CONTENT_TYPE_HEADER.to_string(), content_type.to_string());
+            }
+            if let Some(ttl) = self.ttl {
+                let expires_at = self.clock.now() + ttl;
+                headers.insert(
+                    EXPIRES_AT_HEADER.to_string(),
+                    expires_at.timestamp_millis().to_string(),
+                );
+            }
+            headers
+        }
+
+        /// returns a heartbeat message if no data has been generated for the heartbeat interval,
+        /// the waker is registered to be woken up once the interval elapses otherwise.
+        fn poll_heartbeat(&mut self, cx: &mut Context<'_>) -> Option<Message> {
+            let (interval, ticker) = self.idle_heartbeat.as_mut()?;
+            let interval = *interval;
+            if ticker.poll_tick(cx).is_pending() {
+                return None;
+            }
+
+            let idle = elapsed_since(self.clock.as_ref(), self.last_data_at);
+            if idle < interval {
+                // check again once the generator has been idle for the interval
+                ticker.reset_after(interval - idle);
+                let _ = ticker.poll_tick(cx);
+                return None;
+            }
+
+            self.last_data_at = self.clock.now();
+            let offset = self.next_heartbeat_offset();
+            Some(Message {
+                keys: vec![],
+                value: Bytes::new(),
+                offset: Some(offset.clone()),
+                event_time: self.clock.now(),
+                id: MessageID {
+                    vertex_name: self.vertex.name.clone(),
+                    offset: offset.to_string(),
+                    index: Default::default(),
+                },
+                headers: HashMap::from([(HEARTBEAT_HEADER.to_string(), "true".to_string())]),
+            })
+        }
+
+        /// creates a single message that can be returned by the generator.
+        fn create_message(&mut self) -> Message {
+            let offset = self.next_offset();
+
+            let event_time = self.next_event_time();
+            let mut data = match &mut self.payload_format {
+                (PayloadFormat::Raw, _) => self.next_content().to_vec(),
+                (
+                    PayloadFormat::Csv {
+                        columns,
+                        rows_per_message,
+                        ..
+                    },
+                    rng,
+                ) => generate_csv(*columns, *rows_per_message, rng),
+                (PayloadFormat::Json { fields, .. }, rng) => {
+                    generate_json(fields, rng, self.clock.now())
+                }
+                (PayloadFormat::Protobuf { keys }, _) => {
+                    generate_protobuf(keys, self.emitted as u64)
+                }
+            };
             if data.is_empty() {
                 let value = match self.value {
                     Some(v) => v,
@@ -164,29 +761,204 @@ mod stream_generator {
                 };
                 data = self.generate_payload(value);
             }
+            if let Some((sizes, rng)) = self.payload_sizes.as_mut() {
+                let size = rng.gen_range(sizes.clone());
+                data = resize_payload(&data, size, &self.fill_pattern);
+            }
+            if self.sequence_prefix {
+                let mut prefixed = (self.emitted as u64).to_be_bytes().to_vec();
+                prefixed.extend_from_slice(&data);
+                data = prefixed;
+            }
+            let (prefix, suffix) = &self.framing;
+            if !prefix.is_empty() || !suffix.is_empty() {
+                let mut framed = Vec::with_capacity(prefix.len() + data.len() + suffix.len());
+                framed.extend_from_slice(prefix);
+                framed.extend_from_slice(&data);
+                framed.extend_from_slice(suffix);
+                data = framed;
+            }
+            if let Some(compression) = self.compression {
+                data = compression.compress(&data);
+            }
+            // the payload is still generated for a tombstone, so that the rest of the messages
+            // stay the same irrespective of the tombstones.
+            if self.tombstone_fraction > 0.0 && self.rng.gen_bool(self.tombstone_fraction) {
+                data.clear();
+            }
 
+            let keys = self.next_key_to_be_fetched();
+            self.track_keys(&keys);
             Message {
-                keys: self.next_key_to_be_fetched(),
+                keys,
                 value: data.into(),
                 offset: Some(offset.clone()),
                 event_time,
                 id: MessageID {
-                    vertex_name: get_vertex_name().to_string(),
+                    vertex_name: self.vertex.name.clone(),
                     offset: offset.to_string(),
                     index: Default::default(),
                 },
-                headers: Default::default(),
+                headers: self.next_headers(),
             }
         }
 
-        /// generates a set of messages to be returned.
+        /// generates a set of messages to be returned. Fewer messages are returned if the batch
+        /// would exceed `max_batch_bytes`.
         fn generate_messages(&mut self, count: usize) -> Vec<Message> {
             let mut data = Vec::with_capacity(count);
-            for _ in 0..count {
-                data.push(self.create_message());
+            let mut batch_bytes = 0;
+            while data.len() < count {
+                let message = match self.held_back.take() {
+                    Some(message) => message,
+                    // nothing more can be emitted once the keys have used up their quota
+                    None if !self.has_key_quota() => break,
+                    None => {
+                        let message = self.create_message();
+                        self.emitted += 1;
+                        message
+                    }
+                };
+                // at least one message is returned, however big it is
+                if let Some(max_batch_bytes) = self.max_batch_bytes {
+                    if !data.is_empty() && batch_bytes + message.value.len() > max_batch_bytes {
+                        self.held_back = Some(message);
+                        break;
+                    }
+                }
+                batch_bytes += message.value.len();
+                data.push(message);
+            }
+            if !data.is_empty() {
+                self.last_data_at = self.clock.now();
             }
             data
         }
+
+        /// same as [Self::generate_messages] for the `count` messages already charged to the quota
+        /// of the time-period, the messages which were not returned are given back to the quota.
+        fn generate_charged_messages(&mut self, count: usize) -> Vec<Message> {
+            let data = self.generate_messages(count);
+            self.used = self.used.saturating_sub(count - data.len());
+            data
+        }
+    }
+
+    /// returns the time elapsed since `start` as per the clock.
+    fn elapsed_since(clock: &dyn Clock, start: DateTime<Utc>) -> Duration {
+        (clock.now() - start).to_std().unwrap_or_default()
+    }
+
+    /// returns the base of the deterministic offsets of the partition, it only depends on the
+    /// seed and the partition.
+    fn deterministic_offset_base(seed: u64, partition: u16) -> i64 {
+        StdRng::seed_from_u64(seed ^ ((partition as u64) << 48)).gen_range(0..i64::MAX)
+    }
+
+    /// returns a rng seeded with the given seed, or a random seed if not set.
+    fn seeded_rng(seed: Option<u64>) -> StdRng {
+        match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        }
+    }
+
+    /// returns a rng seeded with the given seed, or a seed drawn from the given rng if not set.
+    fn derive_rng(seed: Option<u64>, rng: &mut StdRng) -> StdRng {
+        StdRng::seed_from_u64(seed.unwrap_or_else(|| rng.gen()))
+    }
+
+    /// generates a CSV document with a header row (`col-{n}`) followed by `rows` rows of random
+    /// values.
+    fn generate_csv(columns: usize, rows: usize, rng: &mut StdRng) -> Vec<u8> {
+        let header: Vec<String> = (0..columns).map(|i| format!("col-{}", i)).collect();
+        let mut csv = header.join(",");
+        for _ in 0..rows {
+            let row: Vec<String> = (0..columns).map(|_| rng.gen::<u32>().to_string()).collect();
+            csv.push('\n');
+            csv.push_str(&row.join(","));
+        }
+        csv.push('\n');
+        csv.into_bytes()
+    }
+
+    /// generates a JSON object with the given fields populated with random values of their type, the
+    /// timestamps are set to `now`.
+    fn generate_json(
+        fields: &[(String, JsonFieldType)],
+        rng: &mut StdRng,
+        now: DateTime<Utc>,
+    ) -> Vec<u8> {
+        let object: serde_json::Map<String, serde_json::Value> = fields
+            .iter()
+            .map(|(name, field_type)| {
+                let value = match field_type {
+                    JsonFieldType::Int => serde_json::Value::from(rng.gen::<i64>()),
+                    JsonFieldType::String => (0..16)
+                        .map(|_| char::from(rng.sample(Alphanumeric)))
+                        .collect::<String>()
+                        .into(),
+                    JsonFieldType::Bool => serde_json::Value::from(rng.gen::<bool>()),
+                    JsonFieldType::Timestamp => now.to_rfc3339().into(),
+                };
+                (name.clone(), value)
+            })
+            .collect();
+        serde_json::to_vec(&object).unwrap()
+    }
+
+    /// encodes a [GeneratedRecord] with an entry for each of the keys, the values increment across
+    /// the entries and the messages (`sequence` is the sequence number of the message).
+    fn generate_protobuf(keys: &[String], sequence: u64) -> Vec<u8> {
+        let base = sequence * keys.len() as u64;
+        let record = GeneratedRecord {
+            entries: keys
+                .iter()
+                .enumerate()
+                .map(|(i, key)| GeneratedEntry {
+                    key: key.clone(),
+                    value: (base + i as u64) as i64,
+                })
+                .collect(),
+        };
+        record.encode_to_vec()
+    }
+
+    /// truncates or pads the payload to the given size, padding is done by repeating the fill
+    /// pattern after the payload, or by repeating the payload if there is no fill pattern.
+    fn resize_payload(data: &[u8], size: usize, fill_pattern: &[u8]) -> Vec<u8> {
+        if fill_pattern.is_empty() {
+            if data.is_empty() {
+                return vec![0; size];
+            }
+            return data.iter().copied().cycle().take(size).collect();
+        }
+        data.iter()
+            .copied()
+            .chain(fill_pattern.iter().copied().cycle())
+            .take(size)
+            .collect()
+    }
+
+    /// returns a random period within `[unit - jitter, unit + jitter]`. Jitter is capped to the unit
+    /// so that the period never goes negative.
+    fn jittered_period(unit: Duration, jitter: Duration, rng: &mut impl Rng) -> Duration {
+        let jitter = jitter.min(unit);
+        if jitter.is_zero() {
+            return unit;
+        }
+        rng.gen_range(unit - jitter..=unit + jitter)
+    }
+
+    /// returns the rpu scaled down by the backpressure, i.e. `rpu * (1 - pressure)`.
+    fn throttled_rpu(rpu: usize, pressure: Option<&watch::Receiver<f64>>) -> usize {
+        match pressure {
+            Some(pressure) => {
+                let pressure = pressure.borrow().clamp(0.0, 1.0);
+                (rpu as f64 * (1.0 - pressure)).ceil() as usize
+            }
+            None => rpu,
+        }
     }
 
     impl Stream for StreamGenerator {
@@ -197,46 +969,181 @@ mod stream_generator {
             cx: &mut Context<'_>,
         ) -> Poll<Option<Self::Item>> {
             let mut this = self.as_mut().project();
+
+            // we do not generate anything while paused, resume will wake us up.
+            if this.control.is_paused() {
+                this.control.register(cx);
+                // check again in case we were resumed before the waker got registered
+                if this.control.is_paused() {
+                    return Poll::Pending;
+                }
+            }
+
+            // nothing is generated until the start delay elapses, the first time-period (and the
+            // ramp) start thereafter.
+            if let Some(start_delay) = this.start_delay.as_mut() {
+                if start_delay.poll_tick(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                *this.start_delay = None;
+                this.tick.reset_after(Duration::ZERO);
+                if let Some((_, start)) = this.ramp.as_mut() {
+                    *start = this.clock.now();
+                }
+            }
+
+            // while ramping, the rpu is driven by the ramp schedule.
+            if let Some((ramp, start)) = this.ramp.as_ref() {
+                let elapsed = elapsed_since(this.clock.as_ref(), *start);
+                this.control.store_rpu(ramp.rpu_at(elapsed));
+                if elapsed >= ramp.ramp_duration {
+                    *this.ramp = None;
+                }
+            }
+
+            if this.control.take_quota_reset() {
+                self.reset_quota();
+                this = self.as_mut().project();
+            }
+
+            // pick up the rpu in case it was changed on the fly, slowing down as the downstream
+            // buffers fill up
+            *this.rpu = throttled_rpu(this.control.rpu(), this.pressure.as_ref());
+
+            // bounded generator has nothing more to send, the held back message is yet to be sent
+            let emitted = *this.emitted - usize::from(this.held_back.is_some());
+            let remaining = match *this.max_messages {
+                Some(max) if emitted >= max => return Poll::Ready(None),
+                Some(max) => max - emitted,
+                None => usize::MAX,
+            };
+
+            // in the trigger mode, the rpu and the ticks do not apply, each trigger is a batch
+            if let Some(trigger) = this.trigger.as_ref() {
+                // registered before checking, so that a concurrent trigger is not missed
+                trigger.register(cx);
+                if !trigger.take() {
+                    return Poll::Pending;
+                }
+                let count = (*this.batch).min(remaining);
+                let data = self.generate_messages(count);
+                return Poll::Ready(Some(data));
+            }
+
+            // the warmup burst is sent in batches, on top of the quota of the first time-period
+            if *this.warmup_burst > 0 {
+                let to_send = (*this.batch).min(*this.warmup_burst).min(remaining);
+                *this.warmup_burst -= to_send;
+                let data = self.generate_messages(to_send);
+                return Poll::Ready(Some(data));
+            }
+
+            // while draining, only the remaining quota of the current time-period is sent
+            if *this.draining {
+                let to_send = (*this.rpu)
+                    .saturating_sub(*this.used)
+                    .min(*this.batch)
+                    .min(remaining);
+                let has_key_quota = this.key_quota.as_ref().map_or(true, KeyQuota::has_quota);
+                if to_send == 0 || !has_key_quota {
+                    return Poll::Ready(None);
+                }
+                *this.used += to_send;
+                let data = self.generate_charged_messages(to_send);
+                return Poll::Ready(Some(data));
+            }
+
             match this.tick.poll_tick(cx) {
                 // Poll::Ready means we are ready to send data the whole batch since enough time
                 // has passed.
                 Poll::Ready(_) => {
-                    *this.used = *this.batch;
-                    let count = self.batch;
-                    let data = self.generate_messages(count);
+                    *this.period_start = this.clock.now();
+                    if let Some(key_quota) = this.key_quota.as_mut() {
+                        key_quota.reset();
+                    }
+                    // recompute the next deadline if the ticks have to be irregular
+                    if !this.tick_jitter.is_zero() {
+                        let period = jittered_period(*this.unit, *this.tick_jitter, this.rng);
+                        this.tick.reset_after(period);
+                    }
+
+                    // batch cannot > rpu
+                    let count = (*this.batch).min(*this.rpu).min(remaining);
+                    *this.used = count;
+                    // nothing is to be sent in this time-period (e.g., the rpu is 0 or the
+                    // backpressure is 1.0), an empty batch would read as a stopped generator. We get
+                    // polled again right away to wait for the next tick or a change of the rpu.
+                    if count == 0 {
+                        cx.waker().wake_by_ref();
+                        return Poll::Pending;
+                    }
+                    trace!(
+                        branch = "tick",
+                        used = count,
+                        emitted = count,
+                        "Generator polled"
+                    );
+                    let data = self.generate_charged_messages(count);
                     // reset used quota
                     Poll::Ready(Some(data))
                 }
                 Poll::Pending => {
                     // even if enough time hasn't passed, we can still send data if we have
                     // quota (rpu - used) left
-                    if this.used < this.rpu {
+                    if this.used < this.rpu
+                        && this.key_quota.as_ref().map_or(true, KeyQuota::has_quota)
+                    {
                         // make sure we do not send more than desired
-                        let to_send = std::cmp::min(*this.rpu - *this.used, *this.batch);
+                        let to_send =
+                            std::cmp::min(*this.rpu - *this.used, *this.batch).min(remaining);
 
                         // update the counters
                         *this.used += to_send;
-                        let data = self.generate_messages(to_send);
+                        trace!(
+                            branch = "quota",
+                            used = *this.used,
+                            emitted = to_send,
+                            "Generator polled"
+                        );
+                        let data = self.generate_charged_messages(to_send);
                         Poll::Ready(Some(data))
                     } else {
-                        Poll::Pending
+                        trace!(
+                            branch = "pending",
+                            used = *this.used,
+                            emitted = 0,
+                            "Generator polled"
+                        );
+                        // an increase of the rpu should give us quota right away
+                        this.control.register(cx);
+                        // let the downstream know that we are idle
+                        match self.poll_heartbeat(cx) {
+                            Some(heartbeat) => Poll::Ready(Some(vec![heartbeat])),
+                            None => Poll::Pending,
+                        }
                     }
                 }
             }
         }
 
-        /// size is roughly what is remaining and upper bound is for sure RPU. This is a very
-        /// rough approximation because Duration is not taken into account for the lower bound.
+        /// upper bound is for sure RPU. The lower bound is the remaining quota (rpu - used) scaled
+        /// down by the fraction of the time-period that has elapsed, i.e., the number of messages
+        /// expected to be emitted before the next tick if the quota is spread over the period.
         fn size_hint(&self) -> (usize, Option<usize>) {
-            (self.rpu - self.used, Some(self.rpu))
+            let remaining = self.rpu.saturating_sub(self.used);
+            let lower = (remaining as f64 * (1.0 - self.elapsed_fraction())).ceil() as usize;
+            (lower.min(remaining), Some(self.rpu))
         }
     }
 
     #[cfg(test)]
     mod tests {
-        use futures::StreamExt;
+        use chrono::TimeZone;
+        use futures::{FutureExt, StreamExt};
+        use tokio::time::MissedTickBehavior;
 
         use super::*;
+        use crate::source::generator::clock::MockClock;
 
         #[tokio::test]
         async fn test_stream_generator() {
@@ -287,6 +1194,67 @@ mod stream_generator {
             assert_eq!(size.1, Some(rpu));
         }
 
+        #[tokio::test]
+        async fn test_stream_generator_uncharged_batches_keep_quota() {
+            // 100 bytes payloads, only 3 of them fit in a batch
+            let cfg = GeneratorConfig {
+                content: Bytes::from(vec![b'x'; 100]),
+                rpu: 100,
+                duration: Duration::from_secs(1),
+                warmup_burst: 10,
+                max_batch_bytes: Some(350),
+                ..Default::default()
+            };
+            let mut stream_generator = StreamGenerator::new(cfg, 10);
+            stream_generator.used = 20;
+
+            // the warmup burst is not charged to the quota, hence nothing is given back either
+            let warmup_batch = stream_generator.next().await.unwrap();
+            assert_eq!(warmup_batch.len(), 3);
+            assert_eq!(stream_generator.used, 20);
+
+            // same for the batches of the trigger mode
+            let trigger = GeneratorTrigger::new();
+            stream_generator.set_trigger(trigger.clone());
+            trigger.trigger();
+            let triggered_batch = stream_generator.next().await.unwrap();
+            assert_eq!(triggered_batch.len(), 3);
+            assert_eq!(stream_generator.used, 20);
+        }
+
+        #[tokio::test]
+        async fn test_stream_generator_size_hint_elapsed() {
+            let rpu = 100;
+            let cfg = GeneratorConfig {
+                content: Bytes::from("test_data"),
+                rpu,
+                duration: Duration::from_millis(300),
+                ..Default::default()
+            };
+            let clock = MockClock::new(Utc.timestamp_opt(1_700_000_000, 0).unwrap());
+            let mut stream_generator =
+                StreamGenerator::with_clock(cfg, 10, Arc::new(clock.clone()));
+
+            // the first tick starts the time-period
+            let first_batch = stream_generator.next().await.unwrap();
+            assert_eq!(first_batch.len(), 10);
+
+            let mut lower_bounds = vec![];
+            for _ in 0..3 {
+                let (lower, upper) = stream_generator.size_hint();
+                assert!(lower <= rpu - 10);
+                assert_eq!(upper, Some(rpu));
+                lower_bounds.push(lower);
+                clock.advance(Duration::from_millis(100));
+            }
+            // nothing has elapsed at the start of the time-period
+            assert_eq!(lower_bounds[0], rpu - 10);
+            assert!(lower_bounds.windows(2).all(|w| w[1] < w[0]));
+
+            // nothing is expected once the time-period is over
+            assert_eq!(stream_generator.size_hint().0, 0);
+        }
+
         #[tokio::test]
         async fn test_stream_generator_config() {
             let cfg = GeneratorConfig {
@@ -306,36 +1274,610 @@ mod stream_generator {
             let stream_generator = StreamGenerator::new(cfg, 30);
             assert_eq!(stream_generator.keys.0.len(), 3);
         }
+
+        #[tokio::test]
+        async fn test_stream_generator_missed_tick_behavior() {
+            // returns the number of batches that are ready right away after missing a few ticks
+            async fn batches_after_delay(missed_tick_behavior: MissedTickBehavior) -> usize {
+                let cfg = GeneratorConfig {
+                    content: Bytes::from("test_data"),
+                    rpu: 5,
+                    duration: Duration::from_millis(50),
+                    missed_tick_behavior,
+                    ..Default::default()
+                };
+                let mut stream_generator = StreamGenerator::new(cfg, 5);
+
+                // consume the quota of the first tick
+                let first_batch = stream_generator.next().await.unwrap();
+                assert_eq!(first_batch.len(), 5);
+
+                // miss a few ticks
+                tokio::time::sleep(Duration::from_millis(220)).await;
+
+                let mut ready = 0;
+                while stream_generator.next().now_or_never().is_some() {
+                    ready += 1;
+                }
+                ready
+            }
+
+            assert_eq!(batches_after_delay(MissedTickBehavior::Skip).await, 1);
+            assert!(batches_after_delay(MissedTickBehavior::Burst).await > 1);
+        }
+
+        #[test]
+        fn test_jittered_period() {
+            let unit = Duration::from_millis(100);
+            let jitter = Duration::from_millis(20);
+            let mut rng = StdRng::seed_from_u64(42);
+
+            for _ in 0..1000 {
+                let period = jittered_period(unit, jitter, &mut rng);
+                assert!(period >= unit - jitter && period <= unit + jitter);
+            }
+
+            // zero jitter keeps the ticks regular
+            assert_eq!(jittered_period(unit, Duration::ZERO, &mut rng), unit);
+
+            // jitter more than the unit is capped to the unit
+            for _ in 0..1000 {
+                let period = jittered_period(unit, Duration::from_secs(1), &mut rng);
+                assert!(period <= unit * 2);
+            }
+        }
     }
 }
 
 /// Creates a new generator and returns all the necessary implementation of the Source trait.
 /// Generator Source is mainly used for development purpose, where you want to have self-contained
 /// source to generate some messages. We mainly use generator for load testing and integration
-/// testing of Numaflow. The load generated is per replica.
+/// testing of Numaflow. The load generated is per replica, unless `total_replicas` is configured in
+/// which case the RPU is split across the replicas. The config is validated using
+/// [GeneratorConfig::try_into_generator], which is used to create the generator from the spec.
+#[cfg(test)]
 pub(crate) fn new_generator(
     cfg: GeneratorConfig,
     batch_size: usize,
 ) -> crate::Result<(GeneratorRead, GeneratorAck, GeneratorLagReader)> {
-    let gen_read = GeneratorRead::new(cfg, batch_size);
-    let gen_ack = GeneratorAck::new();
-    let gen_lag_reader = GeneratorLagReader::new();
-
-    Ok((gen_read, gen_ack, gen_lag_reader))
-}
-
-pub(crate) struct GeneratorRead {
-    stream_generator: stream_generator::StreamGenerator,
+    cfg.try_into_generator(batch_size)
 }
 
-impl GeneratorRead {
-    /// A new [GeneratorRead] is returned. It takes a static content, requests per unit-time, batch size
-    /// to return per [source::SourceReader::read], and the unit-time as duration.
-    fn new(cfg: GeneratorConfig, batch_size: usize) -> Self {
-        let stream_generator = stream_generator::StreamGenerator::new(cfg.clone(), batch_size);
-        Self { stream_generator }
-    }
-}
+impl GeneratorConfig {
+    /// Validates the config and creates the generator from it, it is the typed entry point for
+    /// creating a generator from the pipeline spec. The rpu and the batch size have to be positive
+    /// and the duration cannot be zero, a duration lower than [MIN_DURATION] is allowed but is not
+    /// honored precisely. A batch size higher than the rpu is allowed, since the rpu can change on
+    /// the fly, but each read is capped to the rpu.
+    pub(crate) fn try_into_generator(
+        self,
+        batch_size: usize,
+    ) -> crate::Result<(GeneratorRead, GeneratorAck, GeneratorLagReader)> {
+        if self.rpu == 0 {
+            return Err(Error::Config(
+                "Generator rpu should be greater than 0".to_string(),
+            ));
+        }
+        if batch_size == 0 {
+            return Err(Error::Config(
+                "Generator batch size should be greater than 0".to_string(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.tombstone_fraction) {
+            return Err(Error::Config(format!(
+                "Generator tombstone fraction {} should be within [0.0, 1.0]",
+                self.tombstone_fraction
+            )));
+        }
+        if self.max_batch_bytes == Some(0) {
+            return Err(Error::Config(
+                "Generator max batch bytes should be greater than 0".to_string(),
+            ));
+        }
+        if self
+            .idle_heartbeat
+            .is_some_and(|interval| interval.is_zero())
+        {
+            return Err(Error::Config(
+                "Generator idle heartbeat interval should be greater than 0".to_string(),
+            ));
+        }
+        if self.duration.is_zero() {
+            return Err(Error::Config(
+                "Generator duration should be greater than 0".to_string(),
+            ));
+        }
+        if self.duration < MIN_DURATION {
+            warn!(
+                duration = ?self.duration,
+                min_duration = ?MIN_DURATION,
+                "Generator duration is lower than the minimum granularity, the rate is not exact"
+            );
+        }
+        if batch_size > self.rpu {
+            warn!(
+                batch_size,
+                rpu = self.rpu,
+                "Generator batch size is higher than the rpu, reads will be capped to the rpu"
+            );
+        }
+
+        let gen_lag_reader = GeneratorLagReader::new(self.pending);
+        let gen_read = GeneratorRead::new(self, batch_size);
+        let gen_ack = GeneratorAck::new();
+
+        Ok((gen_read, gen_ack, gen_lag_reader))
+    }
+}
+
+/// Creates a new generator whose content is read (once) from the given file, rest of the config is
+/// used as is. It behaves exactly like [new_generator] thereafter.
+#[cfg(test)]
+pub(crate) fn new_generator_from_file(
+    path: impl AsRef<std::path::Path>,
+    cfg: GeneratorConfig,
+    batch_size: usize,
+) -> crate::Result<(GeneratorRead, GeneratorAck, GeneratorLagReader)> {
+    let path = path.as_ref();
+    let content = std::fs::read(path).map_err(|e| {
+        Error::Config(format!(
+            "Failed to read generator content from {}: {e}",
+            path.display()
+        ))
+    })?;
+
+    new_generator(
+        GeneratorConfig {
+            content: content.into(),
+            ..cfg
+        },
+        batch_size,
+    )
+}
+
+/// Creates a new generator which emits a single batch (of up to `batch_size` messages) each time
+/// it is triggered through the returned [GeneratorTrigger] instead of on the ticks, e.g., to step
+/// through a pipeline while debugging. The rpu does not apply, rest of the config is used as in
+/// [new_generator].
+#[cfg(test)]
+pub(crate) fn new_generator_with_trigger(
+    cfg: GeneratorConfig,
+    batch_size: usize,
+) -> crate::Result<(
+    GeneratorRead,
+    GeneratorAck,
+    GeneratorLagReader,
+    GeneratorTrigger,
+)> {
+    let (gen_read, gen_ack, gen_lag_reader) = new_generator(cfg, batch_size)?;
+    let trigger = GeneratorTrigger::new();
+    Ok((
+        gen_read.with_trigger(trigger.clone()),
+        gen_ack,
+        gen_lag_reader,
+        trigger,
+    ))
+}
+
+/// Creates a new generator from a textual rpu, batch size and unit (duration), e.g., `"100"`,
+/// `"10"` and `"250ms"`, rest of the config is used as is. The strings are parsed using
+/// [parse_count] and [parse_duration], and the result is validated like in [new_generator].
+#[cfg(test)]
+pub(crate) fn new_generator_from_str(
+    cfg: GeneratorConfig,
+    rpu: &str,
+    batch_size: &str,
+    unit: &str,
+) -> crate::Result<(GeneratorRead, GeneratorAck, GeneratorLagReader)> {
+    let rpu = parse_count("rpu", rpu)?;
+    let batch_size = parse_count("batch size", batch_size)?;
+    let duration = parse_duration(unit)?;
+
+    new_generator(
+        GeneratorConfig {
+            rpu,
+            duration,
+            ..cfg
+        },
+        batch_size,
+    )
+}
+
+/// Parses a positive count (e.g., rpu or batch size) of the generator, `name` is used in the error.
+#[cfg(test)]
+pub(crate) fn parse_count(name: &str, value: &str) -> crate::Result<usize> {
+    match value.trim().parse::<usize>() {
+        Ok(0) => Err(Error::Config(format!(
+            "Generator {name} should be greater than 0"
+        ))),
+        Ok(count) => Ok(count),
+        Err(e) => Err(Error::Config(format!(
+            "Generator {name} {value:?} is not a valid number: {e}"
+        ))),
+    }
+}
+
+/// Parses a duration like `"250ms"`, `"1.5s"` or `"1m30s"`, i.e., a sequence of (decimal) numbers
+/// each followed by one of the units `ns`, `us` (or `µs`), `ms`, `s`, `m` and `h`.
+#[cfg(test)]
+pub(crate) fn parse_duration(value: &str) -> crate::Result<Duration> {
+    let invalid = |reason: &str| {
+        Error::Config(format!(
+            "Generator duration {value:?} is not valid: {reason}"
+        ))
+    };
+
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return Err(invalid("empty duration"));
+    }
+
+    let mut total = Duration::ZERO;
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(number_len);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| invalid("expected a number before the unit"))?;
+
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let unit_secs = match unit {
+            "ns" => 1e-9,
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "" => return Err(invalid("missing unit")),
+            _ => return Err(invalid("unknown unit")),
+        };
+
+        total +=
+            Duration::try_from_secs_f64(number * unit_secs).map_err(|_| invalid("out of range"))?;
+        rest = tail;
+    }
+    Ok(total)
+}
+
+/// Creates a new generator which replays the given records (event-time and payload) in order,
+/// preserving their inter-arrival delays divided by the `speed_factor`. At most `batch_size` records
+/// are returned per read. Unlike [new_generator], the read ends (returns an empty batch) once all
+/// the records are replayed.
+#[cfg(test)]
+pub(crate) fn new_generator_replay(
+    records: Vec<replay::ReplayRecord>,
+    speed_factor: f64,
+    batch_size: usize,
+    vertex: crate::config::VertexContext,
+) -> crate::Result<(replay::ReplayRead, GeneratorAck, GeneratorLagReader)> {
+    if !speed_factor.is_finite() || speed_factor <= 0.0 {
+        return Err(Error::Config(format!(
+            "Generator replay speed factor should be a positive number, got {speed_factor}"
+        )));
+    }
+    if batch_size == 0 {
+        return Err(Error::Config(
+            "Generator replay batch size should be greater than 0".to_string(),
+        ));
+    }
+
+    Ok((
+        replay::ReplayRead::new(records, speed_factor, batch_size, vertex),
+        GeneratorAck::new(),
+        GeneratorLagReader::new(None),
+    ))
+}
+
+/// GeneratorState is a snapshot of the internal counters of the generator, e.g., to be dumped
+/// while investigating a load test.
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct GeneratorState {
+    /// quota used in the current time-period.
+    pub(crate) used: usize,
+    /// rpu of the current time-period.
+    pub(crate) rpu: usize,
+    /// maximum number of messages returned per read.
+    pub(crate) batch: usize,
+    /// total number of the messages emitted so far, excluding the heartbeats.
+    pub(crate) emitted_total: usize,
+    /// start of the current time-period.
+    pub(crate) current_period_start: chrono::DateTime<chrono::Utc>,
+}
+
+/// MessageIdContext is what a [MessageIdStrategy] can build the ID of a message from.
+pub(crate) struct MessageIdContext<'a> {
+    /// the generated message, its `id` is the one built by the generator.
+    pub(crate) message: &'a Message,
+    /// position of the message within the batch returned by [source::SourceReader::read].
+    pub(crate) index: i32,
+}
+
+/// MessageIdStrategy builds the [MessageID] of the generated messages, so that the IDs can be made
+/// compatible with the downstream systems (e.g., UUIDs or composite keys).
+pub(crate) trait MessageIdStrategy: Send + Sync {
+    fn generate(&self, ctx: &MessageIdContext<'_>) -> MessageID;
+}
+
+/// DefaultMessageIdStrategy builds the ID from the vertex name, the offset and the index of the
+/// message within the batch, so that (offset, index) is unique even when the messages share an
+/// offset.
+pub(crate) struct DefaultMessageIdStrategy;
+
+impl MessageIdStrategy for DefaultMessageIdStrategy {
+    fn generate(&self, ctx: &MessageIdContext<'_>) -> MessageID {
+        MessageID {
+            index: ctx.index,
+            ..ctx.message.id.clone()
+        }
+    }
+}
+
+pub(crate) struct GeneratorRead {
+    stream_generator: stream_generator::StreamGenerator,
+    /// builds the IDs of the messages returned by [source::SourceReader::read].
+    id_strategy: Arc<dyn MessageIdStrategy>,
+    /// total number of messages returned by [source::SourceReader::read], excluding the heartbeats.
+    emitted: Arc<AtomicU64>,
+    /// partitions the generator reads from.
+    partitions: Vec<u16>,
+    /// the (bounded) generator has stopped.
+    exhausted: bool,
+}
+
+impl GeneratorRead {
+    /// A new [GeneratorRead] is returned. It takes a static content, requests per unit-time, batch size
+    /// to return per [source::SourceReader::read], and the unit-time as duration.
+    fn new(cfg: GeneratorConfig, batch_size: usize) -> Self {
+        Self::with_clock(cfg, batch_size, Arc::new(SystemClock))
+    }
+
+    /// Same as [GeneratorRead::new], but the time is kept using the given [Clock].
+    pub(crate) fn with_clock(
+        cfg: GeneratorConfig,
+        batch_size: usize,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let stream_generator =
+            stream_generator::StreamGenerator::with_clock(cfg, batch_size, clock);
+        Self {
+            partitions: stream_generator.partitions(),
+            stream_generator,
+            id_strategy: Arc::new(DefaultMessageIdStrategy),
+            emitted: Arc::new(AtomicU64::new(0)),
+            exhausted: false,
+        }
+    }
+
+    /// Returns the total number of messages emitted by the generator so far, excluding the
+    /// heartbeats (same as [GeneratorState::emitted_total]).
+    #[cfg(test)]
+    pub(crate) fn emitted_count(&self) -> u64 {
+        self.emitted.load(Ordering::Relaxed)
+    }
+
+    /// Drains the generator for a graceful shutdown. No new quota is granted after the current
+    /// time-period, [source::SourceReader::read] returns the messages left in the current quota
+    /// and then an empty batch once it is used up.
+    #[cfg(test)]
+    pub(crate) fn drain(&mut self) {
+        self.stream_generator.drain();
+    }
+
+    /// Throttles the generator using the backpressure signal of the downstream buffers (see
+    /// [crate::pipeline::isb::jetstream::ISBWriter::pressure]), a pressure of 0.0 leaves the RPU as
+    /// is and 1.0 stops the generation. The pressure is applied from the next read.
+    pub(crate) fn with_backpressure(mut self, pressure: watch::Receiver<f64>) -> Self {
+        self.stream_generator.set_backpressure(pressure);
+        self
+    }
+
+    /// Returns a snapshot of the internal counters of the generator, it has no effect on the
+    /// generation.
+    #[cfg(test)]
+    pub(crate) fn debug_state(&self) -> GeneratorState {
+        self.stream_generator.debug_state()
+    }
+
+    /// Builds the IDs of the messages using the given strategy instead of the
+    /// [DefaultMessageIdStrategy].
+    #[cfg(test)]
+    pub(crate) fn with_id_strategy(mut self, id_strategy: Arc<dyn MessageIdStrategy>) -> Self {
+        self.id_strategy = id_strategy;
+        self
+    }
+
+    /// Returns the number of the distinct keys emitted so far (capped at
+    /// [GeneratorConfig::distinct_keys_cap]), it is always 0 if the keys are not tracked.
+    #[cfg(test)]
+    pub(crate) fn distinct_keys(&self) -> usize {
+        self.stream_generator.distinct_keys()
+    }
+
+    /// Returns how long it takes to emit the rest of the messages of a bounded generator (see
+    /// [GeneratorConfig::max_messages]) at the current RPU, `None` if the generator is unbounded
+    /// or the current RPU is zero.
+    #[cfg(test)]
+    pub(crate) fn estimated_drain_time(&self) -> Option<Duration> {
+        self.stream_generator.estimated_drain_time()
+    }
+
+    /// Emits a single batch for each trigger of the given [GeneratorTrigger] instead of on the
+    /// ticks, see [new_generator_with_trigger].
+    #[cfg(test)]
+    pub(crate) fn with_trigger(mut self, trigger: GeneratorTrigger) -> Self {
+        self.stream_generator.set_trigger(trigger);
+        self
+    }
+
+    /// Returns the RPU currently in effect, it follows the ramp (if any), the RPU set through the
+    /// [GeneratorControl] and the backpressure.
+    #[cfg(test)]
+    pub(crate) fn current_rpu(&self) -> usize {
+        self.stream_generator.current_rpu()
+    }
+
+    /// Returns a [GeneratorControl] handle which can be used to pause and resume the generator
+    /// even after the reader has been moved into the [source::Source].
+    #[cfg(test)]
+    pub(crate) fn control(&self) -> GeneratorControl {
+        self.stream_generator.control()
+    }
+
+    /// Converts the generator into a stream of message batches, each item is what
+    /// [source::SourceReader::read] would have returned. The stream ends when a bounded generator
+    /// stops, so that it can be composed with the stream combinators.
+    #[cfg(test)]
+    pub(crate) fn into_message_stream(
+        self,
+    ) -> impl futures::Stream<Item = crate::Result<Vec<Message>>> {
+        futures::stream::unfold(self, |mut generator| async move {
+            match source::SourceReader::read(&mut generator).await {
+                Ok(messages) if messages.is_empty() => None,
+                result => Some((result, generator)),
+            }
+        })
+    }
+}
+
+/// GeneratorControl is a cloneable handle to pause and resume a running generator. While paused,
+/// the generator does not emit any messages, once resumed it continues from the current time-period.
+/// It can also be used to change the RPU of a running generator, or to reset its quota.
+#[derive(Clone)]
+pub(crate) struct GeneratorControl {
+    state: Arc<ControlState>,
+}
+
+struct ControlState {
+    paused: AtomicBool,
+    /// current requests per unit of time-period.
+    rpu: AtomicUsize,
+    /// the quota has to be reset on the next read.
+    reset_quota: AtomicBool,
+    /// waker of the parked generator, woken up on resume or on change of rpu.
+    waker: AtomicWaker,
+}
+
+impl GeneratorControl {
+    fn new(rpu: usize) -> Self {
+        Self {
+            state: Arc::new(ControlState {
+                paused: AtomicBool::new(false),
+                rpu: AtomicUsize::new(rpu),
+                reset_quota: AtomicBool::new(false),
+                waker: AtomicWaker::new(),
+            }),
+        }
+    }
+
+    /// Pauses the generator.
+    #[cfg(test)]
+    pub(crate) fn pause(&self) {
+        self.state.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Resumes a paused generator.
+    #[cfg(test)]
+    pub(crate) fn resume(&self) {
+        self.state.paused.store(false, Ordering::SeqCst);
+        self.state.waker.wake();
+    }
+
+    /// Returns true if the generator is paused.
+    pub(crate) fn is_paused(&self) -> bool {
+        self.state.paused.load(Ordering::SeqCst)
+    }
+
+    /// Changes the RPU of the generator, it is applied from the next read. If the quota of the
+    /// current time-period was already used up, a higher RPU lets the generator emit more
+    /// messages in the same time-period.
+    #[cfg(test)]
+    pub(crate) fn set_rpu(&self, rpu: usize) {
+        self.store_rpu(rpu);
+        self.state.waker.wake();
+    }
+
+    /// Resets the quota of the generator, so that the next read starts a fresh time-period with
+    /// the full RPU available right away. Useful for stepwise load patterns.
+    #[cfg(test)]
+    pub(crate) fn reset_quota(&self) {
+        self.state.reset_quota.store(true, Ordering::SeqCst);
+        self.state.waker.wake();
+    }
+
+    /// returns true (only once) if the quota has to be reset.
+    fn take_quota_reset(&self) -> bool {
+        self.state.reset_quota.swap(false, Ordering::SeqCst)
+    }
+
+    /// stores the rpu without waking up the generator.
+    fn store_rpu(&self, rpu: usize) {
+        self.state.rpu.store(rpu, Ordering::SeqCst);
+    }
+
+    /// Returns the current RPU of the generator.
+    pub(crate) fn rpu(&self) -> usize {
+        self.state.rpu.load(Ordering::SeqCst)
+    }
+
+    /// registers the waker to be woken up on resume or on change of rpu.
+    fn register(&self, cx: &std::task::Context<'_>) {
+        self.state.waker.register(cx.waker());
+    }
+}
+
+/// GeneratorTrigger is a cloneable handle to step through a generator, each trigger lets the
+/// generator emit one batch. Triggers which are not consumed yet add up.
+#[derive(Clone)]
+pub(crate) struct GeneratorTrigger {
+    state: Arc<TriggerState>,
+}
+
+struct TriggerState {
+    /// number of batches yet to be emitted.
+    pending: AtomicUsize,
+    /// waker of the generator waiting for a trigger.
+    waker: AtomicWaker,
+}
+
+impl GeneratorTrigger {
+    #[cfg(test)]
+    fn new() -> Self {
+        Self {
+            state: Arc::new(TriggerState {
+                pending: AtomicUsize::new(0),
+                waker: AtomicWaker::new(),
+            }),
+        }
+    }
+
+    /// Lets the generator emit the next batch.
+    #[cfg(test)]
+    pub(crate) fn trigger(&self) {
+        self.state.pending.fetch_add(1, Ordering::SeqCst);
+        self.state.waker.wake();
+    }
+
+    /// returns true if there was a pending trigger, consuming it.
+    fn take(&self) -> bool {
+        self.state
+            .pending
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |pending| {
+                pending.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// registers the waker to be woken up on the next trigger.
+    fn register(&self, cx: &std::task::Context<'_>) {
+        self.state.waker.register(cx.waker());
+    }
+}
 
 impl source::SourceReader for GeneratorRead {
     fn name(&self) -> &'static str {
@@ -343,57 +1885,116 @@ impl source::SourceReader for GeneratorRead {
     }
 
     async fn read(&mut self) -> crate::error::Result<Vec<Message>> {
-        let Some(messages) = self.stream_generator.next().await else {
-            panic!("Stream generator has stopped");
+        // a bounded generator returns no more messages once it has stopped
+        let Some(mut messages) = self.stream_generator.next().await else {
+            self.exhausted = true;
+            return Ok(vec![]);
         };
+        for (index, message) in messages.iter_mut().enumerate() {
+            message.id = self.id_strategy.generate(&MessageIdContext {
+                message,
+                index: index as i32,
+            });
+        }
+        // the heartbeats are not generated data
+        let data = messages
+            .iter()
+            .filter(|m| !m.headers.contains_key(HEARTBEAT_HEADER))
+            .count();
+        self.emitted.fetch_add(data as u64, Ordering::Relaxed);
         Ok(messages)
     }
 
     fn partitions(&self) -> Vec<u16> {
-        todo!()
+        self.partitions.clone()
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.exhausted
     }
 }
 
-pub(crate) struct GeneratorAck {}
+pub(crate) struct GeneratorAck {
+    observer: Arc<dyn source::AckObserver>,
+}
 
 impl GeneratorAck {
     fn new() -> Self {
-        Self {}
+        Self::with_observer(Arc::new(source::NoopAckObserver))
+    }
+
+    /// Same as [GeneratorAck::new], the observer is notified after every ack. The generator has
+    /// nothing to acknowledge, so the reported duration is (close to) zero and only the count is
+    /// meaningful, see [crate::source::user_defined::UserDefinedSourceAck] for the real round trip.
+    pub(crate) fn with_observer(observer: Arc<dyn source::AckObserver>) -> Self {
+        Self { observer }
     }
 }
 
 impl source::SourceAcker for GeneratorAck {
-    async fn ack(&mut self, _: Vec<Offset>) -> crate::error::Result<()> {
+    async fn ack(&mut self, offsets: Vec<Offset>) -> crate::error::Result<()> {
+        let start = Instant::now();
+        self.observer.on_ack(offsets.len(), start.elapsed());
         Ok(())
     }
+
+    async fn ack_with_results(
+        &mut self,
+        offsets: Vec<Offset>,
+    ) -> crate::error::Result<Vec<source::AckResult>> {
+        let start = Instant::now();
+        // there is nothing to be acked for the generator, hence it never fails.
+        let results: Vec<source::AckResult> = offsets.iter().map(|_| Ok(())).collect();
+        self.observer.on_ack(results.len(), start.elapsed());
+        Ok(results)
+    }
 }
 
 #[derive(Clone)]
-pub(crate) struct GeneratorLagReader {}
+pub(crate) struct GeneratorLagReader {
+    /// synthetic backlog to be reported as pending.
+    pending: Option<usize>,
+}
 
 impl GeneratorLagReader {
-    fn new() -> Self {
-        Self {}
+    fn new(pending: Option<usize>) -> Self {
+        Self { pending }
     }
 }
 
 impl reader::LagReader for GeneratorLagReader {
     async fn pending(&mut self) -> crate::error::Result<Option<usize>> {
-        // Generator is not meant to auto-scale.
-        Ok(None)
+        // Generator is not meant to auto-scale, unless a synthetic backlog is configured to
+        // exercise the autoscaler.
+        Ok(self.pending)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::{HashMap, HashSet};
+
     use bytes::Bytes;
+    use chrono::{TimeZone, Utc};
+    use futures::FutureExt;
+    use rand::Rng;
     use tokio::time::Duration;
 
     use super::*;
-    use crate::message::StringOffset;
+    use crate::config::components::source::{
+        Compression, EventTimeMode, EventTimeSkew, JsonFieldType, KeySelection, KeySpec,
+        OffsetType, PayloadFormat, RampConfig, SizeDistribution,
+    };
+    use crate::config::{get_vertex_replica, VertexContext};
+    use crate::message::{IntOffset, StringOffset, CONTENT_TYPE_HEADER, EXPIRES_AT_HEADER};
     use crate::reader::LagReader;
     use crate::source::{SourceAcker, SourceReader};
 
+    /// returns a clock which only moves when it is advanced, starting at a fixed time.
+    fn mock_clock() -> clock::MockClock {
+        clock::MockClock::new(Utc.timestamp_opt(1_700_000_000, 0).unwrap())
+    }
+
     #[tokio::test]
     async fn test_generator_read() {
         // Define the content to be generated
@@ -466,33 +2067,2042 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_generator_lag_pending() {
-        // Create a new GeneratorLagReader
-        let mut lag_reader = GeneratorLagReader::new();
+    async fn test_generator_read_with_key_spec_round_robin() {
+        let rpu = 10;
+        let batch = 4;
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu,
+            duration: Duration::from_millis(100),
+            key_spec: Some(KeySpec {
+                keys: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                selection: KeySelection::RoundRobin,
+            }),
+            ..Default::default()
+        };
 
-        // Call the pending method and check the result
-        let pending_result = lag_reader.pending().await;
+        let mut generator = GeneratorRead::new(cfg, batch);
 
-        // Assert that the result is Ok(None)
-        assert!(pending_result.is_ok());
-        assert_eq!(pending_result.unwrap(), None);
+        // keys should continue to cycle across the batches
+        let mut keys = vec![];
+        for _ in 0..2 {
+            let messages = generator.read().await.unwrap();
+            assert_eq!(messages.len(), batch);
+            keys.extend(messages.into_iter().map(|m| m.keys));
+        }
+
+        let expected_keys = ["a", "b", "c", "a", "b", "c", "a", "b"]
+            .iter()
+            .map(|k| vec![k.to_string()])
+            .collect::<Vec<_>>();
+        assert_eq!(keys, expected_keys);
     }
 
     #[tokio::test]
-    async fn test_generator_ack() {
-        // Create a new GeneratorAck instance
-        let mut generator_ack = GeneratorAck::new();
+    async fn test_generator_read_with_key_rpu() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 100,
+            duration: Duration::from_secs(1),
+            key_rpu: vec![("hot".to_string(), 5), ("cold".to_string(), 2)],
+            ..Default::default()
+        };
+        let mut generator = GeneratorRead::with_clock(cfg, 100, Arc::new(clock.clone()));
 
-        // Create a vector of offsets to acknowledge
-        let offsets = vec![
-            Offset::String(StringOffset::new("offset1".to_string(), 0)),
-            Offset::String(StringOffset::new("offset2".to_string(), 0)),
-        ];
+        for _ in 0..3 {
+            let messages = generator
+                .read()
+                .now_or_never()
+                .expect("read should not wait at the start of the time-period")
+                .unwrap();
+            let mut counts: HashMap<String, usize> = HashMap::new();
+            for message in messages {
+                *counts.entry(message.keys[0].clone()).or_default() += 1;
+            }
+            // the keys are capped to their rpu even though the generator rpu is higher
+            assert_eq!(
+                counts,
+                HashMap::from([("hot".to_string(), 5), ("cold".to_string(), 2)])
+            );
 
-        // Call the ack method and check the result
-        let ack_result = generator_ack.ack(offsets).await;
+            // nothing more till the next time-period once the keys have used up their quota
+            assert!(generator.read().now_or_never().is_none());
+            clock.advance(Duration::from_secs(1));
+        }
+    }
 
-        // Assert that the result is Ok(())
-        assert!(ack_result.is_ok());
+    #[tokio::test]
+    async fn test_generator_read_with_idle_heartbeat() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_secs(10),
+            idle_heartbeat: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let mut generator = GeneratorRead::with_clock(cfg, 100, Arc::new(clock.clone()));
+        let mut read = || {
+            generator
+                .read()
+                .now_or_never()
+                .map(|messages| messages.unwrap())
+        };
+
+        let messages = read().expect("read should not wait at the start of the time-period");
+        assert_eq!(messages.len(), 10);
+        assert!(messages
+            .iter()
+            .all(|m| !m.headers.contains_key(HEARTBEAT_HEADER)));
+        // the quota is used up, but we have not been idle for long
+        assert!(read().is_none());
+
+        // a heartbeat for every interval of idleness
+        for _ in 0..2 {
+            clock.advance(Duration::from_secs(1));
+            let messages = read().expect("heartbeat should be emitted once idle");
+            assert_eq!(messages.len(), 1);
+            assert!(messages[0].value.is_empty());
+            assert!(messages[0].keys.is_empty());
+            assert_eq!(messages[0].headers.get(HEARTBEAT_HEADER).unwrap(), "true");
+            assert!(read().is_none());
+        }
+        // the heartbeats are not counted as emitted
+        assert_eq!(generator.emitted_count(), 10);
+
+        // data is generated as usual in the next time-period
+        clock.advance(Duration::from_secs(8));
+        let messages = generator.read().now_or_never().unwrap().unwrap();
+        assert_eq!(messages.len(), 10);
+        assert_eq!(generator.emitted_count(), 20);
+    }
+
+    #[tokio::test]
+    async fn test_generator_heartbeat_offsets() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_secs(10),
+            idle_heartbeat: Some(Duration::from_secs(1)),
+            offset_type: OffsetType::Int,
+            ..Default::default()
+        };
+        let mut generator = GeneratorRead::with_clock(cfg, 100, Arc::new(clock.clone()));
+        let sequences = |messages: &[Message]| -> Vec<u64> {
+            messages
+                .iter()
+                .map(|m| match m.offset.as_ref().unwrap() {
+                    Offset::Int(offset) => offset.offset,
+                    offset => panic!("unexpected offset {offset}"),
+                })
+                .collect()
+        };
+
+        let messages = generator.read().now_or_never().unwrap().unwrap();
+        assert_eq!(sequences(&messages), (0..10).collect::<Vec<u64>>());
+
+        let mut heartbeat_offsets = vec![];
+        for _ in 0..2 {
+            clock.advance(Duration::from_secs(1));
+            let heartbeats = generator.read().now_or_never().unwrap().unwrap();
+            assert_eq!(heartbeats.len(), 1);
+            heartbeat_offsets.push(heartbeats[0].offset.clone().unwrap());
+        }
+        assert!(heartbeat_offsets[0] < heartbeat_offsets[1]);
+        assert!(heartbeat_offsets
+            .iter()
+            .all(|offset| offset.to_string().starts_with("heartbeat.")));
+
+        // the heartbeats have not taken up any of the sequences of the data
+        clock.advance(Duration::from_secs(8));
+        let messages = generator.read().now_or_never().unwrap().unwrap();
+        assert_eq!(sequences(&messages), (10..20).collect::<Vec<u64>>());
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_key_spec_random() {
+        let candidates = vec!["a".to_string(), "b".to_string()];
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            key_spec: Some(KeySpec {
+                keys: candidates.clone(),
+                selection: KeySelection::Random,
+            }),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 10);
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 10);
+        for message in messages {
+            assert_eq!(message.keys.len(), 1);
+            assert!(candidates.contains(&message.keys[0]));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_distinct_keys() {
+        let candidates: Vec<String> = (0..5).map(|i| format!("key-{i}")).collect();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 100,
+            duration: Duration::from_millis(100),
+            key_spec: Some(KeySpec {
+                keys: candidates.clone(),
+                selection: KeySelection::Random,
+            }),
+            seed: Some(7),
+            distinct_keys_cap: Some(16),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg.clone(), 100);
+        assert_eq!(generator.distinct_keys(), 0);
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 100);
+        let emitted: HashSet<String> = messages.into_iter().flat_map(|m| m.keys).collect();
+        assert_eq!(emitted.len(), candidates.len());
+        assert_eq!(generator.distinct_keys(), candidates.len());
+
+        // tracking stops at the cap
+        let mut generator = GeneratorRead::new(
+            GeneratorConfig {
+                distinct_keys_cap: Some(2),
+                ..cfg.clone()
+            },
+            100,
+        );
+        generator.read().await.unwrap();
+        assert_eq!(generator.distinct_keys(), 2);
+
+        // the keys are not tracked by default
+        let mut generator = GeneratorRead::new(
+            GeneratorConfig {
+                distinct_keys_cap: None,
+                ..cfg
+            },
+            100,
+        );
+        generator.read().await.unwrap();
+        assert_eq!(generator.distinct_keys(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_seed() {
+        // returns the keys and the payloads of the first few reads
+        async fn generate(seed: u64) -> Vec<(Vec<String>, Bytes)> {
+            let cfg = GeneratorConfig {
+                rpu: 20,
+                duration: Duration::from_millis(100),
+                value: Some(1),
+                msg_size_bytes: 64,
+                key_spec: Some(KeySpec {
+                    keys: (0..10).map(|i| format!("key-{}", i)).collect(),
+                    selection: KeySelection::Random,
+                }),
+                size_distribution: Some(SizeDistribution {
+                    min_bytes: 10,
+                    max_bytes: 100,
+                    seed: None,
+                }),
+                seed: Some(seed),
+                ..Default::default()
+            };
+
+            let mut generator = GeneratorRead::new(cfg, 10);
+            let mut output = vec![];
+            for _ in 0..2 {
+                for message in generator.read().await.unwrap() {
+                    output.push((message.keys, message.value));
+                }
+            }
+            output
+        }
+
+        let output = generate(7).await;
+        assert_eq!(output.len(), 20);
+        assert_eq!(output, generate(7).await);
+        assert_ne!(output, generate(8).await);
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_synthetic_event_time() {
+        let start = chrono::Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let step = Duration::from_millis(500);
+        let batch = 3;
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            event_time: EventTimeMode::Synthetic { start, step },
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, batch);
+
+        // the synthetic clock should keep advancing across the batches
+        let mut event_times = vec![];
+        for _ in 0..2 {
+            let messages = generator.read().await.unwrap();
+            assert_eq!(messages.len(), batch);
+            event_times.extend(messages.into_iter().map(|m| m.event_time));
+        }
+
+        let expected = (0..6u32).map(|i| start + step * i).collect::<Vec<_>>();
+        assert_eq!(event_times, expected);
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_event_time_skew() {
+        let start = chrono::Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let step = Duration::from_secs(1);
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 1000,
+            duration: Duration::from_millis(100),
+            event_time: EventTimeMode::Synthetic { start, step },
+            event_time_skew: Some(EventTimeSkew {
+                late_fraction: 0.2,
+                max_lateness: Duration::from_secs(5),
+                seed: Some(42),
+            }),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 1000);
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 1000);
+
+        let mut late = 0;
+        for (i, message) in messages.iter().enumerate() {
+            let expected = start + step * i as u32;
+            assert!(message.event_time <= expected);
+            assert!(message.event_time >= expected - Duration::from_secs(5));
+            if message.event_time < expected {
+                late += 1;
+            }
+        }
+        // roughly 20% of the messages are late
+        assert!((150..=250).contains(&late), "late messages: {}", late);
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_max_messages() {
+        let batch = 5;
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            max_messages: Some(7),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, batch);
+
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 5);
+
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 2);
+
+        // generator has stopped, no more messages
+        let messages = generator.read().await.unwrap();
+        assert!(messages.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_payloads() {
+        let payloads = vec![
+            Bytes::from("payload-0"),
+            Bytes::from("payload-1"),
+            Bytes::from("payload-2"),
+        ];
+        let batch = 5;
+        let cfg = GeneratorConfig {
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            payloads: payloads.clone(),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, batch);
+
+        // payloads should be cycled within and across the batches
+        let mut values = vec![];
+        for _ in 0..2 {
+            let messages = generator.read().await.unwrap();
+            assert_eq!(messages.len(), batch);
+            values.extend(messages.into_iter().map(|m| m.value));
+        }
+
+        let expected = (0..10).map(|i| payloads[i % 3].clone()).collect::<Vec<_>>();
+        assert_eq!(values, expected);
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_size_distribution() {
+        let content = Bytes::from("test_data");
+        let cfg = GeneratorConfig {
+            content: content.clone(),
+            rpu: 20,
+            duration: Duration::from_millis(100),
+            size_distribution: Some(SizeDistribution {
+                min_bytes: 4,
+                max_bytes: 64,
+                seed: Some(7),
+            }),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 10);
+
+        let mut sizes = vec![];
+        for _ in 0..2 {
+            for message in generator.read().await.unwrap() {
+                let size = message.value.len();
+                assert!((4..=64).contains(&size));
+                // the payload is the base content truncated or repeated
+                let expected: Vec<u8> = content.iter().copied().cycle().take(size).collect();
+                assert_eq!(message.value.to_vec(), expected);
+                sizes.push(size);
+            }
+        }
+        assert_eq!(sizes.len(), 20);
+        assert!(sizes.iter().any(|size| *size != sizes[0]));
+    }
+
+    #[tokio::test]
+    async fn test_generator_into_message_stream() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let generator = GeneratorRead::new(cfg, 5);
+
+        let batches: Vec<_> = generator.into_message_stream().take(2).collect().await;
+        assert_eq!(batches.len(), 2);
+        for batch in batches {
+            let batch = batch.unwrap();
+            assert_eq!(batch.len(), 5);
+            assert_eq!(batch[0].value, Bytes::from("test_data"));
+        }
+
+        // the stream of a bounded generator ends once it stops
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            max_messages: Some(7),
+            ..Default::default()
+        };
+        let generator = GeneratorRead::new(cfg, 5);
+        let sizes: Vec<usize> = generator
+            .into_message_stream()
+            .map(|batch| batch.unwrap().len())
+            .collect()
+            .await;
+        assert_eq!(sizes, vec![5, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_generator_into_message_stream_with_full_backpressure() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let (pressure_tx, pressure_rx) = watch::channel(1.0);
+        let generator = GeneratorRead::with_clock(cfg, 10, Arc::new(clock.clone()))
+            .with_backpressure(pressure_rx);
+        let mut stream = std::pin::pin!(generator.into_message_stream());
+
+        // the stream does not end while the generator is throttled down to 0
+        assert!(stream.next().now_or_never().is_none());
+        clock.advance(Duration::from_secs(1));
+        assert!(stream.next().now_or_never().is_none());
+
+        pressure_tx.send(0.0).unwrap();
+        clock.advance(Duration::from_secs(1));
+        let batch = stream.next().now_or_never().unwrap().unwrap().unwrap();
+        assert_eq!(batch.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_tombstones() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 1000,
+            key_count: 2,
+            duration: Duration::from_millis(100),
+            tombstone_fraction: 0.3,
+            seed: Some(42),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 1000);
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 1000);
+
+        let tombstones: Vec<_> = messages.iter().filter(|m| m.value.is_empty()).collect();
+        assert!(
+            (250..=350).contains(&tombstones.len()),
+            "tombstones: {}",
+            tombstones.len()
+        );
+        // tombstones still have valid keys and offsets
+        assert!(tombstones
+            .iter()
+            .all(|m| m.keys.len() == 1 && m.offset.is_some()));
+        assert!(messages
+            .iter()
+            .filter(|m| !m.value.is_empty())
+            .all(|m| m.value == Bytes::from("test_data")));
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_fill_pattern() {
+        let content = Bytes::from("test_data");
+        let cfg = GeneratorConfig {
+            content: content.clone(),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            size_distribution: Some(SizeDistribution {
+                min_bytes: 32,
+                max_bytes: 32,
+                seed: Some(7),
+            }),
+            fill_pattern: vec![0x00, 0xff],
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 10);
+
+        for message in generator.read().await.unwrap() {
+            assert_eq!(message.value.len(), 32);
+            // the payload is followed by the fill pattern
+            assert_eq!(&message.value[..content.len()], &content[..]);
+            let padding: Vec<u8> = [0x00, 0xff]
+                .into_iter()
+                .cycle()
+                .take(32 - content.len())
+                .collect();
+            assert_eq!(&message.value[content.len()..], &padding[..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_csv_payload() {
+        let cfg = GeneratorConfig {
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            payload_format: PayloadFormat::Csv {
+                columns: 4,
+                rows_per_message: 3,
+                seed: Some(11),
+            },
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 5);
+
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 5);
+        for message in messages {
+            let body = String::from_utf8(message.value.to_vec()).unwrap();
+            let rows: Vec<Vec<&str>> = body.lines().map(|line| line.split(',').collect()).collect();
+
+            // header followed by the rows
+            assert_eq!(rows.len(), 4);
+            assert_eq!(rows[0], vec!["col-0", "col-1", "col-2", "col-3"]);
+            for row in &rows[1..] {
+                assert_eq!(row.len(), 4);
+                assert!(row.iter().all(|value| value.parse::<u32>().is_ok()));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_json_payload() {
+        let cfg = GeneratorConfig {
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            payload_format: PayloadFormat::Json {
+                fields: vec![
+                    ("id".to_string(), JsonFieldType::Int),
+                    ("name".to_string(), JsonFieldType::String),
+                    ("active".to_string(), JsonFieldType::Bool),
+                    ("created_at".to_string(), JsonFieldType::Timestamp),
+                ],
+                seed: Some(3),
+            },
+            ..Default::default()
+        };
+
+        let clock = mock_clock();
+        let mut generator = GeneratorRead::with_clock(cfg, 5, Arc::new(clock.clone()));
+
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 5);
+        for message in messages {
+            assert_eq!(
+                message.headers.get(CONTENT_TYPE_HEADER).map(String::as_str),
+                Some("application/json")
+            );
+            let object: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_slice(&message.value).unwrap();
+            assert_eq!(object.len(), 4);
+            assert!(object["id"].is_i64());
+            assert!(object["name"].is_string());
+            assert!(object["active"].is_boolean());
+            // the timestamps are taken from the clock
+            assert_eq!(
+                object["created_at"].as_str(),
+                Some(clock.now().to_rfc3339().as_str())
+            );
+        }
+
+        // the raw content is user provided, so its content type is unknown
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut generator = GeneratorRead::new(cfg, 5);
+        let messages = generator.read().await.unwrap();
+        assert!(messages
+            .iter()
+            .all(|message| !message.headers.contains_key(CONTENT_TYPE_HEADER)));
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_protobuf_payload() {
+        use prost::Message as _;
+
+        let keys = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let cfg = GeneratorConfig {
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            payload_format: PayloadFormat::Protobuf { keys: keys.clone() },
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 5);
+
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 5);
+        for (i, message) in messages.into_iter().enumerate() {
+            let record = GeneratedRecord::decode(message.value).unwrap();
+            let expected: Vec<GeneratedEntry> = keys
+                .iter()
+                .enumerate()
+                .map(|(j, key)| GeneratedEntry {
+                    key: key.clone(),
+                    value: (i * keys.len() + j) as i64,
+                })
+                .collect();
+            assert_eq!(record.entries, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_sequence_prefix() {
+        let content = Bytes::from("test_data");
+        let cfg = GeneratorConfig {
+            content: content.clone(),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            sequence_prefix: true,
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 5);
+
+        let mut sequences = vec![];
+        for _ in 0..2 {
+            for message in generator.read().await.unwrap() {
+                let (seq, rest) = message.value.split_at(8);
+                sequences.push(u64::from_be_bytes(seq.try_into().unwrap()));
+                assert_eq!(rest, content.as_ref());
+            }
+        }
+        assert_eq!(sequences, (0..10).collect::<Vec<u64>>());
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_payload_framing() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            payload_prefix: Bytes::from("<"),
+            payload_suffix: Bytes::from(">\n"),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 5);
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 5);
+        for message in messages {
+            assert_eq!(message.value, Bytes::from("<test_data>\n"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_message_index() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 5);
+
+        for _ in 0..2 {
+            let messages = generator.read().await.unwrap();
+            let indices: Vec<i32> = messages.iter().map(|m| m.id.index).collect();
+            assert_eq!(indices, (0..messages.len() as i32).collect::<Vec<i32>>());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_warmup_burst() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            warmup_burst: 50,
+            ..Default::default()
+        };
+
+        let start = tokio::time::Instant::now();
+        let mut generator = GeneratorRead::new(cfg, 5);
+
+        // the burst is returned in batches
+        for _ in 0..10 {
+            assert_eq!(generator.read().await.unwrap().len(), 5);
+        }
+
+        // followed by the normal quota of the time-period
+        assert_eq!(generator.read().await.unwrap().len(), 5);
+        assert_eq!(generator.read().await.unwrap().len(), 5);
+        assert_eq!(generator.read().await.unwrap().len(), 5);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_gzip_compression() {
+        let content = Bytes::from("test_data");
+        let cfg = GeneratorConfig {
+            content: content.clone(),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            compression: Some(Compression::Gzip),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 5);
+
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 5);
+        for message in messages {
+            let mut decoder = flate2::read::GzDecoder::new(message.value.as_ref());
+            let mut decompressed = vec![];
+            std::io::Read::read_to_end(&mut decoder, &mut decompressed).unwrap();
+            assert_eq!(decompressed, content.to_vec());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_int_offset() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            offset_type: OffsetType::Int,
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 5);
+
+        let mut offsets = vec![];
+        for _ in 0..2 {
+            let messages = generator.read().await.unwrap();
+            for message in messages {
+                let Some(Offset::Int(offset)) = message.offset else {
+                    panic!("expected an int offset");
+                };
+                offsets.push(offset);
+            }
+        }
+
+        assert_eq!(offsets.len(), 10);
+        // offsets should strictly increase across the reads
+        assert!(offsets.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[tokio::test]
+    async fn test_generator_pause_resume() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 5);
+        let control = generator.control();
+
+        control.pause();
+        assert!(control.is_paused());
+
+        // read should not complete while the generator is paused
+        let result = tokio::time::timeout(Duration::from_millis(300), generator.read()).await;
+        assert!(result.is_err());
+
+        // resume from another task, the pending read should get woken up
+        let resume_control = control.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            resume_control.resume();
+        });
+
+        let messages = tokio::time::timeout(Duration::from_secs(1), generator.read())
+            .await
+            .expect("read should complete after resume")
+            .unwrap();
+        assert_eq!(messages.len(), 5);
+        assert!(!control.is_paused());
+    }
+
+    #[tokio::test]
+    async fn test_generator_set_rpu() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            // long enough to not tick again during the test
+            duration: Duration::from_secs(60),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 10);
+        let control = generator.control();
+
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 10);
+
+        // quota of the current tick is used up
+        let blocked = tokio::time::timeout(Duration::from_millis(50), generator.read()).await;
+        assert!(blocked.is_err());
+
+        // bumping the rpu gives more quota in the same tick
+        control.set_rpu(20);
+        assert_eq!(control.rpu(), 20);
+        let messages = tokio::time::timeout(Duration::from_millis(50), generator.read())
+            .await
+            .expect("read should not wait for the next tick")
+            .unwrap();
+        assert_eq!(messages.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_ramp() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 1000,
+            duration: Duration::from_millis(100),
+            ramp: Some(RampConfig {
+                start_rpu: 2,
+                target_rpu: 20,
+                ramp_duration: Duration::from_secs(60),
+            }),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 50);
+        let control = generator.control();
+
+        // the ramp starts from the start_rpu and not the configured rpu
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(control.rpu(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_mock_clock() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_secs(1),
+            jitter: Duration::ZERO,
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::with_clock(cfg, 5, Arc::new(clock.clone()));
+
+        // event-times are as per the clock
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 5);
+        assert!(messages.iter().all(|m| m.event_time == clock.now()));
+        assert_eq!(generator.read().await.unwrap().len(), 5);
+
+        // quota is used up, the generator waits for the clock to advance
+        assert!(generator.read().now_or_never().is_none());
+        clock.advance(Duration::from_millis(999));
+        assert!(generator.read().now_or_never().is_none());
+
+        clock.advance(Duration::from_millis(1));
+        let messages = generator
+            .read()
+            .now_or_never()
+            .expect("read should not wait after the clock advanced")
+            .unwrap();
+        assert_eq!(messages.len(), 5);
+        assert!(messages.iter().all(|m| m.event_time == clock.now()));
+    }
+
+    #[tokio::test]
+    async fn test_generator_reset_quota() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::with_clock(cfg, 10, Arc::new(clock.clone()));
+        let control = generator.control();
+
+        // exhaust the quota
+        assert_eq!(generator.read().await.unwrap().len(), 10);
+        assert!(generator.read().now_or_never().is_none());
+
+        // a full batch can be read right away after the reset
+        clock.advance(Duration::from_millis(500));
+        control.reset_quota();
+        let messages = generator
+            .read()
+            .now_or_never()
+            .expect("read should not wait after the quota was reset")
+            .unwrap();
+        assert_eq!(messages.len(), 10);
+
+        // the time-period restarts from the reset, so the tick does not grant a second quota
+        // within the same time-period
+        clock.advance(Duration::from_millis(500));
+        assert!(generator.read().now_or_never().is_none());
+        clock.advance(Duration::from_millis(500));
+        let messages = generator
+            .read()
+            .now_or_never()
+            .expect("read should not wait after the time-period")
+            .unwrap();
+        assert_eq!(messages.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_backpressure() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let (pressure_tx, pressure_rx) = watch::channel(0.0);
+
+        let mut generator = GeneratorRead::with_clock(cfg, 100, Arc::new(clock.clone()))
+            .with_backpressure(pressure_rx);
+        assert_eq!(generator.read().await.unwrap().len(), 10);
+
+        // the emission slows down as the pressure rises
+        for (pressure, expected) in [(0.5, 5), (0.75, 3)] {
+            pressure_tx.send(pressure).unwrap();
+            clock.advance(Duration::from_secs(1));
+            let messages = generator
+                .read()
+                .now_or_never()
+                .expect("read should not wait after the clock advanced")
+                .unwrap();
+            assert_eq!(messages.len(), expected, "pressure {pressure}");
+        }
+
+        // nothing is emitted at the full pressure, the read waits instead of returning an empty
+        // batch
+        pressure_tx.send(1.0).unwrap();
+        clock.advance(Duration::from_secs(1));
+        assert!(generator.read().now_or_never().is_none());
+
+        pressure_tx.send(0.0).unwrap();
+        clock.advance(Duration::from_secs(1));
+        let messages = generator.read().now_or_never().unwrap().unwrap();
+        assert_eq!(messages.len(), 10);
+    }
+
+    /// PollEvents captures the fields of the "Generator polled" events.
+    #[derive(Default, Clone)]
+    struct PollEvents(Arc<std::sync::Mutex<Vec<HashMap<String, String>>>>);
+
+    #[derive(Default)]
+    struct EventFields(HashMap<String, String>);
+
+    impl tracing::field::Visit for EventFields {
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{value:?}"));
+        }
+    }
+
+    impl tracing::Subscriber for PollEvents {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            tracing::span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, event: &tracing::Event<'_>) {
+            let mut fields = EventFields::default();
+            event.record(&mut fields);
+            if fields.0.get("message").map(String::as_str) == Some("Generator polled") {
+                self.0.lock().unwrap().push(fields.0);
+            }
+        }
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_max_batch_bytes() {
+        let cfg = GeneratorConfig {
+            // 100 bytes payloads including the sequence prefix
+            content: Bytes::from(vec![b'x'; 92]),
+            rpu: 100,
+            duration: Duration::from_secs(1),
+            sequence_prefix: true,
+            max_batch_bytes: Some(350),
+            ..Default::default()
+        };
+        let mut generator = GeneratorRead::new(cfg, 10);
+
+        let mut sequences = vec![];
+        for _ in 0..4 {
+            let messages = generator.read().await.unwrap();
+            assert_eq!(messages.len(), 3);
+            let batch_bytes: usize = messages.iter().map(|m| m.value.len()).sum();
+            assert!(batch_bytes <= 350, "batch of {batch_bytes} bytes");
+            sequences.extend(
+                messages
+                    .iter()
+                    .map(|m| u64::from_be_bytes(m.value[..8].try_into().unwrap())),
+            );
+        }
+        // the messages which did not fit are not lost
+        assert_eq!(sequences, (0..12).collect::<Vec<u64>>());
+
+        // a message bigger than the cap is returned on its own
+        let cfg = GeneratorConfig {
+            content: Bytes::from(vec![b'x'; 500]),
+            rpu: 100,
+            duration: Duration::from_secs(1),
+            max_batch_bytes: Some(350),
+            ..Default::default()
+        };
+        let mut generator = GeneratorRead::new(cfg, 10);
+        assert_eq!(generator.read().await.unwrap().len(), 1);
+
+        let cfg = GeneratorConfig {
+            max_batch_bytes: Some(0),
+            ..Default::default()
+        };
+        assert!(cfg.try_into_generator(10).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_generator_poll_tracing() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let mut generator = GeneratorRead::with_clock(cfg, 5, Arc::new(clock.clone()));
+
+        let events = PollEvents::default();
+        tracing::subscriber::with_default(events.clone(), || {
+            // tick, then the remaining quota, then nothing is left till the next tick
+            for expected in [Some(5), Some(5), None] {
+                let messages = generator.read().now_or_never().map(|m| m.unwrap().len());
+                assert_eq!(messages, expected);
+            }
+        });
+
+        let events = events.0.lock().unwrap();
+        let fields: Vec<(&str, &str, &str)> = events
+            .iter()
+            .map(|e| {
+                (
+                    e["branch"].as_str(),
+                    e["used"].as_str(),
+                    e["emitted"].as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("tick", "5", "5"),
+                ("quota", "10", "5"),
+                ("pending", "10", "0")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_ramp_and_mock_clock() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 1000,
+            duration: Duration::from_secs(1),
+            ramp: Some(RampConfig {
+                start_rpu: 10,
+                target_rpu: 110,
+                ramp_duration: Duration::from_secs(10),
+            }),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::with_clock(cfg, 1000, Arc::new(clock.clone()));
+        let control = generator.control();
+
+        // every tick emits the rpu of the ramp at that time
+        for (seconds, rpu) in [(0, 10), (5, 60), (10, 110), (20, 110)] {
+            clock.advance(Duration::from_secs(seconds) - elapsed(&clock));
+            let messages = generator
+                .read()
+                .now_or_never()
+                .expect("read should not wait after the clock advanced")
+                .unwrap();
+            assert_eq!(messages.len(), rpu);
+            assert_eq!(control.rpu(), rpu);
+        }
+
+        // returns the time elapsed since the start of the test
+        fn elapsed(clock: &clock::MockClock) -> Duration {
+            let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+            (clock.now() - start).to_std().unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_estimated_drain_time() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(500),
+            max_messages: Some(45),
+            ..Default::default()
+        };
+        let assert_close = |actual: Option<Duration>, expected: Duration| {
+            let actual = actual.expect("bounded generator should have an estimate");
+            let diff = actual.abs_diff(expected);
+            assert!(
+                diff < Duration::from_millis(1),
+                "{actual:?} != {expected:?}"
+            );
+        };
+
+        let mut generator = GeneratorRead::with_clock(cfg.clone(), 5, Arc::new(clock.clone()));
+        // remaining / rpu * unit
+        assert_close(
+            generator.estimated_drain_time(),
+            Duration::from_millis(2250),
+        );
+
+        for _ in 0..2 {
+            generator.read().now_or_never().unwrap().unwrap();
+        }
+        assert_close(
+            generator.estimated_drain_time(),
+            Duration::from_millis(1750),
+        );
+
+        // the estimate follows the rpu
+        generator.control().set_rpu(5);
+        assert_close(
+            generator.estimated_drain_time(),
+            Duration::from_millis(3500),
+        );
+        generator.control().set_rpu(0);
+        assert_eq!(generator.estimated_drain_time(), None);
+
+        // unbounded generators never drain
+        let generator = GeneratorRead::with_clock(
+            GeneratorConfig {
+                max_messages: None,
+                ..cfg
+            },
+            5,
+            Arc::new(clock),
+        );
+        assert_eq!(generator.estimated_drain_time(), None);
+    }
+
+    #[tokio::test]
+    async fn test_generator_current_rpu() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 1000,
+            duration: Duration::from_secs(1),
+            ramp: Some(RampConfig {
+                start_rpu: 10,
+                target_rpu: 110,
+                ramp_duration: Duration::from_secs(10),
+            }),
+            ..Default::default()
+        };
+        let (pressure_tx, pressure_rx) = watch::channel(0.0);
+
+        let mut generator = GeneratorRead::with_clock(cfg, 1000, Arc::new(clock.clone()))
+            .with_backpressure(pressure_rx);
+
+        // the rpu follows the ramp as the time passes, even without reads
+        assert_eq!(generator.current_rpu(), 10);
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(generator.current_rpu(), 30);
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(generator.current_rpu(), 60);
+
+        // and matches what is emitted
+        let messages = generator
+            .read()
+            .now_or_never()
+            .expect("read should not wait on the first tick")
+            .unwrap();
+        assert_eq!(messages.len(), 60);
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(generator.current_rpu(), 110);
+
+        // the backpressure is reflected right away
+        pressure_tx.send(0.5).unwrap();
+        assert_eq!(generator.current_rpu(), 55);
+        pressure_tx.send(0.0).unwrap();
+
+        // once the ramp is complete, the rpu set on the fly is in effect
+        generator
+            .read()
+            .now_or_never()
+            .expect("read should not wait after the clock advanced")
+            .unwrap();
+        generator.control().set_rpu(42);
+        assert_eq!(generator.current_rpu(), 42);
+    }
+
+    /// UuidIdStrategy assigns a random (version 4) UUID to every message.
+    struct UuidIdStrategy;
+
+    impl MessageIdStrategy for UuidIdStrategy {
+        fn generate(&self, ctx: &MessageIdContext<'_>) -> MessageID {
+            let mut rng = rand::thread_rng();
+            let uuid = format!(
+                "{:08x}-{:04x}-4{:03x}-{:x}{:03x}-{:012x}",
+                rng.gen::<u32>(),
+                rng.gen::<u16>(),
+                rng.gen::<u16>() & 0xfff,
+                rng.gen_range(8..12),
+                rng.gen::<u16>() & 0xfff,
+                rng.gen::<u64>() & 0xffff_ffff_ffff,
+            );
+            MessageID {
+                vertex_name: ctx.message.id.vertex_name.clone(),
+                offset: uuid,
+                index: 0,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_id_strategy() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 100,
+            duration: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        // by default, the messages are indexed within the batch
+        let mut generator = GeneratorRead::new(cfg.clone(), 5);
+        let messages = generator.read().await.unwrap();
+        let indexes: Vec<i32> = messages.iter().map(|m| m.id.index).collect();
+        assert_eq!(indexes, vec![0, 1, 2, 3, 4]);
+        for message in &messages {
+            let offset = message.offset.as_ref().unwrap().to_string();
+            assert_eq!(message.id.offset, offset);
+        }
+
+        let mut generator = GeneratorRead::new(cfg, 100).with_id_strategy(Arc::new(UuidIdStrategy));
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 100);
+
+        let ids: HashSet<String> = messages.iter().map(|m| m.id.offset.clone()).collect();
+        assert_eq!(ids.len(), messages.len(), "the IDs should be unique");
+        for id in &ids {
+            let groups: Vec<usize> = id.split('-').map(str::len).collect();
+            assert_eq!(groups, vec![8, 4, 4, 4, 12]);
+            assert_eq!(&id[14..15], "4");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_debug_state() {
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let clock = clock::MockClock::new(start);
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_secs(1),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::with_clock(cfg, 4, Arc::new(clock.clone()));
+        let state = generator.debug_state();
+        assert_eq!(state.used, 0);
+        assert_eq!(state.emitted_total, 0);
+
+        for _ in 0..2 {
+            generator.read().now_or_never().unwrap().unwrap();
+        }
+        assert_eq!(
+            generator.debug_state(),
+            GeneratorState {
+                used: 8,
+                rpu: 10,
+                batch: 4,
+                emitted_total: 8,
+                current_period_start: start,
+            }
+        );
+
+        // the quota is granted again on the next tick
+        clock.advance(Duration::from_secs(1));
+        generator.read().now_or_never().unwrap().unwrap();
+        let state = generator.debug_state();
+        assert_eq!(state.used, 4);
+        assert_eq!(state.emitted_total, 12);
+        assert_eq!(state.current_period_start, start + Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_generator_start_delay() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_secs(1),
+            start_delay: Duration::from_secs(5),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::with_clock(cfg, 4, Arc::new(clock.clone()));
+        assert!(generator.read().now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(4));
+        assert!(generator.read().now_or_never().is_none());
+
+        // the data flows once the delay elapses
+        clock.advance(Duration::from_secs(1));
+        let messages = generator.read().now_or_never().unwrap().unwrap();
+        assert_eq!(messages.len(), 4);
+        let messages = generator.read().now_or_never().unwrap().unwrap();
+        assert_eq!(messages.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_generator_with_trigger() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+
+        let (mut generator, _, _, trigger) = new_generator_with_trigger(cfg, 5).unwrap();
+        // nothing is emitted till triggered, not even the quota of the first time-period
+        assert!(generator.read().now_or_never().is_none());
+
+        let mut batches = vec![];
+        for _ in 0..2 {
+            trigger.trigger();
+            batches.push(generator.read().now_or_never().unwrap().unwrap());
+            assert!(generator.read().now_or_never().is_none());
+        }
+        assert_eq!(batches.len(), 2);
+        assert!(batches.iter().all(|batch| batch.len() == 5));
+
+        // a read waiting for the trigger is woken up by it
+        let handle = tokio::spawn(async move { generator.read().await.unwrap().len() });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!handle.is_finished());
+        trigger.trigger();
+        assert_eq!(handle.await.unwrap(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_generator_drain() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_secs(60),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 4);
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 4);
+
+        generator.drain();
+
+        // only the remaining quota (10 - 4) of the current time-period is emitted
+        let mut drained = 0;
+        loop {
+            let messages = tokio::time::timeout(Duration::from_millis(100), generator.read())
+                .await
+                .expect("drain should not wait for the next tick")
+                .unwrap();
+            if messages.is_empty() {
+                break;
+            }
+            drained += messages.len();
+        }
+        assert_eq!(drained, 6);
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_headers() {
+        let headers = HashMap::from([
+            ("source".to_string(), "generator".to_string()),
+            ("env".to_string(), "test".to_string()),
+        ]);
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            headers: headers.clone(),
+            sequence_header: Some("x-seq".to_string()),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 5);
+
+        let mut seq = 0;
+        for _ in 0..2 {
+            let messages = generator.read().await.unwrap();
+            for message in messages {
+                assert_eq!(message.headers.len(), 3);
+                assert_eq!(message.headers.get("source").unwrap(), "generator");
+                assert_eq!(message.headers.get("env").unwrap(), "test");
+                assert_eq!(message.headers.get("x-seq").unwrap(), &seq.to_string());
+                seq += 1;
+            }
+        }
+        assert_eq!(seq, 10);
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_replica_header() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            replica_header: Some("x-replica".to_string()),
+            ..Default::default()
+        };
+        let mut generator = GeneratorRead::new(cfg.clone(), 5);
+
+        // the replica is read (once) from the NUMAFLOW_REPLICA env
+        let replica = get_vertex_replica().to_string();
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 5);
+        for message in messages {
+            assert_eq!(message.headers.get("x-replica"), Some(&replica));
+        }
+
+        // the header is not set by default
+        let mut generator = GeneratorRead::new(
+            GeneratorConfig {
+                replica_header: None,
+                ..cfg
+            },
+            5,
+        );
+        let messages = generator.read().await.unwrap();
+        assert!(messages.iter().all(|m| m.headers.is_empty()));
+    }
+
+    #[tokio::test]
+    async fn test_generator_read_with_ttl() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            ttl: Some(Duration::from_secs(30)),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::with_clock(cfg, 5, Arc::new(clock));
+        for message in generator.read().await.unwrap() {
+            assert_eq!(message.headers[EXPIRES_AT_HEADER], "1700000030000");
+        }
+
+        // messages do not expire by default
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut generator = GeneratorRead::new(cfg, 5);
+        for message in generator.read().await.unwrap() {
+            assert!(!message.headers.contains_key(EXPIRES_AT_HEADER));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_emitted_count() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::new(cfg, 4);
+        assert_eq!(generator.emitted_count(), 0);
+
+        let first = generator.read().await.unwrap();
+        let second = generator.read().await.unwrap();
+        assert_eq!(
+            generator.emitted_count(),
+            (first.len() + second.len()) as u64
+        );
+        assert_eq!(generator.emitted_count(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_generator_partitions() {
+        let generator = GeneratorRead::new(GeneratorConfig::default(), 5);
+        assert_eq!(generator.partitions(), vec![*get_vertex_replica()]);
+
+        let cfg = GeneratorConfig {
+            partitions: Some(vec![1, 3]),
+            ..Default::default()
+        };
+        let generator = GeneratorRead::new(cfg, 5);
+        assert_eq!(generator.partitions(), vec![1, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_generator_deterministic_offsets() {
+        async fn offsets(seed: u64, clock: clock::MockClock) -> Vec<Offset> {
+            let cfg = GeneratorConfig {
+                content: Bytes::from("test_data"),
+                rpu: 10,
+                duration: Duration::from_millis(100),
+                partitions: Some(vec![0, 1]),
+                offset_type: OffsetType::Deterministic { seed },
+                ..Default::default()
+            };
+            let mut generator = GeneratorRead::with_clock(cfg, 10, Arc::new(clock));
+            generator
+                .read()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|message| message.offset.unwrap())
+                .collect()
+        }
+
+        // the second generator is started later, like after a restart
+        let first = offsets(42, mock_clock()).await;
+        let second = offsets(
+            42,
+            clock::MockClock::new(Utc.timestamp_opt(1_700_000_600, 0).unwrap()),
+        )
+        .await;
+        assert_eq!(first.len(), 10);
+        assert_eq!(first, second);
+
+        // the offsets are unique
+        let unique: HashSet<String> = first.iter().map(|o| o.to_string()).collect();
+        assert_eq!(unique.len(), 10);
+
+        let other_seed = offsets(7, mock_clock()).await;
+        assert_ne!(first, other_seed);
+    }
+
+    #[tokio::test]
+    async fn test_generator_string_offsets_across_partitions() {
+        // the clock does not move, so all the offsets are created at the same instant
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            partitions: Some(vec![0, 1]),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::with_clock(cfg, 10, Arc::new(clock));
+
+        let offsets: Vec<StringOffset> = generator
+            .read()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|message| match message.offset {
+                Some(Offset::String(offset)) => offset,
+                _ => panic!("expected a string offset"),
+            })
+            .collect();
+        assert_eq!(offsets.len(), 10);
+
+        // messages are spread across the partitions and are sequenced within each of them
+        let replica = *get_vertex_replica();
+        for (i, offset) in offsets.iter().enumerate() {
+            let expected = StringOffset::sequenced(
+                1_700_000_000_000_000_000,
+                replica,
+                i as u64 / 2,
+                i as u16 % 2,
+            );
+            assert_eq!(offset, &expected);
+            assert_eq!(
+                offset.to_string().parse::<StringOffset>().unwrap(),
+                expected
+            );
+        }
+
+        let unique: HashSet<String> = offsets.iter().map(|offset| offset.to_string()).collect();
+        assert_eq!(unique.len(), offsets.len());
+    }
+
+    #[tokio::test]
+    async fn test_generator_with_vertex_context() {
+        let clock = mock_clock();
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 30,
+            duration: Duration::from_millis(100),
+            total_replicas: Some(3),
+            replica_header: Some("x-replica".to_string()),
+            vertex: Some(VertexContext {
+                name: "in".to_string(),
+                replica: 2,
+            }),
+            ..Default::default()
+        };
+
+        let mut generator = GeneratorRead::with_clock(cfg, 20, Arc::new(clock));
+        // the replica is a partition of its own
+        assert_eq!(generator.partitions(), vec![2]);
+
+        // the rpu is split across the replicas
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 10);
+        for (i, message) in messages.iter().enumerate() {
+            let expected = StringOffset::sequenced(1_700_000_000_000_000_000, 2, i as u64, 2);
+            assert_eq!(message.offset, Some(Offset::String(expected.clone())));
+            assert_eq!(message.id.vertex_name, "in");
+            assert_eq!(message.id.offset, expected.to_string());
+            assert_eq!(message.headers["x-replica"], "2");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_from_file() {
+        let tmp_dir = tempfile::TempDir::new().unwrap();
+        let path = tmp_dir.path().join("payload.json");
+        std::fs::write(&path, r#"{"name":"numaflow"}"#).unwrap();
+
+        let cfg = GeneratorConfig {
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let (mut generator, _, _) = new_generator_from_file(&path, cfg.clone(), 5).unwrap();
+
+        let messages = generator.read().await.unwrap();
+        assert_eq!(messages.len(), 5);
+        for message in messages {
+            assert_eq!(message.value, Bytes::from(r#"{"name":"numaflow"}"#));
+        }
+
+        // missing file is an error and not a panic
+        let result = new_generator_from_file(tmp_dir.path().join("missing"), cfg, 5);
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_try_into_generator() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            rpu: 10,
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let (mut generator, _, _) = cfg.clone().try_into_generator(5).unwrap();
+        assert_eq!(generator.read().await.unwrap().len(), 5);
+
+        // batch size higher than the rpu is capped to the rpu
+        let (mut generator, _, _) = cfg.clone().try_into_generator(50).unwrap();
+        assert_eq!(generator.read().await.unwrap().len(), 10);
+
+        let invalid_configs = [
+            (
+                GeneratorConfig {
+                    rpu: 0,
+                    ..cfg.clone()
+                },
+                5,
+            ),
+            (cfg.clone(), 0),
+            (
+                GeneratorConfig {
+                    duration: Duration::ZERO,
+                    ..cfg.clone()
+                },
+                5,
+            ),
+            (
+                GeneratorConfig {
+                    tombstone_fraction: 1.5,
+                    ..cfg.clone()
+                },
+                5,
+            ),
+        ];
+        for (cfg, batch_size) in invalid_configs {
+            assert!(matches!(
+                cfg.try_into_generator(batch_size),
+                Err(Error::Config(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_generator_min_duration() {
+        let cfg = GeneratorConfig {
+            duration: Duration::ZERO,
+            ..Default::default()
+        };
+        assert!(matches!(new_generator(cfg, 5), Err(Error::Config(_))));
+
+        // a duration below the minimum granularity is still valid, as it was in the spec
+        for duration in [Duration::from_millis(1), MIN_DURATION] {
+            let cfg = GeneratorConfig {
+                content: Bytes::from("test_data"),
+                rpu: 10,
+                duration,
+                ..Default::default()
+            };
+            let (mut generator, _, _) = new_generator(cfg, 5).unwrap();
+            assert_eq!(generator.read().await.unwrap().len(), 5);
+        }
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        let valid = [
+            ("250ms", Duration::from_millis(250)),
+            ("1s", Duration::from_secs(1)),
+            (" 1.5s ", Duration::from_millis(1500)),
+            ("1m30s", Duration::from_secs(90)),
+            ("2h", Duration::from_secs(7200)),
+            ("500us", Duration::from_micros(500)),
+            ("500µs", Duration::from_micros(500)),
+            ("100ns", Duration::from_nanos(100)),
+        ];
+        for (value, expected) in valid {
+            assert_eq!(parse_duration(value).unwrap(), expected, "{value}");
+        }
+
+        let invalid = [
+            "", "   ", "100", "ms", "1.2.3s", "10 ms", "10d", "-1s", "1s500",
+        ];
+        for value in invalid {
+            assert!(
+                matches!(parse_duration(value), Err(Error::Config(_))),
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("rpu", "100").unwrap(), 100);
+        assert_eq!(parse_count("rpu", " 7 ").unwrap(), 7);
+        for value in ["0", "", "-1", "1.5", "ten"] {
+            assert!(
+                matches!(parse_count("rpu", value), Err(Error::Config(_))),
+                "{value}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_new_generator_from_str() {
+        let cfg = GeneratorConfig {
+            content: Bytes::from("test_data"),
+            ..Default::default()
+        };
+        let (mut generator, _, _) =
+            new_generator_from_str(cfg.clone(), "10", "5", "250ms").unwrap();
+        assert_eq!(generator.read().await.unwrap().len(), 5);
+
+        let invalid = [
+            ("ten", "5", "1s"),
+            ("10", "0", "1s"),
+            ("10", "5", "1sec"),
+            // lower than the minimum duration
+            ("10", "5", "1ms"),
+        ];
+        for (rpu, batch_size, unit) in invalid {
+            assert!(matches!(
+                new_generator_from_str(cfg.clone(), rpu, batch_size, unit),
+                Err(Error::Config(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_lag_pending() {
+        // Create a new GeneratorLagReader
+        let mut lag_reader = GeneratorLagReader::new(None);
+
+        // Call the pending method and check the result
+        let pending_result = lag_reader.pending().await;
+
+        // Assert that the result is Ok(None)
+        assert!(pending_result.is_ok());
+        assert_eq!(pending_result.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_generator_lag_pending_fixed_backlog() {
+        let cfg = GeneratorConfig {
+            pending: Some(1000),
+            ..Default::default()
+        };
+        let (_, _, mut lag_reader) = new_generator(cfg, 10).unwrap();
+
+        let pending_result = lag_reader.pending().await;
+        assert_eq!(pending_result.unwrap(), Some(1000));
+    }
+
+    #[tokio::test]
+    async fn test_generator_ack() {
+        // Create a new GeneratorAck instance
+        let mut generator_ack = GeneratorAck::new();
+
+        // Create a vector of offsets to acknowledge
+        let offsets = vec![
+            Offset::String(StringOffset::new("offset1".to_string(), 0)),
+            Offset::String(StringOffset::new("offset2".to_string(), 0)),
+        ];
+
+        // Call the ack method and check the result
+        let ack_result = generator_ack.ack(offsets).await;
+
+        // Assert that the result is Ok(())
+        assert!(ack_result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_generator_ack_with_results() {
+        let mut generator_ack = GeneratorAck::new();
+
+        let offsets = vec![
+            Offset::String(StringOffset::new("offset1".to_string(), 0)),
+            Offset::String(StringOffset::new("offset2".to_string(), 0)),
+        ];
+
+        let results = generator_ack.ack_with_results(offsets).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.is_ok()));
+    }
+
+    #[tokio::test]
+    async fn test_generator_nack() {
+        let observer = Arc::new(RecordingAckObserver::default());
+        let mut generator_ack = GeneratorAck::with_observer(observer.clone());
+
+        let offsets = vec![Offset::String(StringOffset::new("offset1".to_string(), 0))];
+        // the generator cannot redeliver, nack is a no-op
+        generator_ack
+            .nack(offsets, Some(Duration::from_secs(1)))
+            .await
+            .unwrap();
+        assert!(observer.counts.lock().unwrap().is_empty());
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingAckObserver {
+        counts: std::sync::Mutex<Vec<usize>>,
+    }
+
+    impl source::AckObserver for RecordingAckObserver {
+        fn on_ack(&self, count: usize, _duration: Duration) {
+            self.counts.lock().unwrap().push(count);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_generator_ack_observer() {
+        let observer = Arc::new(RecordingAckObserver::default());
+        let mut generator_ack = GeneratorAck::with_observer(observer.clone());
+
+        let offsets = vec![
+            Offset::String(StringOffset::new("offset1".to_string(), 0)),
+            Offset::String(StringOffset::new("offset2".to_string(), 0)),
+            Offset::String(StringOffset::new("offset3".to_string(), 0)),
+        ];
+        generator_ack.ack(offsets.clone()).await.unwrap();
+        generator_ack
+            .ack_with_results(offsets[..1].to_vec())
+            .await
+            .unwrap();
+
+        assert_eq!(*observer.counts.lock().unwrap(), vec![3, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_generator_replay() {
+        let vertex = VertexContext {
+            name: "replay".to_string(),
+            replica: 1,
+        };
+        assert!(new_generator_replay(vec![], 0.0, 10, vertex.clone()).is_err());
+        assert!(new_generator_replay(vec![], f64::NAN, 10, vertex.clone()).is_err());
+        assert!(new_generator_replay(vec![], 1.0, 0, vertex.clone()).is_err());
+
+        let first = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let records = (0..3)
+            .map(|i| {
+                (
+                    first + chrono::Duration::seconds(i),
+                    Bytes::from(format!("payload-{i}")),
+                )
+            })
+            .collect();
+        // replayed 10 times faster, the records are 100ms apart
+        let (mut reader, _, _) = new_generator_replay(records, 10.0, 10, vertex.clone()).unwrap();
+
+        let start = tokio::time::Instant::now();
+        let mut replayed = vec![];
+        while replayed.len() < 3 {
+            let messages = reader.read().await.unwrap();
+            replayed.extend(messages);
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(190), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(1000), "{elapsed:?}");
+
+        let payloads: Vec<Bytes> = replayed.iter().map(|m| m.value.clone()).collect();
+        assert_eq!(
+            payloads,
+            vec![
+                Bytes::from("payload-0"),
+                Bytes::from("payload-1"),
+                Bytes::from("payload-2")
+            ]
+        );
+        for (i, message) in replayed.iter().enumerate() {
+            assert_eq!(
+                message.event_time,
+                first + chrono::Duration::seconds(i as i64)
+            );
+            assert_eq!(message.id.vertex_name, "replay");
+            assert_eq!(
+                message.offset,
+                Some(Offset::Int(IntOffset::new(i as u64, 1)))
+            );
+        }
+
+        // the replay is over
+        assert!(reader.read().await.unwrap().is_empty());
+
+        // the records which are due together are capped by the batch size
+        let records = (0..3)
+            .map(|i| (first, Bytes::from(format!("payload-{i}"))))
+            .collect();
+        let (mut reader, _, _) = new_generator_replay(records, 1.0, 2, vertex).unwrap();
+        assert_eq!(reader.read().await.unwrap().len(), 2);
+        assert_eq!(reader.read().await.unwrap().len(), 1);
+        assert!(reader.read().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_generator_replay_with_mock_clock() {
+        let vertex = VertexContext {
+            name: "replay".to_string(),
+            replica: 0,
+        };
+        let first = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        let records = (0..3)
+            .map(|i| {
+                (
+                    first + chrono::Duration::seconds(i),
+                    Bytes::from(format!("payload-{i}")),
+                )
+            })
+            .collect();
+        let clock = clock::MockClock::new(first);
+        let mut reader =
+            replay::ReplayRead::with_clock(records, 1.0, 10, vertex, Arc::new(clock.clone()));
+
+        // the first record is due right away
+        let messages = reader.read().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].value, Bytes::from("payload-0"));
+
+        for i in 1..3 {
+            // the next record is only due once the clock is advanced
+            assert!(reader.read().now_or_never().is_none());
+            clock.advance(Duration::from_secs(1));
+            let messages = reader.read().now_or_never().unwrap().unwrap();
+            assert_eq!(messages.len(), 1);
+            assert_eq!(messages[0].value, Bytes::from(format!("payload-{i}")));
+        }
+
+        // the replay is over
+        assert!(reader.read().await.unwrap().is_empty());
     }
 }
@@ -0,0 +1,192 @@
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::time::{Interval, MissedTickBehavior};
+
+/// Clock is the source of time for the generator. It is used for the event-times, the ramp schedule
+/// and the ticks of the time-periods, so that the time can be controlled in the tests.
+pub(crate) trait Clock: Send + Sync {
+    /// returns the current time.
+    fn now(&self) -> DateTime<Utc>;
+
+    /// creates a [Ticker] which ticks every `period`, the first tick completes immediately.
+    fn interval(
+        &self,
+        period: Duration,
+        missed_tick_behavior: MissedTickBehavior,
+    ) -> Box<dyn Ticker>;
+}
+
+/// Ticker yields ticks at a regular period, similar to [Interval].
+pub(crate) trait Ticker: Send + Sync {
+    /// polls for the next tick.
+    fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<()>;
+
+    /// resets the ticker to complete the next tick after the given period.
+    fn reset_after(&mut self, period: Duration);
+}
+
+/// SystemClock is the wall-clock backed by [Utc::now] and [tokio::time::interval].
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn interval(
+        &self,
+        period: Duration,
+        missed_tick_behavior: MissedTickBehavior,
+    ) -> Box<dyn Ticker> {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(missed_tick_behavior);
+        Box::new(interval)
+    }
+}
+
+impl Ticker for Interval {
+    fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        Interval::poll_tick(self, cx).map(|_| ())
+    }
+
+    fn reset_after(&mut self, period: Duration) {
+        Interval::reset_after(self, period)
+    }
+}
+
+#[cfg(test)]
+pub(crate) use mock::MockClock;
+
+#[cfg(test)]
+mod mock {
+    use std::sync::{Arc, Mutex};
+    use std::task::Waker;
+
+    use super::*;
+
+    /// MockClock is a clock whose time only moves when it is advanced manually. The tickers created
+    /// from it are woken up when the time is advanced.
+    #[derive(Clone)]
+    pub(crate) struct MockClock {
+        state: Arc<Mutex<MockState>>,
+    }
+
+    struct MockState {
+        now: DateTime<Utc>,
+        /// wakers of the tickers waiting for the time to advance.
+        wakers: Vec<Waker>,
+    }
+
+    impl MockClock {
+        pub(crate) fn new(now: DateTime<Utc>) -> Self {
+            Self {
+                state: Arc::new(Mutex::new(MockState {
+                    now,
+                    wakers: vec![],
+                })),
+            }
+        }
+
+        /// advances the time and wakes up the waiting tickers.
+        pub(crate) fn advance(&self, duration: Duration) {
+            let wakers = {
+                let mut state = self.state.lock().unwrap();
+                state.now += duration;
+                std::mem::take(&mut state.wakers)
+            };
+            wakers.into_iter().for_each(Waker::wake);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> DateTime<Utc> {
+            self.state.lock().unwrap().now
+        }
+
+        fn interval(
+            &self,
+            period: Duration,
+            missed_tick_behavior: MissedTickBehavior,
+        ) -> Box<dyn Ticker> {
+            assert!(!period.is_zero(), "`period` must be non-zero.");
+            Box::new(MockTicker {
+                clock: self.clone(),
+                period,
+                missed_tick_behavior,
+                next: self.now(),
+            })
+        }
+    }
+
+    struct MockTicker {
+        clock: MockClock,
+        period: Duration,
+        missed_tick_behavior: MissedTickBehavior,
+        /// time at which the next tick completes.
+        next: DateTime<Utc>,
+    }
+
+    impl Ticker for MockTicker {
+        fn poll_tick(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+            let mut state = self.clock.state.lock().unwrap();
+            if state.now < self.next {
+                state.wakers.push(cx.waker().clone());
+                return Poll::Pending;
+            }
+
+            self.next = match self.missed_tick_behavior {
+                MissedTickBehavior::Burst => self.next + self.period,
+                MissedTickBehavior::Delay => state.now + self.period,
+                MissedTickBehavior::Skip => {
+                    let mut next = self.next + self.period;
+                    while next <= state.now {
+                        next += self.period;
+                    }
+                    next
+                }
+            };
+            Poll::Ready(())
+        }
+
+        fn reset_after(&mut self, period: Duration) {
+            self.next = self.clock.now() + period;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::task::noop_waker;
+
+    use super::*;
+
+    #[test]
+    fn test_mock_clock_ticker() {
+        let start = Utc::now();
+        let clock = MockClock::new(start);
+        let period = Duration::from_secs(1);
+        let mut ticker = clock.interval(period, MissedTickBehavior::Skip);
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // first tick completes immediately
+        assert!(ticker.poll_tick(&mut cx).is_ready());
+        assert!(ticker.poll_tick(&mut cx).is_pending());
+
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(clock.now(), start + Duration::from_millis(500));
+        assert!(ticker.poll_tick(&mut cx).is_pending());
+
+        clock.advance(Duration::from_millis(500));
+        assert!(ticker.poll_tick(&mut cx).is_ready());
+
+        // missed ticks are skipped
+        clock.advance(period * 3);
+        assert!(ticker.poll_tick(&mut cx).is_ready());
+        assert!(ticker.poll_tick(&mut cx).is_pending());
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use tokio::time::MissedTickBehavior;
+
+use crate::config::VertexContext;
+use crate::message::{IntOffset, Message, MessageID, Offset};
+use crate::source;
+use crate::source::generator::clock::{Clock, SystemClock};
+
+/// ReplayRecord is a recorded message, the payload along with its event-time.
+pub(crate) type ReplayRecord = (DateTime<Utc>, Bytes);
+
+/// ReplayRead replays a recorded ordered sequence of messages, preserving the inter-arrival delays
+/// of the records (scaled down by the `speed_factor`). Each read returns the records which are due
+/// (at most `batch_size` of them), waiting for the next one if none is. The replay ends once all the
+/// records are emitted. All the time keeping is done using the injected [Clock].
+pub(crate) struct ReplayRead {
+    records: VecDeque<ReplayRecord>,
+    /// the inter-arrival delays are divided by it, e.g., 10.0 replays 10 times faster.
+    speed_factor: f64,
+    /// maximum number of records returned per read.
+    batch_size: usize,
+    /// name and replica of the vertex replaying the records.
+    vertex: VertexContext,
+    /// time at which the replay started and the event-time of the first record, set on the
+    /// first read.
+    start: Option<(DateTime<Utc>, DateTime<Utc>)>,
+    /// sequence to be used for the offset of the next message.
+    next_seq: u64,
+    /// source of the time for pacing the records.
+    clock: Arc<dyn Clock>,
+}
+
+impl ReplayRead {
+    pub(super) fn new(
+        records: Vec<ReplayRecord>,
+        speed_factor: f64,
+        batch_size: usize,
+        vertex: VertexContext,
+    ) -> Self {
+        Self::with_clock(
+            records,
+            speed_factor,
+            batch_size,
+            vertex,
+            Arc::new(SystemClock),
+        )
+    }
+
+    pub(super) fn with_clock(
+        records: Vec<ReplayRecord>,
+        speed_factor: f64,
+        batch_size: usize,
+        vertex: VertexContext,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            records: records.into(),
+            speed_factor,
+            batch_size,
+            vertex,
+            start: None,
+            next_seq: 0,
+            clock,
+        }
+    }
+
+    /// returns the time at which the record with the given event-time is due.
+    fn due_at(&self, event_time: DateTime<Utc>) -> DateTime<Utc> {
+        let Some((started_at, first_event_time)) = self.start else {
+            return self.clock.now();
+        };
+        // records older than the first one are due right away
+        let delay = (event_time - first_event_time)
+            .to_std()
+            .unwrap_or_default()
+            .div_f64(self.speed_factor);
+        started_at + delay
+    }
+
+    fn create_message(&mut self, (event_time, payload): ReplayRecord, index: i32) -> Message {
+        let offset = Offset::Int(IntOffset::new(self.next_seq, self.vertex.replica));
+        self.next_seq += 1;
+        Message {
+            keys: vec![],
+            value: payload,
+            offset: Some(offset.clone()),
+            event_time,
+            id: MessageID {
+                vertex_name: self.vertex.name.clone(),
+                offset: offset.to_string(),
+                index,
+            },
+            headers: Default::default(),
+        }
+    }
+}
+
+impl source::SourceReader for ReplayRead {
+    fn name(&self) -> &'static str {
+        "generator"
+    }
+
+    async fn read(&mut self) -> crate::Result<Vec<Message>> {
+        let Some((event_time, _)) = self.records.front() else {
+            return Ok(vec![]);
+        };
+        let event_time = *event_time;
+        if self.start.is_none() {
+            self.start = Some((self.clock.now(), event_time));
+        }
+        let delay = (self.due_at(event_time) - self.clock.now())
+            .to_std()
+            .unwrap_or_default();
+        if !delay.is_zero() {
+            let mut ticker = self.clock.interval(delay, MissedTickBehavior::Delay);
+            // the first tick completes right away, it should only complete after the delay
+            ticker.reset_after(delay);
+            std::future::poll_fn(|cx| ticker.poll_tick(cx)).await;
+        }
+
+        // the records which are due by now are returned together, the rest of them are returned by
+        // the next reads
+        let now = self.clock.now();
+        let mut messages = vec![];
+        while messages.len() < self.batch_size {
+            let Some((event_time, _)) = self.records.front() else {
+                break;
+            };
+            if self.due_at(*event_time) > now {
+                break;
+            }
+            let record = self.records.pop_front().expect("front record exists");
+            let message = self.create_message(record, messages.len() as i32);
+            messages.push(message);
+        }
+        Ok(messages)
+    }
+
+    fn partitions(&self) -> Vec<u16> {
+        vec![self.vertex.replica]
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.records.is_empty()
+    }
+}
@@ -1,3 +1,4 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use numaflow_pb::clients::source;
@@ -6,13 +7,14 @@ use numaflow_pb::clients::source::{
     read_request, AckRequest, AckResponse, ReadRequest, ReadResponse,
 };
 use tokio::sync::mpsc;
+use tokio::time::Instant;
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::transport::Channel;
 use tonic::{Request, Streaming};
 
 use crate::message::{Message, Offset};
 use crate::reader::LagReader;
-use crate::source::{SourceAcker, SourceReader};
+use crate::source::{AckObserver, NoopAckObserver, SourceAcker, SourceReader};
 use crate::{Error, Result};
 
 /// User-Defined Source to operative on custom sources.
@@ -29,6 +31,8 @@ pub(crate) struct UserDefinedSourceRead {
 pub(crate) struct UserDefinedSourceAck {
     ack_tx: mpsc::Sender<AckRequest>,
     ack_resp_stream: Streaming<AckResponse>,
+    /// notified with the round trip of every ack to the user-defined source.
+    observer: Arc<dyn AckObserver>,
 }
 
 /// Creates a new User-Defined Source and its corresponding Lag Reader.
@@ -147,9 +151,18 @@ impl UserDefinedSourceAck {
         Ok(Self {
             ack_tx,
             ack_resp_stream,
+            observer: Arc::new(NoopAckObserver),
         })
     }
 
+    /// sets the observer which is notified after every ack, with the time it took for the
+    /// user-defined source to respond.
+    #[cfg(test)]
+    pub(crate) fn with_observer(mut self, observer: Arc<dyn AckObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
     async fn create_acker(
         batch_size: usize,
         client: &mut SourceClient<Channel>,
@@ -185,9 +198,12 @@ impl UserDefinedSourceAck {
 
 impl SourceAcker for UserDefinedSourceAck {
     async fn ack(&mut self, offsets: Vec<Offset>) -> Result<()> {
+        let count = offsets.len();
         let ack_offsets: Result<Vec<source::Offset>> =
             offsets.into_iter().map(TryInto::try_into).collect();
 
+        let start = Instant::now();
+
         self.ack_tx
             .send(AckRequest {
                 request: Some(source::ack_request::Request {
@@ -203,6 +219,7 @@ impl SourceAcker for UserDefinedSourceAck {
             .message()
             .await?
             .ok_or(Error::Source("failed to receive ack response".to_string()))?;
+        self.observer.on_ack(count, start.elapsed());
 
         Ok(())
     }
@@ -301,6 +318,17 @@ mod tests {
         }
     }
 
+    #[derive(Debug, Default)]
+    struct RecordingAckObserver {
+        acks: std::sync::Mutex<Vec<(usize, Duration)>>,
+    }
+
+    impl AckObserver for RecordingAckObserver {
+        fn on_ack(&self, count: usize, duration: Duration) {
+            self.acks.lock().unwrap().push((count, duration));
+        }
+    }
+
     #[tokio::test]
     async fn source_operations() {
         // start the server
@@ -326,11 +354,13 @@ mod tests {
 
         let client = SourceClient::new(create_rpc_channel(sock_file).await.unwrap());
 
-        let (mut src_read, mut src_ack, mut lag_reader) =
+        let (mut src_read, src_ack, mut lag_reader) =
             new_source(client, 5, Duration::from_millis(1000))
                 .await
                 .map_err(|e| panic!("failed to create source reader: {:?}", e))
                 .unwrap();
+        let observer = Arc::new(RecordingAckObserver::default());
+        let mut src_ack = src_ack.with_observer(observer.clone());
 
         let messages = src_read.read().await.unwrap();
         assert_eq!(messages.len(), 5);
@@ -340,6 +370,12 @@ mod tests {
             .await;
         assert!(response.is_ok());
 
+        // the observer sees the round trip to the user-defined source
+        let acks = observer.acks.lock().unwrap().clone();
+        assert_eq!(acks.len(), 1);
+        assert_eq!(acks[0].0, 5);
+        assert!(acks[0].1 > Duration::ZERO);
+
         let pending = lag_reader.pending().await.unwrap();
         assert_eq!(pending, Some(0));
 